@@ -0,0 +1,135 @@
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use tokio::runtime::Runtime;
+
+use beammp_rust_server::config::Config;
+use beammp_rust_server::server::*;
+
+const CAR_JSON: &str = r#"{"jbm":"pickup","vcf":{"parts":{"pickup_body":"pickup_body","pickup_bed":"pickup_bed_8ft","pickup_engine":"pickup_engine_v8_4.5","pickup_transmission":"pickup_transmission_4M"},"paints":[{"baseColor":[0.8,0.1,0.1,1.2],"metallic":0.2,"roughness":0.5,"clearcoat":0.8,"clearcoatRoughness":0.1}],"vars":{"$fuel":0.7,"$tirepressure_F":32,"$tirepressure_R":34}}}"#;
+
+fn position_packet(client_id: u8, car_id: u8) -> RawPacket {
+    let data = format!(
+        "Zp:{}-{}:{{\"rvel\":[0.01,0.02,0.03],\"tim\":12.5,\"pos\":[120.5,-340.25,35.75],\"ping\":0.05,\"rot\":[0.0,0.0,0.7071,0.7071],\"vel\":[12.5,3.25,0.0]}}",
+        client_id, car_id,
+    );
+    // UDP packets start with the sender id (offset by 1) and a separator
+    let mut raw = vec![client_id + 1, b':'];
+    raw.extend_from_slice(data.as_bytes());
+    RawPacket { header: raw.len() as u32, data: raw }
+}
+
+/// Starts a server with `clients` connected players, each with one car and a UDP address.
+/// The returned sockets receive (and drop) whatever the server sends them over UDP.
+async fn setup_server(clients: usize) -> (Server, Vec<UdpSocket>) {
+    let resources = std::env::temp_dir().join(format!("beammp_rust_server_bench_{}", std::process::id()));
+    let config: Config = toml::from_str(&format!(
+r#"[General]
+Name = "Bench"
+Port = 0
+Description = ""
+MaxPlayers = 64
+Private = true
+Map = "/levels/gridmap_v2/info.json"
+ResourceFolder = "{}"
+Debug = false
+LogChat = false
+"#, resources.display().to_string().replace('\\', "/"))).unwrap();
+    // Nothing sends heartbeats here, so the health stays at its default
+    let (_, heartbeat) = tokio::sync::watch::channel(Default::default());
+    let server = Server::new(Arc::new(config), heartbeat).await.unwrap();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let listener_addr = listener.local_addr().unwrap();
+    let mut server = server;
+    let mut udp_sockets = Vec::new();
+    for i in 0..clients {
+        let _stream = tokio::net::TcpStream::connect(listener_addr).await.unwrap();
+        let (socket, _) = listener.accept().await.unwrap();
+        let mut client = Client::new(socket, None).await;
+        client.info = Some(UserData {
+            uid: i.to_string(),
+            created_at: String::new(),
+            guest: false,
+            roles: String::from("USER"),
+            username: format!("player{}", i),
+            identifiers: Vec::new(),
+            avatar: None,
+        });
+        client.register_car(Car::new(CAR_JSON.to_string()));
+
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        udp.set_nonblocking(true).unwrap();
+        client.udp_addr = Some(udp.local_addr().unwrap());
+        udp_sockets.push(udp);
+
+        server.clients.push(client);
+        std::mem::forget(_stream); // Keep the other end of the connection open
+    }
+    (server, udp_sockets)
+}
+
+/// Closes the server and waits a moment, so the player ids are free again for the next benchmark.
+fn close_server(rt: &Runtime, server: Server) {
+    rt.block_on(async {
+        server.close().await;
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    });
+}
+
+fn drain(sockets: &[UdpSocket]) {
+    let mut buf = [0u8; 4096];
+    for socket in sockets {
+        while socket.recv_from(&mut buf).is_ok() {}
+    }
+}
+
+fn bench_compression(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compression");
+    group.bench_function("compress_car_config", |b| {
+        b.iter(|| compress_packet_data(CAR_JSON.as_bytes()).unwrap())
+    });
+    let compressed = compress_packet_data(CAR_JSON.as_bytes()).unwrap();
+    group.bench_function("decompress_car_config", |b| {
+        b.iter(|| decompress_packet_data(&compressed).unwrap())
+    });
+    group.finish();
+}
+
+fn bench_parse_packet_udp(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let (mut server, sockets) = rt.block_on(setup_server(1));
+    let sender_id = server.clients[0].id;
+    let addr: SocketAddr = "127.0.0.1:1".parse().unwrap();
+
+    c.bench_function("parse_packet_udp/position", |b| {
+        b.iter(|| {
+            rt.block_on(server.process_udp(addr, position_packet(sender_id, 0))).unwrap();
+        })
+    });
+    drain(&sockets);
+    close_server(&rt, server);
+}
+
+fn bench_broadcast_fanout(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("broadcast_fanout");
+    for clients in [8, 16, 32] {
+        let (mut server, sockets) = rt.block_on(setup_server(clients));
+        let sender = server.clients.iter().min_by_key(|client| client.id).unwrap();
+        let (sender_id, sender_addr) = (sender.id, sender.udp_addr.unwrap());
+
+        group.bench_with_input(BenchmarkId::new("position", clients), &clients, |b, _| {
+            b.iter(|| {
+                rt.block_on(server.process_udp(sender_addr, position_packet(sender_id, 0))).unwrap();
+                drain(&sockets);
+            })
+        });
+        close_server(&rt, server);
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_compression, bench_parse_packet_udp, bench_broadcast_fanout);
+criterion_main!(benches);
@@ -0,0 +1,192 @@
+use std::collections::hash_map::RandomState;
+use std::f64::consts::TAU;
+use std::hash::{BuildHasher, Hasher};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::{TcpStream, UdpSocket};
+
+use crate::config::BotSettings;
+use crate::server::{decompress_packet_data, UserData};
+
+lazy_static! {
+    /// Random secret in the keys of the bots, so only bots started by this process can use them.
+    static ref BOT_SECRET: String = {
+        let state = RandomState::new();
+        let mut a = state.build_hasher();
+        a.write_u8(0);
+        let mut b = state.build_hasher();
+        b.write_u8(1);
+        format!("{:016x}{:016x}", a.finish(), b.finish())
+    };
+}
+
+/// How many bots this process started. Bot keys are only accepted for these.
+static BOT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// How often bots send their position.
+const BOT_UPDATE_RATE: u32 = 20;
+
+/// Returns the user data of a bot, if the key belongs to one of the bots of this process.
+/// Without bots running, no key is accepted.
+pub fn bot_user_data(key: &str) -> Option<UserData> {
+    let index = key.strip_prefix("bot:")?.strip_prefix(BOT_SECRET.as_str())?.strip_prefix(':')?;
+    let index: usize = index.parse().ok()?;
+    if index >= BOT_COUNT.load(Ordering::Relaxed) {
+        return None;
+    }
+    Some(UserData {
+        uid: format!("bot{}", index),
+        created_at: String::new(),
+        guest: false,
+        roles: String::from("USER"),
+        username: format!("Bot{}", index),
+        identifiers: Vec::new(),
+        avatar: None,
+    })
+}
+
+fn bot_key(index: usize) -> String {
+    format!("bot:{}:{}", BOT_SECRET.as_str(), index)
+}
+
+/// Connects `count` simulated clients to the server, which spawn a car and drive it in a circle.
+/// They go through the same protocol as real players, so they can be used for load testing.
+pub async fn run_bots(port: u16, count: usize, settings: BotSettings) {
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    info!("Starting {} bots", count);
+    BOT_COUNT.store(count, Ordering::Relaxed);
+    for index in 0..count {
+        let settings = settings.clone();
+        tokio::spawn(async move {
+            if let Err(e) = run_bot(addr, index, count, &settings).await {
+                error!("[BOT] Bot{} stopped: {:?}", index, e);
+            }
+        });
+        // Joining all at once would just make them wait on each other
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+}
+
+async fn run_bot(addr: SocketAddr, index: usize, count: usize, settings: &BotSettings) -> anyhow::Result<()> {
+    let mut tcp = connect(addr).await?;
+    tcp.set_nodelay(true)?;
+    tcp.write_all(b"C").await?;
+
+    send(&mut tcp, "VC2.0").await?;
+    expect(&mut tcp, "S").await?;
+    send(&mut tcp, &bot_key(index)).await?;
+    let id: u8 = expect(&mut tcp, "P").await?[1..].parse()?;
+    send(&mut tcp, "SR").await?;
+    recv(&mut tcp).await?; // Mod list
+    send(&mut tcp, "Done").await?;
+    expect(&mut tcp, "M").await?;
+    send(&mut tcp, "H").await?;
+    expect(&mut tcp, "Sn").await?;
+
+    send(&mut tcp, &format!("Os:0:{}", settings.car)).await?;
+    // Spawn packets look like `Os:<role>:<name>:<player id>-<car id>:<json>`
+    let car_id = loop {
+        let spawn = expect(&mut tcp, "Os:").await?;
+        if let Some((player_id, car_id)) = spawn.split(':').nth(3).and_then(|ids| ids.split_once('-')) {
+            if player_id == id.to_string() {
+                break car_id.to_string();
+            }
+        }
+    };
+    debug!("[BOT] Bot{} joined as #{} with car {}", index, id, car_id);
+
+    // The write half has to stay alive, dropping it closes the connection
+    let (read_half, _write_half) = tcp.into_split();
+    let reader = tokio::spawn(discard_tcp(read_half));
+
+    let udp = UdpSocket::bind("0.0.0.0:0").await?;
+    udp.connect(addr).await?;
+
+    // Spread the bots out around the circle
+    let start_angle = TAU * index as f64 / count.max(1) as f64;
+    let angular_speed = settings.speed / settings.radius.max(1.0);
+    let start = Instant::now();
+    let mut interval = tokio::time::interval(Duration::from_secs(1) / BOT_UPDATE_RATE);
+    let mut buf = vec![0u8; 4096];
+    while !reader.is_finished() {
+        interval.tick().await;
+        let tim = start.elapsed().as_secs_f64();
+        let angle = start_angle + angular_speed * tim;
+        let (sin, cos) = angle.sin_cos();
+        let pos = [
+            settings.center[0] + cos * settings.radius,
+            settings.center[1] + sin * settings.radius,
+            settings.center[2],
+        ];
+        let vel = [-sin * settings.speed, cos * settings.speed, 0.0];
+        // Cars face -Y, turn them towards where they're going
+        let yaw = angle + TAU / 2.0;
+        let rot = [0.0, 0.0, (yaw / 2.0).sin(), (yaw / 2.0).cos()];
+        let packet = format!(
+            "Zp:{}-{}:{{\"rvel\":[0,0,{}],\"tim\":{},\"pos\":[{},{},{}],\"ping\":0.0,\"rot\":[{},{},{},{}],\"vel\":[{},{},{}]}}",
+            id, car_id, angular_speed, tim, pos[0], pos[1], pos[2], rot[0], rot[1], rot[2], rot[3], vel[0], vel[1], vel[2],
+        );
+        let mut raw = vec![id + 1, b':'];
+        raw.extend_from_slice(packet.as_bytes());
+        udp.send(&raw).await?;
+
+        // Nothing to do with what the server sends, but it shouldn't pile up
+        while udp.try_recv(&mut buf).is_ok() {}
+    }
+    Ok(())
+}
+
+async fn connect(addr: SocketAddr) -> anyhow::Result<TcpStream> {
+    let start = Instant::now();
+    loop {
+        match TcpStream::connect(addr).await {
+            Ok(tcp) => return Ok(tcp),
+            Err(e) if start.elapsed() > Duration::from_secs(10) => return Err(e.into()),
+            Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+        }
+    }
+}
+
+async fn send(tcp: &mut TcpStream, data: &str) -> anyhow::Result<()> {
+    let mut raw = (data.len() as u32).to_le_bytes().to_vec();
+    raw.extend_from_slice(data.as_bytes());
+    tcp.write_all(&raw).await?;
+    Ok(())
+}
+
+async fn recv<R: AsyncReadExt + Unpin>(tcp: &mut R) -> anyhow::Result<String> {
+    let size = tcp.read_u32_le().await? as usize;
+    let mut data = vec![0u8; size];
+    tcp.read_exact(&mut data).await?;
+    let data = decompress_packet_data(&data)?.unwrap_or(data);
+    Ok(String::from_utf8_lossy(&data).to_string())
+}
+
+/// Receives packets until one starts with the prefix. Kicks end the bot.
+async fn expect(tcp: &mut TcpStream, prefix: &str) -> anyhow::Result<String> {
+    loop {
+        let packet = tokio::time::timeout(Duration::from_secs(10), recv(tcp)).await??;
+        if packet.starts_with(prefix) {
+            return Ok(packet);
+        }
+        if let Some(reason) = packet.strip_prefix('K') {
+            anyhow::bail!("Kicked: {}", reason);
+        }
+    }
+}
+
+async fn discard_tcp(mut read_half: OwnedReadHalf) {
+    loop {
+        match recv(&mut read_half).await {
+            Ok(packet) => if let Some(reason) = packet.strip_prefix('K') {
+                info!("[BOT] Kicked: {}", reason);
+                return;
+            },
+            Err(_) => return,
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::net::{SocketAddr, TcpListener, UdpSocket};
+use std::path::Path;
+
+use crate::config::{AuthProviderKind, Config};
+
+/// Results of `--check`, printed as they come in.
+#[derive(Default)]
+struct Report {
+    problems: usize,
+    warnings: usize,
+}
+
+impl Report {
+    fn ok(&mut self, message: &str) {
+        println!("  OK    {}", message);
+    }
+
+    fn warn(&mut self, message: &str) {
+        self.warnings += 1;
+        println!("  WARN  {}", message);
+    }
+
+    fn fail(&mut self, message: &str) {
+        self.problems += 1;
+        println!("  FAIL  {}", message);
+    }
+}
+
+/// Checks everything the server needs to start, without starting it, and prints a summary.
+/// Meant for CI of event configs. Returns false if there are problems that would stop the
+/// server from starting or working as configured.
+pub async fn run_checks(config_path: &str) -> bool {
+    let mut report = Report::default();
+    println!("Checking {}", config_path);
+
+    let config = match Config::load(config_path) {
+        Ok(config) => {
+            report.ok("Config file is valid");
+            config
+        },
+        Err(e) => {
+            report.fail(&e.to_string());
+            return print_summary(&report);
+        },
+    };
+
+    check_auth_key(&config, &mut report);
+    check_ports(&config, &mut report);
+    check_mods(&config, &mut report);
+    check_plugins(&config, &mut report);
+    check_auth_provider(&config, &mut report);
+    check_geoip(&config, &mut report);
+    check_backend(&config, &mut report).await;
+
+    print_summary(&report)
+}
+
+fn print_summary(report: &Report) -> bool {
+    println!("{} problem(s), {} warning(s)", report.problems, report.warnings);
+    report.problems == 0
+}
+
+fn check_auth_key(config: &Config, report: &mut Report) {
+    match (config.general.is_auth_key_valid(), config.general.private) {
+        (true, _) => report.ok("AuthKey has a valid format"),
+        (false, true) => report.warn("AuthKey has an invalid format, which is fine for a private server"),
+        (false, false) => report.fail("AuthKey has an invalid format, the server won't show up on the server list"),
+    }
+}
+
+fn check_ports(config: &Config, report: &mut Report) {
+    let port = config.general.port.unwrap_or(48900);
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    match (TcpListener::bind(addr), UdpSocket::bind(addr)) {
+        (Ok(_), Ok(_)) => report.ok(&format!("Port {} is free (TCP and UDP)", port)),
+        (Err(e), _) => report.fail(&format!("Can't use TCP port {}: {}", port, e)),
+        (_, Err(e)) => report.fail(&format!("Can't use UDP port {}: {}", port, e)),
+    }
+}
+
+fn check_mods(config: &Config, report: &mut Report) {
+    let client_resources = Path::new(&config.general.resource_folder).join("Client");
+    if !client_resources.is_dir() {
+        report.warn(&format!("{} doesn't exist yet, the server will start without mods", client_resources.display()));
+    } else {
+        match crate::mods::scan_mods(&client_resources, &config.mods_settings) {
+            Ok(_) => {
+                let mods = crate::mods::get_mods();
+                let size: usize = mods.iter().map(|m| m.size).sum();
+                report.ok(&format!("{} mod(s), {:.1} MiB in total", mods.len(), size as f64 / 1024.0 / 1024.0));
+            },
+            Err(e) => report.fail(&format!("Failed to read the mods in {}: {}", client_resources.display(), e)),
+        }
+    }
+
+    for remote in &config.mods_settings.remote {
+        if !client_resources.join(&remote.path).is_file() {
+            report.warn(&format!("Remote mod {} hasn't been downloaded yet, it will be on startup", remote.path));
+        }
+        if remote.sha256.is_none() {
+            report.warn(&format!("Remote mod {} has no Sha256, it's downloaded again on every sync", remote.path));
+        }
+    }
+}
+
+/// Compiles every plugin, so syntax errors show up before the event.
+fn check_plugins(config: &Config, report: &mut Report) {
+    let server_resources = Path::new(&config.general.resource_folder).join("Server");
+    let Ok(entries) = std::fs::read_dir(&server_resources) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let main = entry.path().join("main.lua");
+        let Ok(src) = std::fs::read_to_string(&main) else {
+            continue;
+        };
+        let lua = mlua::Lua::new();
+        let compiled = lua.load(src.as_str()).set_name(main.to_string_lossy()).into_function().map(|_| ());
+        match compiled {
+            Ok(()) => report.ok(&format!("Plugin {} compiles", main.display())),
+            Err(e) => report.fail(&format!("Plugin {} doesn't compile: {}", main.display(), e)),
+        }
+    }
+}
+
+fn check_auth_provider(config: &Config, report: &mut Report) {
+    if let Err(e) = crate::server::create_auth_provider(config) {
+        report.fail(&format!("Auth provider can't be created: {}", e));
+        return;
+    }
+    if config.auth.provider == AuthProviderKind::KeyFile {
+        let path = config.auth.key_file.as_deref().unwrap_or_default();
+        let keys = std::fs::read_to_string(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| Ok(serde_json::from_str::<HashMap<String, serde_json::Value>>(&data)?));
+        match keys {
+            Ok(keys) => report.ok(&format!("Key file {} has {} key(s)", path, keys.len())),
+            Err(e) => report.fail(&format!("Key file {} can't be read: {}", path, e)),
+        }
+    }
+}
+
+fn check_geoip(config: &Config, report: &mut Report) {
+    let Some(path) = &config.geoip.database else {
+        return;
+    };
+    match crate::geoip::GeoIpDatabase::load(Path::new(path)) {
+        Ok(db) if db.is_empty() => report.warn(&format!("GeoIP database {} has no valid IP ranges", path)),
+        Ok(db) => report.ok(&format!("GeoIP database {} has {} IP range(s)", path, db.len())),
+        Err(e) => report.fail(&format!("GeoIP database {} can't be read: {}", path, e)),
+    }
+}
+
+/// Checks whether the BeamMP backend can be reached, as the heartbeat and authentication need it.
+async fn check_backend(config: &Config, report: &mut Report) {
+    let needs_backend = !config.general.private || config.auth.provider == AuthProviderKind::BeamMP;
+    if !needs_backend {
+        return;
+    }
+    let client = match crate::server::http_client_builder(&config.http).and_then(|builder| Ok(builder.build()?)) {
+        Ok(client) => client,
+        Err(e) => {
+            report.fail(&format!("Failed to create the HTTP client: {}", e));
+            return;
+        },
+    };
+    match client.get(format!("https://{}", crate::server::BACKEND_URL)).send().await {
+        Ok(_) => report.ok("BeamMP backend is reachable"),
+        Err(e) => report.fail(&format!("BeamMP backend can't be reached: {}", e)),
+    }
+}
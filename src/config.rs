@@ -1,18 +1,192 @@
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use serde::Deserialize;
 use uuid::Uuid;
 use crate::fs_util;
 
-#[derive(Deserialize)]
-pub struct Config {
-    #[serde(skip)] // Skipping uses Default::default, which makes a new vector for us :)
-    pub mods: Vec<(String, usize)>,
+/// The config file, in the working directory.
+pub const CONFIG_PATH: &str = "ServerConfig.toml";
 
+#[derive(Deserialize, Clone)]
+pub struct Config {
     #[serde(rename = "General")]
     pub general: GeneralSettings,
+
+    #[serde(rename = "Mods", default)]
+    pub mods_settings: ModSettings,
+
+    #[serde(rename = "Damage", default)]
+    pub damage: DamageSettings,
+
+    #[serde(rename = "Chat", default)]
+    pub chat: ChatSettings,
+
+    #[serde(rename = "Auth", default)]
+    pub auth: AuthSettings,
+
+    #[serde(rename = "Http", default)]
+    pub http: HttpSettings,
+
+    #[serde(rename = "Capture", default)]
+    pub capture: CaptureSettings,
+
+    #[serde(rename = "Bots", default)]
+    pub bots: BotSettings,
+
+    #[serde(rename = "Output", default)]
+    pub output: OutputSettings,
+
+    #[serde(rename = "Radar", default)]
+    pub radar: RadarSettings,
+
+    #[serde(rename = "ConnectionQuality", default)]
+    pub connection_quality: ConnectionQualitySettings,
+
+    #[serde(rename = "Liveries", default)]
+    pub liveries: LiverySettings,
+
+    #[serde(rename = "Edits", default)]
+    pub edits: EditSettings,
+
+    #[serde(rename = "Traffic", default)]
+    pub traffic: TrafficSettings,
+
+    #[serde(rename = "Props", default)]
+    pub props: PropSettings,
+
+    #[serde(rename = "Garage", default)]
+    pub garage: GarageSettings,
+
+    #[serde(rename = "Bans", default)]
+    pub bans: BanSettings,
+
+    #[serde(rename = "World", default)]
+    pub world: WorldSettings,
+
+    #[serde(rename = "PlayerStore", default)]
+    pub player_store: PlayerStoreSettings,
+
+    #[serde(rename = "Api", default)]
+    pub api: ApiSettings,
+
+    #[serde(rename = "Teleport", default)]
+    pub teleport: TeleportSettings,
+
+    #[serde(rename = "GeoIp", default)]
+    pub geoip: GeoIpSettings,
+
+    /// Roles, keyed by their name. Sorted so role resolution is deterministic.
+    #[serde(rename = "Roles", default)]
+    pub roles: BTreeMap<String, RoleSettings>,
+
+    /// Display names and race numbers set by the organizers, keyed by BeamMP ID.
+    #[serde(rename = "Drivers", default)]
+    pub drivers: HashMap<String, DriverSettings>,
+
+    /// Classes of cars with a limit on how many can be spawned, keyed by their name. Sorted so
+    /// models listed in more than one class always end up in the same one.
+    #[serde(rename = "CarClasses", default)]
+    pub car_classes: BTreeMap<String, CarClassSettings>,
+
+    /// Settings that change with the amount of players on the server.
+    #[serde(rename = "PlayerCountRules", default)]
+    pub player_count_rules: Vec<PlayerCountRule>,
+
+    /// Commands and webhooks that run when something happens on the server.
+    #[serde(rename = "Hooks", default)]
+    pub hooks: Vec<HookSettings>,
+}
+
+/// Errors while loading the config file.
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("Failed to read config file {path}: {source}")]
+    Read {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Failed to parse config file {path}: {source}")]
+    Parse {
+        path: String,
+        #[source]
+        source: toml::de::Error,
+    },
+    #[error("Invalid config file {path}: {reason}")]
+    Invalid {
+        path: String,
+        reason: String,
+    },
+}
+
+impl Config {
+    /// Reads and parses the config file at the given path.
+    pub fn load(path: &str) -> Result<Self, ConfigError> {
+        let src = std::fs::read_to_string(path).map_err(|source| ConfigError::Read { path: path.to_string(), source })?;
+        let config: Config = toml::from_str(&src).map_err(|source| ConfigError::Parse { path: path.to_string(), source })?;
+        config.validate().map_err(|reason| ConfigError::Invalid { path: path.to_string(), reason })?;
+        Ok(config)
+    }
+
+    /// Checks the values that parse fine but can't work.
+    fn validate(&self) -> Result<(), String> {
+        let quality = &self.connection_quality;
+        for (name, limit) in [
+            ("MaxPingMs", quality.max_ping_ms),
+            ("MaxJitterMs", quality.max_jitter_ms),
+            ("MaxLossPercent", quality.max_loss_percent),
+        ] {
+            if limit.is_nan() || limit <= 0.0 {
+                return Err(format!("ConnectionQuality.{} must be above 0", name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the settings of `new` that can change while the server is running. Everything
+    /// else (ports, folders, auth, ...) is only used at startup, so it's kept from `self`.
+    pub fn reloaded(&self, new: Config) -> Config {
+        let mut config = self.clone();
+        config.general.max_cars = new.general.max_cars;
+        config.general.max_players = new.general.max_players;
+        config.general.max_resets_per_minute = new.general.max_resets_per_minute;
+        config.general.connection_timeout = new.general.connection_timeout;
+        config.general.max_outbound_kib_per_second = new.general.max_outbound_kib_per_second;
+        config.general.admins = new.general.admins;
+        config.damage = new.damage;
+        config.chat = new.chat;
+        config.radar = new.radar;
+        config.connection_quality = new.connection_quality;
+        config.liveries = new.liveries;
+        config.edits = new.edits;
+        config.traffic = new.traffic;
+        config.props = new.props;
+        config.teleport = new.teleport;
+        config.roles = new.roles;
+        config.drivers = new.drivers;
+        config.car_classes = new.car_classes;
+        config.player_count_rules = new.player_count_rules;
+        config
+    }
+
+    /// Finds the configured role for a player. Roles that list the player's BeamMP ID
+    /// take priority over roles matched through the roles sent by the BeamMP backend.
+    pub fn resolve_role(&self, beammp_id: &str, backend_roles: &str) -> Option<(&String, &RoleSettings)> {
+        self.roles.iter()
+            .find(|(_, role)| role.members.iter().any(|id| id == beammp_id))
+            .or_else(|| self.roles.iter().find(|(_, role)| {
+                backend_roles.split(',').any(|backend_role| role.backend_roles.iter().any(|r| r == backend_role.trim()))
+            }))
+    }
+
+    /// Finds the class of a car model (the `jbm` of a car config).
+    pub fn car_class(&self, model: &str) -> Option<(&String, &CarClassSettings)> {
+        self.car_classes.iter().find(|(_, class)| class.models.iter().any(|m| m == model))
+    }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct GeneralSettings {
     #[serde(rename = "Port")]
     pub port: Option<u16>,
@@ -32,6 +206,10 @@ pub struct GeneralSettings {
     #[serde(rename = "MaxPlayers")]
     pub max_players: usize,
 
+    /// Maximum amount of vehicle resets a client may do per minute. No limit if not set.
+    #[serde(rename = "MaxResetsPerMinute")]
+    pub max_resets_per_minute: Option<u32>,
+
     #[serde(rename = "Private")]
     pub private: bool,
 
@@ -44,11 +222,55 @@ pub struct GeneralSettings {
     #[serde(rename = "Debug")]
     pub debug: bool,
 
-    // Options below are not yet supported
+    /// Size of the UDP receive buffer in KiB. Defaults to 32 KiB per player (at least 256 KiB,
+    /// at most 8 MiB), so bursts of position updates don't get dropped.
+    #[serde(rename = "UdpBufferKiB")]
+    pub udp_buffer_kib: Option<usize>,
+
+    /// Most KiB per second sent to each player over TCP, not counting mod downloads. No limit if not set.
+    #[serde(rename = "MaxOutboundKiBPerSecond")]
+    pub max_outbound_kib_per_second: Option<usize>,
+
+    /// How many times per second the server processes its state (joins, chat, spawns, plugin events).
+    /// Packets are still handled as soon as they arrive.
+    #[serde(rename = "TickRate", default = "default_tick_rate")]
+    pub tick_rate: u32,
+
+    /// Seconds between TCP keepalive probes, so connections that died without closing
+    /// (like a dropped Wi-Fi connection) get noticed by the OS.
+    #[serde(rename = "KeepaliveInterval", default = "default_keepalive_interval")]
+    pub keepalive_interval: u64,
+
+    /// Players that haven't sent anything for this many seconds get disconnected. 0 disables it.
+    #[serde(rename = "ConnectionTimeout", default = "default_connection_timeout")]
+    pub connection_timeout: u64,
+
+    /// Seed for everything random the server (and its plugins) does. A new one is picked every
+    /// start if not set. It's saved in `event.json`, so an event can be replayed with the same seed.
+    #[serde(rename = "Seed")]
+    pub seed: Option<u64>,
+
+    /// BeamMP IDs of players that are allowed to run admin commands from chat.
+    #[serde(rename = "Admins", default)]
+    pub admins: Vec<String>,
+
+    /// Logs chat messages to the console.
     #[serde(rename = "LogChat")]
     pub log_chat: bool,
 }
 
+fn default_tick_rate() -> u32 {
+    20
+}
+
+fn default_keepalive_interval() -> u64 {
+    5
+}
+
+fn default_connection_timeout() -> u64 {
+    30
+}
+
 impl GeneralSettings {
     pub fn is_auth_key_valid(&self) -> bool {
         if let Some(auth_key) = &self.auth_key {
@@ -58,6 +280,22 @@ impl GeneralSettings {
         }
     }
 
+    /// Returns the UDP receive buffer size in bytes.
+    pub fn udp_buffer_size(&self) -> usize {
+        let kib = self.udp_buffer_kib.unwrap_or_else(|| (self.max_players.saturating_mul(32)).clamp(256, 8 * 1024));
+        kib.saturating_mul(1024)
+    }
+
+    /// Returns the most bytes per second sent to each player, if there's a limit.
+    pub fn outbound_limit(&self) -> Option<usize> {
+        self.max_outbound_kib_per_second.map(|kib| kib.saturating_mul(1024))
+    }
+
+    /// Returns how long a player may stay silent before they get disconnected, if there's a limit.
+    pub fn connection_timeout(&self) -> Option<Duration> {
+        (self.connection_timeout > 0).then(|| Duration::from_secs(self.connection_timeout))
+    }
+
     /// Returns the client resource path, and ensures it exists.
     /// Default is Resources/Client.
     pub fn get_client_resource_folder(&self) -> anyhow::Result<String> {
@@ -74,3 +312,784 @@ impl GeneralSettings {
         Ok(fs_util::path_to_string(res_server_path))
     }
 }
+
+#[derive(Deserialize, Clone)]
+pub struct DamageSettings {
+    /// Accelerations (in m/s^2) below this are not counted as an impact.
+    #[serde(rename = "MinImpactAcceleration", default = "default_min_impact_acceleration")]
+    pub min_impact_acceleration: f64,
+
+    /// Client events to trigger once a car's cumulative impact energy passes the given value.
+    /// Impact energy is measured per unit of mass, so it doesn't depend on the vehicle.
+    #[serde(rename = "Events", default)]
+    pub events: HashMap<String, f64>,
+}
+
+impl Default for DamageSettings {
+    fn default() -> Self {
+        Self {
+            min_impact_acceleration: default_min_impact_acceleration(),
+            events: HashMap::new(),
+        }
+    }
+}
+
+fn default_min_impact_acceleration() -> f64 {
+    150.0
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ChatSettings {
+    /// Messages longer than this (in characters) get cut off.
+    #[serde(rename = "MaxMessageLength", default = "default_max_message_length")]
+    pub max_message_length: usize,
+
+    /// Minimum time between 2 messages from the same player, in milliseconds.
+    #[serde(rename = "CooldownMs", default = "default_chat_cooldown_ms")]
+    pub cooldown_ms: u64,
+
+    /// Amount of messages a player may send within 10 seconds before they get muted.
+    #[serde(rename = "SpamThreshold", default = "default_spam_threshold")]
+    pub spam_threshold: usize,
+
+    /// How long a player stays muted after spamming, in seconds.
+    #[serde(rename = "MuteSeconds", default = "default_mute_seconds")]
+    pub mute_seconds: u64,
+}
+
+impl Default for ChatSettings {
+    fn default() -> Self {
+        Self {
+            max_message_length: default_max_message_length(),
+            cooldown_ms: default_chat_cooldown_ms(),
+            spam_threshold: default_spam_threshold(),
+            mute_seconds: default_mute_seconds(),
+        }
+    }
+}
+
+fn default_max_message_length() -> usize {
+    200
+}
+
+fn default_chat_cooldown_ms() -> u64 {
+    500
+}
+
+fn default_spam_threshold() -> usize {
+    8
+}
+
+fn default_mute_seconds() -> u64 {
+    60
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct RoleSettings {
+    /// Shown in front of the player's name in chat, e.g. `[Admin]`.
+    #[serde(rename = "Tag")]
+    pub tag: Option<String>,
+
+    /// Role string sent to clients in spawn packets. The game uses this to color name tags
+    /// (e.g. `STAFF`, `MDEV`, `EA`, `YT`). Defaults to the role sent by the BeamMP backend.
+    #[serde(rename = "ClientRole")]
+    pub client_role: Option<String>,
+
+    /// Whether players with this role can use admin commands.
+    #[serde(rename = "Admin", default)]
+    pub admin: bool,
+
+    /// BeamMP IDs of the players with this role.
+    #[serde(rename = "Members", default)]
+    pub members: Vec<String>,
+
+    /// Players get this role automatically if the BeamMP backend gives them one of these roles.
+    #[serde(rename = "BackendRoles", default)]
+    pub backend_roles: Vec<String>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct CarClassSettings {
+    /// Car models (like `us_semi`) in this class.
+    #[serde(rename = "Models")]
+    pub models: Vec<String>,
+
+    /// Maximum amount of cars of this class on the whole server. No limit if not set.
+    #[serde(rename = "Max")]
+    pub max: Option<usize>,
+
+    /// Maximum amount of cars of this class per player. No limit if not set.
+    #[serde(rename = "MaxPerPlayer")]
+    pub max_per_player: Option<usize>,
+}
+
+/// Events that hooks can run on.
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum HookEvent {
+    ServerStarted,
+    PlayerJoined,
+    PlayerLeft,
+    PlayerKicked,
+    PlayerBanned,
+}
+
+/// Runs a command and/or calls a webhook when an event happens. `{name}` style placeholders in
+/// the arguments are replaced with the details of the event.
+#[derive(Deserialize, Clone, Debug)]
+pub struct HookSettings {
+    #[serde(rename = "Event")]
+    pub event: HookEvent,
+
+    /// Program to run. Not ran through a shell, so player names can't inject commands.
+    #[serde(rename = "Command")]
+    pub command: Option<String>,
+
+    #[serde(rename = "Args", default)]
+    pub args: Vec<String>,
+
+    /// Gets the event as JSON in a POST request.
+    #[serde(rename = "Url")]
+    pub url: Option<String>,
+}
+
+/// Overrides settings while at least `min_players` players are on the server. Settings that
+/// aren't set keep their normal value.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct PlayerCountRule {
+    #[serde(rename = "MinPlayers")]
+    pub min_players: usize,
+
+    #[serde(rename = "MaxCars")]
+    pub max_cars: Option<u8>,
+
+    #[serde(rename = "MaxResetsPerMinute")]
+    pub max_resets_per_minute: Option<u32>,
+
+    #[serde(rename = "ChatCooldownMs")]
+    pub chat_cooldown_ms: Option<u64>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct DriverSettings {
+    /// Shown instead of the BeamMP account name in chat and the player list.
+    #[serde(rename = "DisplayName")]
+    pub display_name: Option<String>,
+
+    /// Race number, shown in front of the name (e.g. `#7 Luuk`).
+    #[serde(rename = "Number")]
+    pub number: Option<u32>,
+
+    /// Livery of the cars of this driver, used if `Liveries.Enforce` is on.
+    #[serde(rename = "Livery")]
+    pub livery: Option<AssignedLivery>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct AssignedLivery {
+    /// The skin, which is the part in the `paint_design` slot.
+    #[serde(rename = "PaintDesign")]
+    pub paint_design: Option<String>,
+
+    /// Paints in the format of the game's car configs, e.g. `{ baseColor = [0.8, 0.1, 0.1, 1.2] }`.
+    #[serde(rename = "Paints")]
+    pub paints: Option<Vec<serde_json::Value>>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct LiverySettings {
+    /// Cars of drivers with an assigned livery always get that livery.
+    #[serde(rename = "Enforce", default)]
+    pub enforce: bool,
+
+    /// Edits that change the livery of a car keep the old livery. Can be toggled at runtime
+    /// (e.g. for a race) with `set lock_liveries true`.
+    #[serde(rename = "Locked", default)]
+    pub locked: bool,
+}
+
+/// Which edits of a car are relayed to the other players.
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+pub enum EditMode {
+    #[default]
+    Any,
+    /// Only cosmetic changes like the livery or the plate, no part or tuning changes
+    Cosmetic,
+    /// No edits at all
+    None,
+}
+
+impl std::str::FromStr for EditMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "any" => Ok(Self::Any),
+            "cosmetic" => Ok(Self::Cosmetic),
+            "none" => Ok(Self::None),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct EditSettings {
+    /// Can be changed at runtime (e.g. for the race) with `set edits <any|cosmetic|none>`.
+    #[serde(rename = "Mode", default)]
+    pub mode: EditMode,
+}
+
+/// How AI traffic cars (`"isTraffic": true` in the car config) are handled.
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+pub enum TrafficPolicy {
+    /// Traffic cars are treated like any other car
+    #[default]
+    Normal,
+    /// Traffic cars don't count towards MaxCars, and have their own limit
+    Separate,
+    /// Traffic cars can't be spawned
+    Block,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct TrafficSettings {
+    #[serde(rename = "Policy", default)]
+    pub policy: TrafficPolicy,
+
+    /// Maximum amount of traffic cars per player with the Separate policy. Always limited, as
+    /// clients decide themselves which of their cars are traffic.
+    #[serde(rename = "MaxPerPlayer", default = "default_traffic_max_per_player")]
+    pub max_per_player: usize,
+}
+
+fn default_traffic_max_per_player() -> usize {
+    5
+}
+
+impl Default for TrafficSettings {
+    fn default() -> Self {
+        Self {
+            policy: TrafficPolicy::default(),
+            max_per_player: default_traffic_max_per_player(),
+        }
+    }
+}
+
+/// Trailers and props, like cones and barriers. They are relayed like cars, but don't show up on
+/// the radar. With a MaxPerPlayer, they don't count towards MaxCars either.
+#[derive(Deserialize, Clone, Debug)]
+pub struct PropSettings {
+    #[serde(rename = "Allow", default = "default_true")]
+    pub allow: bool,
+
+    /// Models that are trailers or props.
+    #[serde(rename = "Models", default = "default_prop_models")]
+    pub models: Vec<String>,
+
+    /// Maximum amount of trailers and props per player. If not set, they count towards MaxCars.
+    #[serde(rename = "MaxPerPlayer")]
+    pub max_per_player: Option<usize>,
+}
+
+fn default_prop_models() -> Vec<String> {
+    [
+        // Trailers
+        "boxutility", "boxutility_large", "caravan", "dolly", "dryvan", "flatbed", "tanker", "tsfb",
+        // Props
+        "barrels", "barrier", "barrier_plastic", "blockwall", "bollard", "cannon", "cones", "haybale",
+        "inflated_mat", "kickplate", "metal_box", "metal_ramp", "piano", "rocks", "rollover",
+        "roadsigns", "sawhorse", "shipping_container", "streetlight", "tirestacks", "tirewall",
+        "trafficbarrel", "tube", "tv", "wall", "weightpad", "woodcrate", "woodplanks",
+    ].into_iter().map(String::from).collect()
+}
+
+impl Default for PropSettings {
+    fn default() -> Self {
+        Self {
+            allow: true,
+            models: default_prop_models(),
+            max_per_player: None,
+        }
+    }
+}
+
+impl PropSettings {
+    pub fn is_prop(&self, model: &str) -> bool {
+        self.models.iter().any(|m| m == model)
+    }
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+pub enum AuthProviderKind {
+    /// The official BeamMP backend
+    #[default]
+    BeamMP,
+    /// A static JSON file mapping player keys to players
+    KeyFile,
+    /// An external HTTP endpoint
+    HttpHook,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct AuthSettings {
+    #[serde(rename = "Provider", default)]
+    pub provider: AuthProviderKind,
+
+    /// Path to the key file, used by the KeyFile provider.
+    #[serde(rename = "KeyFile")]
+    pub key_file: Option<String>,
+
+    /// URL to post authentication requests to, used by the HttpHook provider.
+    #[serde(rename = "HookUrl")]
+    pub hook_url: Option<String>,
+
+    /// How long a successful authentication is remembered, in seconds. Players reconnecting
+    /// within this time don't need the BeamMP backend to be reachable. Set to 0 to disable.
+    #[serde(rename = "CacheSeconds", default = "default_auth_cache_seconds")]
+    pub cache_seconds: u64,
+}
+
+impl Default for AuthSettings {
+    fn default() -> Self {
+        Self {
+            provider: AuthProviderKind::default(),
+            key_file: None,
+            hook_url: None,
+            cache_seconds: default_auth_cache_seconds(),
+        }
+    }
+}
+
+fn default_auth_cache_seconds() -> u64 {
+    600
+}
+
+/// Protocol capture, for debugging client compatibility issues.
+#[derive(Deserialize, Clone, Debug)]
+pub struct CaptureSettings {
+    /// Dumps all packets sent and received to the capture file (one JSON object per line).
+    /// Relative paths are in the event directory.
+    #[serde(rename = "Enabled", default)]
+    pub enabled: bool,
+
+    #[serde(rename = "Path", default = "default_capture_path")]
+    pub path: String,
+
+    /// The capture stops after this many seconds. 0 captures until the server stops.
+    #[serde(rename = "DurationSeconds", default = "default_capture_duration_seconds")]
+    pub duration_seconds: u64,
+}
+
+impl Default for CaptureSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: default_capture_path(),
+            duration_seconds: default_capture_duration_seconds(),
+        }
+    }
+}
+
+fn default_capture_path() -> String {
+    String::from("capture.ndjson")
+}
+
+fn default_capture_duration_seconds() -> u64 {
+    300
+}
+
+/// Where the server writes the files belonging to an event (captures, results, reports).
+#[derive(Deserialize, Clone, Debug)]
+pub struct OutputSettings {
+    /// Template for the directory of the current event, resolved at startup. Supports `{date}`,
+    /// `{time}`, `{map}` and `{name}` (the server name).
+    #[serde(rename = "EventDirectory", default = "default_event_directory")]
+    pub event_directory: String,
+}
+
+impl Default for OutputSettings {
+    fn default() -> Self {
+        Self {
+            event_directory: default_event_directory(),
+        }
+    }
+}
+
+fn default_event_directory() -> String {
+    String::from("events/{date}_{map}")
+}
+
+/// REST API for external dashboards and tournament tools.
+#[derive(Deserialize, Clone, Debug)]
+pub struct ApiSettings {
+    #[serde(rename = "Enabled", default)]
+    pub enabled: bool,
+
+    #[serde(rename = "Port", default = "default_api_port")]
+    pub port: u16,
+
+    /// Required as `Authorization: Bearer <token>`. Without one, the API only listens on localhost.
+    #[serde(rename = "Token")]
+    pub token: Option<String>,
+}
+
+fn default_api_port() -> u16 {
+    48901
+}
+
+impl Default for ApiSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: default_api_port(),
+            token: None,
+        }
+    }
+}
+
+/// Region lookup of joining players, see `GeoIpDatabase`.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct GeoIpSettings {
+    /// CSV file with IP ranges and their region. Players get no region if not set.
+    #[serde(rename = "Database")]
+    pub database: Option<String>,
+}
+
+/// The `save` and `tp` commands.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct TeleportSettings {
+    #[serde(rename = "Enabled", default)]
+    pub enabled: bool,
+
+    #[serde(rename = "AdminOnly", default)]
+    pub admin_only: bool,
+}
+
+/// Cars of each player, kept between sessions.
+#[derive(Deserialize, Clone, Debug)]
+pub struct GarageSettings {
+    #[serde(rename = "Enabled", default)]
+    pub enabled: bool,
+
+    /// Where the garages are saved. Not in the event directory, so they're kept between events.
+    #[serde(rename = "File", default = "default_garage_file")]
+    pub file: String,
+}
+
+fn default_garage_file() -> String {
+    String::from("garages.json")
+}
+
+impl Default for GarageSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            file: default_garage_file(),
+        }
+    }
+}
+
+/// Players banned with the ban command.
+#[derive(Deserialize, Clone, Debug)]
+pub struct BanSettings {
+    /// Where the bans are saved. Not in the event directory, so they're kept between events.
+    #[serde(rename = "File", default = "default_bans_file")]
+    pub file: String,
+}
+
+fn default_bans_file() -> String {
+    String::from("bans.json")
+}
+
+impl Default for BanSettings {
+    fn default() -> Self {
+        Self {
+            file: default_bans_file(),
+        }
+    }
+}
+
+/// Time of day and weather set with the world command.
+#[derive(Deserialize, Clone, Debug)]
+pub struct WorldSettings {
+    /// Where the world state is saved, so it's kept between restarts.
+    #[serde(rename = "File", default = "default_world_file")]
+    pub file: String,
+}
+
+fn default_world_file() -> String {
+    String::from("world.json")
+}
+
+impl Default for WorldSettings {
+    fn default() -> Self {
+        Self {
+            file: default_world_file(),
+        }
+    }
+}
+
+/// Values plugins keep per player, see `MP.SetPlayerValue`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct PlayerStoreSettings {
+    /// Where the player values are saved. Not in the event directory, so they're kept between events.
+    #[serde(rename = "File", default = "default_player_store_file")]
+    pub file: String,
+}
+
+fn default_player_store_file() -> String {
+    String::from("store.json")
+}
+
+impl Default for PlayerStoreSettings {
+    fn default() -> Self {
+        Self {
+            file: default_player_store_file(),
+        }
+    }
+}
+
+/// Nearby cars sent to each player, for client side spotter and radar mods.
+#[derive(Deserialize, Clone, Debug)]
+pub struct RadarSettings {
+    #[serde(rename = "Enabled", default)]
+    pub enabled: bool,
+
+    /// Cars further away than this (in meters) aren't included.
+    #[serde(rename = "Range", default = "default_radar_range")]
+    pub range: f64,
+
+    /// How many times per second players get the nearby cars.
+    #[serde(rename = "UpdateRate", default = "default_radar_update_rate")]
+    pub update_rate: u32,
+}
+
+impl Default for RadarSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            range: default_radar_range(),
+            update_rate: default_radar_update_rate(),
+        }
+    }
+}
+
+fn default_radar_range() -> f64 {
+    30.0
+}
+
+fn default_radar_update_rate() -> u32 {
+    5
+}
+
+/// Limits for grading the connection of players. A connection over any of the limits is poor.
+#[derive(Deserialize, Clone, Debug)]
+pub struct ConnectionQualitySettings {
+    #[serde(rename = "MaxPingMs", default = "default_max_ping_ms")]
+    pub max_ping_ms: f64,
+
+    /// Average difference between consecutive pings, in milliseconds.
+    #[serde(rename = "MaxJitterMs", default = "default_max_jitter_ms")]
+    pub max_jitter_ms: f64,
+
+    /// Percentage of position updates that never arrived.
+    #[serde(rename = "MaxLossPercent", default = "default_max_loss_percent")]
+    pub max_loss_percent: f64,
+
+    /// Warns players with a poor connection, and moves them to spectator if it stays poor.
+    #[serde(rename = "AutoSpectate", default)]
+    pub auto_spectate: bool,
+
+    /// Seconds of poor connection before the player is warned.
+    #[serde(rename = "WarnAfter", default = "default_warn_after")]
+    pub warn_after: u64,
+
+    /// Seconds of poor connection before the player is moved to spectator. They can drive again
+    /// once their connection hasn't been poor for the same amount of time. Spectators have no
+    /// cars to measure, so then only the jitter and loss of their UDP pings count.
+    #[serde(rename = "SpectateAfter", default = "default_spectate_after")]
+    pub spectate_after: u64,
+}
+
+impl Default for ConnectionQualitySettings {
+    fn default() -> Self {
+        Self {
+            max_ping_ms: default_max_ping_ms(),
+            max_jitter_ms: default_max_jitter_ms(),
+            max_loss_percent: default_max_loss_percent(),
+            auto_spectate: false,
+            warn_after: default_warn_after(),
+            spectate_after: default_spectate_after(),
+        }
+    }
+}
+
+fn default_max_ping_ms() -> f64 {
+    250.0
+}
+
+fn default_max_jitter_ms() -> f64 {
+    50.0
+}
+
+fn default_max_loss_percent() -> f64 {
+    5.0
+}
+
+fn default_warn_after() -> u64 {
+    5
+}
+
+fn default_spectate_after() -> u64 {
+    15
+}
+
+/// Simulated clients started with `--bots`, for load testing.
+#[derive(Deserialize, Clone, Debug)]
+pub struct BotSettings {
+    /// Speed of the bots, in m/s.
+    #[serde(rename = "Speed", default = "default_bot_speed")]
+    pub speed: f64,
+
+    /// Bots drive in a circle with this radius (in meters) around the center.
+    #[serde(rename = "Radius", default = "default_bot_radius")]
+    pub radius: f64,
+
+    #[serde(rename = "Center", default)]
+    pub center: [f64; 3],
+
+    /// Vehicle data the bots spawn with.
+    #[serde(rename = "Car", default = "default_bot_car")]
+    pub car: String,
+}
+
+impl Default for BotSettings {
+    fn default() -> Self {
+        Self {
+            speed: default_bot_speed(),
+            radius: default_bot_radius(),
+            center: [0.0; 3],
+            car: default_bot_car(),
+        }
+    }
+}
+
+fn default_bot_speed() -> f64 {
+    20.0
+}
+
+fn default_bot_radius() -> f64 {
+    100.0
+}
+
+fn default_bot_car() -> String {
+    String::from(r#"{"jbm":"pickup","vcf":{"model":"pickup","partConfigFilename":"vehicles/pickup/d15_4wd_A.pc"}}"#)
+}
+
+/// Settings for all outbound HTTP requests (authentication and heartbeat).
+#[derive(Deserialize, Clone, Debug)]
+pub struct HttpSettings {
+    #[serde(rename = "TimeoutSeconds", default = "default_http_timeout_seconds")]
+    pub timeout_seconds: u64,
+
+    /// How often a request is retried when the backend can't be reached.
+    #[serde(rename = "Retries", default = "default_http_retries")]
+    pub retries: u32,
+
+    /// Delay before the first retry, in milliseconds. Doubles with every retry, plus some jitter.
+    #[serde(rename = "RetryDelayMs", default = "default_http_retry_delay_ms")]
+    pub retry_delay_ms: u64,
+
+    /// Proxy to send all requests through, e.g. `http://127.0.0.1:8080` or `socks5://...`.
+    #[serde(rename = "Proxy")]
+    pub proxy: Option<String>,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            timeout_seconds: default_http_timeout_seconds(),
+            retries: default_http_retries(),
+            retry_delay_ms: default_http_retry_delay_ms(),
+            proxy: None,
+        }
+    }
+}
+
+fn default_http_timeout_seconds() -> u64 {
+    10
+}
+
+fn default_http_retries() -> u32 {
+    2
+}
+
+fn default_http_retry_delay_ms() -> u64 {
+    1000
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ModSettings {
+    /// Also serve the client resources over HTTP on the game port, under `/mods/<name>`.
+    #[serde(rename = "ServeOverHttp", default)]
+    pub serve_over_http: bool,
+
+    /// Extra information for the mod manifest, keyed by the mod's file name.
+    #[serde(rename = "Info", default)]
+    pub info: HashMap<String, ModInfoSettings>,
+
+    /// Mods to download into the client resource folder and keep up to date.
+    #[serde(rename = "Remote", default)]
+    pub remote: Vec<RemoteModSettings>,
+
+    /// URL of a JSON list of remote mods, in the same format as `Remote`.
+    #[serde(rename = "RepositoryIndex")]
+    pub repository_index: Option<String>,
+
+    /// How often remote mods are checked for updates. 0 only syncs them at startup.
+    #[serde(rename = "SyncIntervalMinutes", default = "default_mod_sync_interval_minutes")]
+    pub sync_interval_minutes: u64,
+}
+
+impl Default for ModSettings {
+    fn default() -> Self {
+        Self {
+            serve_over_http: false,
+            info: HashMap::new(),
+            remote: Vec::new(),
+            repository_index: None,
+            sync_interval_minutes: default_mod_sync_interval_minutes(),
+        }
+    }
+}
+
+fn default_mod_sync_interval_minutes() -> u64 {
+    60
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct RemoteModSettings {
+    #[serde(rename = "Url", alias = "url")]
+    pub url: String,
+
+    /// Where to store the mod, relative to the client resource folder (like `maps/track.zip`).
+    #[serde(rename = "Path", alias = "path")]
+    pub path: String,
+
+    /// Expected SHA256 hash of the file. Without it, the mod is downloaded again on every sync.
+    #[serde(rename = "Sha256", alias = "sha256")]
+    pub sha256: Option<String>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ModInfoSettings {
+    #[serde(rename = "Version")]
+    pub version: Option<String>,
+
+    /// Optional mods are not pushed to players when they join.
+    #[serde(rename = "Required", default = "default_true")]
+    pub required: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
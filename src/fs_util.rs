@@ -1,4 +1,13 @@
-use std::path::{Path, PathBuf};
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Extension used for files that are still being written. Anything scanning folders the
+/// server writes to should skip these.
+pub const TEMP_EXTENSION: &str = "tmp";
 
 /// Ensures the given path exists by creating it if it doesn't.
 pub fn ensure_path_exists(path: &PathBuf) -> anyhow::Result<()> {
@@ -11,8 +20,18 @@ pub fn ensure_path_exists(path: &PathBuf) -> anyhow::Result<()> {
 
 /// Joins a parent folder and a sub-path, resolving the subpath beforehand to ensure that
 /// the resulting path is still within the parent folder, regardless of ".." in the sub-path.
+/// Sub-paths may contain folders (like `maps/track.zip`), but any component that could escape
+/// the parent folder results in an error.
 pub fn join_path_secure(parent: &Path, sub: &Path) -> anyhow::Result<PathBuf> {
-    Ok(parent.join(sub.file_name().unwrap_or("".as_ref())))
+    let mut path = parent.to_path_buf();
+    for component in sub.components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::RootDir | Component::CurDir => {},
+            Component::ParentDir | Component::Prefix(_) => anyhow::bail!("Path {:?} escapes its parent folder", sub),
+        }
+    }
+    Ok(path)
 }
 
 /// Converts a PathBuf into a String in a lossy way. This is generally the way we want to do it
@@ -20,3 +39,71 @@ pub fn join_path_secure(parent: &Path, sub: &Path) -> anyhow::Result<PathBuf> {
 pub fn path_to_string(path: PathBuf) -> String {
     path.into_os_string().to_string_lossy().to_string()
 }
+
+/// Returns the path with an extra extension appended, so `bans.json` becomes `bans.json.1`.
+fn append_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut name: OsString = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+/// Writes the data to a temporary file next to the path and then renames it into place,
+/// so a crash halfway through never leaves a half-written file behind.
+pub fn write_atomic(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            ensure_path_exists(&parent.to_path_buf())?;
+        }
+    }
+    let tmp_path = append_extension(path, TEMP_EXTENSION);
+    {
+        let mut file = std::fs::File::create(&tmp_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+    }
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// Like `write_atomic`, but keeps the previous `backups` versions of the file around as
+/// `<path>.1` (newest) up to `<path>.<backups>` (oldest).
+pub fn write_with_backup(path: &Path, data: &[u8], backups: usize) -> anyhow::Result<()> {
+    if backups > 0 && path.is_file() {
+        for i in (1..backups).rev() {
+            let from = append_extension(path, &i.to_string());
+            if from.is_file() {
+                std::fs::rename(&from, append_extension(path, &(i + 1).to_string()))?;
+            }
+        }
+        std::fs::copy(path, append_extension(path, "1"))?;
+    }
+    write_atomic(path, data)
+}
+
+/// Loads JSON saved by an earlier session. Without a file there's nothing saved yet, so it starts
+/// from the default. A file that can't be read is logged as `what` (like "the ban list") and
+/// also starts from the default, as the server shouldn't refuse to start over it.
+pub fn load_json<T: DeserializeOwned + Default>(path: &Path, what: &str) -> T {
+    let result = match std::fs::read_to_string(path) {
+        Ok(data) => serde_json::from_str(&data).map_err(anyhow::Error::from),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return T::default(),
+        Err(e) => Err(e.into()),
+    };
+    result.unwrap_or_else(|e| {
+        error!("Failed to read {}, starting without it: {:?}", what, e);
+        T::default()
+    })
+}
+
+/// Saves the value as pretty printed JSON, keeping `backups` previous versions.
+pub fn save_json<T: Serialize>(path: &Path, value: &T, backups: usize) -> anyhow::Result<()> {
+    let data = serde_json::to_string_pretty(value)?;
+    write_with_backup(path, data.as_bytes(), backups)
+}
+
+/// Saves the value as TOML, keeping `backups` previous versions.
+pub fn save_toml<T: Serialize>(path: &Path, value: &T, backups: usize) -> anyhow::Result<()> {
+    let data = toml::to_string_pretty(value)?;
+    write_with_backup(path, data.as_bytes(), backups)
+}
@@ -0,0 +1,118 @@
+use std::net::IpAddr;
+use std::path::Path;
+
+/// A local GeoIP database, loaded from a CSV file with one IP range per line, like the free
+/// country databases of DB-IP (`1.0.0.0,1.0.0.255,AU`) and IP2Location (`"16777216","16777471","AU",...`).
+/// The third column is used as the region.
+pub struct GeoIpDatabase {
+    /// Ranges as (first, last, region), sorted by their first address. IPv4 addresses are stored
+    /// as IPv4-mapped IPv6 addresses, so both fit in one list.
+    ranges: Vec<(u128, u128, String)>,
+}
+
+fn ip_to_u128(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(ip) => u128::from(ip.to_ipv6_mapped()),
+        IpAddr::V6(ip) => u128::from(ip),
+    }
+}
+
+/// Parses an address, or a number as used by IP2Location (IPv4 if it fits in 32 bits).
+fn parse_address(value: &str) -> Option<u128> {
+    let value = value.trim().trim_matches('"');
+    if let Ok(ip) = value.parse::<IpAddr>() {
+        return Some(ip_to_u128(ip));
+    }
+    let number: u128 = value.parse().ok()?;
+    match u32::try_from(number) {
+        Ok(ipv4) => Some(ip_to_u128(IpAddr::V4(ipv4.into()))),
+        Err(_) => Some(number),
+    }
+}
+
+impl GeoIpDatabase {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Ok(Self::parse(&std::fs::read_to_string(path)?))
+    }
+
+    /// Parses the CSV data, skipping lines that aren't a valid range.
+    pub fn parse(data: &str) -> Self {
+        let mut ranges: Vec<(u128, u128, String)> = data.lines()
+            .filter_map(|line| {
+                let mut columns = line.split(',');
+                let first = parse_address(columns.next()?)?;
+                let last = parse_address(columns.next()?)?;
+                let region = columns.next()?.trim().trim_matches('"').to_string();
+                (first <= last && !region.is_empty() && region != "-").then_some((first, last, region))
+            })
+            .collect();
+        ranges.sort_by_key(|(first, _, _)| *first);
+        Self { ranges }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Returns the region of the address, if it's in the database.
+    pub fn lookup(&self, ip: IpAddr) -> Option<&str> {
+        let ip = ip_to_u128(ip);
+        // The last range that starts at or before the address is the only one that can contain it
+        let index = self.ranges.partition_point(|(first, _, _)| *first <= ip).checked_sub(1)?;
+        let (_, last, region) = &self.ranges[index];
+        (ip <= *last).then_some(region.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(db: &GeoIpDatabase, ip: &str) -> Option<String> {
+        db.lookup(ip.parse().unwrap()).map(String::from)
+    }
+
+    #[test]
+    fn ipv4_and_ipv6_ranges() {
+        let db = GeoIpDatabase::parse("1.0.0.0,1.0.0.255,AU\n2001:db8::,2001:db8::ffff,NL\n");
+        assert_eq!(lookup(&db, "1.0.0.42"), Some(String::from("AU")));
+        assert_eq!(lookup(&db, "1.0.1.0"), None);
+        assert_eq!(lookup(&db, "2001:db8::1234"), Some(String::from("NL")));
+        assert_eq!(lookup(&db, "2001:db8::1:0"), None);
+        // IPv4-mapped IPv6 addresses are the same as their IPv4 address
+        assert_eq!(lookup(&db, "::ffff:1.0.0.1"), Some(String::from("AU")));
+    }
+
+    #[test]
+    fn numeric_ranges() {
+        let db = GeoIpDatabase::parse(concat!(
+            "\"16777216\",\"16777471\",\"AU\",\"Australia\"\n",
+            // Past 32 bits, numbers are IPv6 addresses
+            "\"42540766411282592856903984951653826560\",\"42540766411282592856903984951653892095\",\"NL\",\"Netherlands\"\n",
+        ));
+        assert_eq!(db.len(), 2);
+        assert_eq!(lookup(&db, "1.0.0.1"), Some(String::from("AU")));
+        assert_eq!(lookup(&db, "2001:db8::1"), Some(String::from("NL")));
+        assert_eq!(lookup(&db, "1.0.1.0"), None);
+    }
+
+    #[test]
+    fn malformed_rows_are_skipped() {
+        let db = GeoIpDatabase::parse(concat!(
+            "ip_from,ip_to,country\n",
+            "1.0.0.0,1.0.0.255\n",
+            "1.0.1.255,1.0.1.0,AU\n",
+            "1.0.2.0,1.0.2.255,-\n",
+            "1.0.3.0,1.0.3.255,\n",
+            "not an ip,1.0.4.255,AU\n",
+            "1.0.5.0,1.0.5.255,JP\n",
+        ));
+        assert_eq!(db.len(), 1);
+        assert_eq!(lookup(&db, "1.0.5.5"), Some(String::from("JP")));
+        assert_eq!(lookup(&db, "1.0.2.5"), None);
+    }
+}
@@ -1,5 +1,53 @@
+use std::time::{Duration, Instant};
+
 use serde::Serialize;
 use tokio::sync::mpsc::Receiver;
+use tokio::sync::watch;
+
+/// Health of the connection to the BeamMP backend, as seen by the heartbeat.
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct HeartbeatHealth {
+    /// False if the server isn't meant to show up on the server list, because it's private
+    /// or the AuthKey is invalid.
+    pub public: bool,
+    pub last_success: Option<Instant>,
+    pub consecutive_failures: u32,
+    /// Round trip time of the last successful heartbeat.
+    pub latency: Option<Duration>,
+    /// Reason the backend gave for refusing the AuthKey, if it did.
+    pub key_refused: Option<String>,
+}
+
+impl HeartbeatHealth {
+    /// Returns true if the last heartbeat went through, meaning the server shows up on the server list.
+    pub fn is_listed(&self) -> bool {
+        self.public && self.last_success.is_some() && self.consecutive_failures == 0 && self.key_refused.is_none()
+    }
+}
+
+/// What the backend said about the AuthKey in response to a heartbeat.
+#[derive(PartialEq, Debug)]
+enum KeyStatus {
+    Authenticated(String),
+    Refused(String),
+}
+
+/// Reads the response to a heartbeat, like `{"status":"2000","code":"...","msg":"..."}`.
+/// Status 2000 means the key was accepted, 200 that an earlier session was resumed.
+fn parse_heartbeat_response(body: &str) -> Option<KeyStatus> {
+    let response: serde_json::Value = serde_json::from_str(body).ok()?;
+    let status = match &response["status"] {
+        serde_json::Value::String(status) => status.clone(),
+        serde_json::Value::Number(status) => status.to_string(),
+        _ => return None,
+    };
+    let message = response["msg"].as_str().unwrap_or_default().to_string();
+    match status.as_str() {
+        "2000" | "200" => Some(KeyStatus::Authenticated(message)),
+        _ if message.is_empty() => Some(KeyStatus::Refused(String::from("The backend didn't give a reason"))),
+        _ => Some(KeyStatus::Refused(message)),
+    }
+}
 
 #[derive(Serialize)]
 struct HeartbeatInfo {
@@ -19,13 +67,10 @@ struct HeartbeatInfo {
     desc: String,
 }
 
-pub async fn backend_heartbeat(config: std::sync::Arc<crate::config::Config>, mut hb_rx: Receiver<crate::server::ServerStatus>) {
+pub async fn backend_heartbeat(config: std::sync::Arc<crate::config::Config>, mut hb_rx: Receiver<crate::server::ServerStatus>, health_tx: watch::Sender<HeartbeatHealth>) {
     if !config.general.is_auth_key_valid() {
-        if config.general.private {
-            warn!("AuthKey has invalid format. This is not an error, since your server is private.");
-        } else {
-            error!("AuthKey has invalid format. The server will not appear on the server list.");
-        }
+        // Public servers refuse to start without a valid key, see `main`
+        warn!("AuthKey has invalid format. This is not an error, since your server is private.");
         // FIXME: The heartbeat should be started if the config is ever changed/reloaded.
         return;
     }
@@ -39,23 +84,74 @@ pub async fn backend_heartbeat(config: std::sync::Arc<crate::config::Config>, mu
         version: String::from("3.3.0"), // TODO: Don't hardcode this
         clientversion: String::from("2.0"), // TODO: What? I think for now I can fill in 2.0
         name: config.general.name.clone(),
-        modlist: String::from("-"), // TODO: Implement this
-        modstotalsize: 0, // TODO: Implement this
-        modstotal: 0, // TODO: Implement this
+        modlist: String::from("-"),
+        modstotalsize: 0,
+        modstotal: 0,
         playerslist: String::new(),
         desc: config.general.description.clone(),
     };
 
+    let mut health = HeartbeatHealth {
+        public: !config.general.private,
+        ..Default::default()
+    };
+    let _ = health_tx.send(health.clone());
+
+    let client = match crate::server::http_client_builder(&config.http)
+        .and_then(|builder| Ok(builder.local_address("0.0.0.0".parse::<std::net::IpAddr>().unwrap()).build()?))
+    {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to create the heartbeat HTTP client: {e}");
+            return;
+        }
+    };
+
+    // The first heartbeat goes out right away, which is what validates the AuthKey at startup
     let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(30));
+    let mut authenticated = false;
     loop {
-        interval.tick().await;
-
         tokio::select! {
-            _ = heartbeat_post(&info) => {}
+            _ = interval.tick() => {
+                let mods = crate::mods::get_required_mods();
+                info.modlist = if mods.is_empty() { String::from("-") } else { mods.iter().map(|m| format!("{};", m.name)).collect() };
+                info.modstotalsize = mods.iter().map(|m| m.size).sum();
+                info.modstotal = mods.len();
+
+                let start = Instant::now();
+                match crate::server::with_retries(&config.http, || heartbeat_post(&client, &info)).await {
+                    Ok(key_status) => {
+                        health.last_success = Some(Instant::now());
+                        health.latency = Some(start.elapsed());
+                        health.consecutive_failures = 0;
+                        match key_status {
+                            Some(KeyStatus::Refused(reason)) => {
+                                if health.key_refused.as_ref() != Some(&reason) {
+                                    error!("The BeamMP backend refused the AuthKey: {reason}");
+                                }
+                                health.key_refused = Some(reason);
+                            },
+                            Some(KeyStatus::Authenticated(message)) => {
+                                if health.key_refused.take().is_some() || !authenticated {
+                                    info!("Authenticated with the BeamMP backend: {message}");
+                                }
+                                authenticated = true;
+                            },
+                            None => {},
+                        }
+                    },
+                    Err(e) => {
+                        health.consecutive_failures += 1;
+                        error!("Heartbeat error occured ({} in a row): {e}", health.consecutive_failures);
+                    },
+                }
+                let _ = health_tx.send(health.clone());
+            }
             status = hb_rx.recv() => {
                 if let Some(status) = status {
                     trace!("status update: {:?}", status);
                     info.players = status.player_count;
+                    info.maxplayers = status.max_players;
                     info.playerslist = status.player_list.iter().map(|(_id, name)| format!("{};", name)).collect();
                 }
             }
@@ -63,18 +159,41 @@ pub async fn backend_heartbeat(config: std::sync::Arc<crate::config::Config>, mu
     }
 }
 
-async fn heartbeat_post(heartbeat_info: &HeartbeatInfo) {
-    match reqwest::Client::builder()
-        .local_address("0.0.0.0".parse::<std::net::IpAddr>().unwrap())
-        .build().unwrap()
-        .post("https://backend.beammp.com/heartbeat")
+async fn heartbeat_post(client: &reqwest::Client, heartbeat_info: &HeartbeatInfo) -> anyhow::Result<Option<KeyStatus>> {
+    let resp = client
+        .post(format!("https://{}/heartbeat", crate::server::BACKEND_URL))
         .form(heartbeat_info)
         .send()
-        .await
-    {
-        Ok(resp) => {
-            trace!("heartbeat response:\n{:?}", resp.text().await);
-        },
-        Err(e) => error!("Heartbeat error occured: {e}"),
+        .await?
+        .error_for_status()?;
+    let body = resp.text().await?;
+    trace!("heartbeat response:\n{:?}", body);
+    Ok(parse_heartbeat_response(&body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heartbeat_responses_are_parsed() {
+        assert_eq!(
+            parse_heartbeat_response(r#"{"status":"2000","code":"","msg":"Authenticated!"}"#),
+            Some(KeyStatus::Authenticated(String::from("Authenticated!"))),
+        );
+        assert_eq!(
+            parse_heartbeat_response(r#"{"status":200,"msg":"Resumed"}"#),
+            Some(KeyStatus::Authenticated(String::from("Resumed"))),
+        );
+        assert_eq!(
+            parse_heartbeat_response(r#"{"status":"4000","msg":"Invalid key"}"#),
+            Some(KeyStatus::Refused(String::from("Invalid key"))),
+        );
+        assert_eq!(
+            parse_heartbeat_response(r#"{"status":"4000"}"#),
+            Some(KeyStatus::Refused(String::from("The backend didn't give a reason"))),
+        );
+        assert_eq!(parse_heartbeat_response(r#"{"msg":"No status"}"#), None);
+        assert_eq!(parse_heartbeat_response("<html>Bad gateway</html>"), None);
     }
 }
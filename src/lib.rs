@@ -0,0 +1,17 @@
+#[macro_use] extern crate log;
+#[macro_use] extern crate async_trait;
+#[macro_use] extern crate lazy_static;
+
+pub mod logger;
+pub mod tui;
+pub mod server;
+pub mod config;
+pub mod heartbeat;
+pub mod fs_util;
+pub mod mods;
+pub mod mod_sync;
+pub mod bots;
+pub mod output;
+pub mod seed;
+pub mod check;
+pub mod geoip;
@@ -1,19 +1,15 @@
 #[macro_use] extern crate log;
-#[macro_use] extern crate async_trait;
-#[macro_use] extern crate lazy_static;
 
 use std::path::Path;
 use argh::FromArgs;
 
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::time::{Duration, Instant};
+use futures::FutureExt;
+use tokio::sync::{mpsc, watch};
+use tokio::time::MissedTickBehavior;
 
-mod logger;
-mod tui;
-mod server;
-mod config;
-mod heartbeat;
-mod fs_util;
+use beammp_rust_server::{bots, check, config, heartbeat, logger, mod_sync, mods, output, seed, server, tui};
 
 #[derive(FromArgs)]
 /// BeamMP Server v3.3.0
@@ -21,19 +17,38 @@ struct Args {
     /// disables the TUI and shows a simple console log instead
     #[argh(switch)]
     disable_tui: bool,
+
+    /// connects this many simulated clients to the server, for load testing
+    #[argh(option, default = "0")]
+    bots: usize,
+
+    /// checks the config, mods, plugins and backend connection, then exits. Exits with 1 on problems
+    #[argh(switch)]
+    check: bool,
 }
 
 #[tokio::main]
 async fn main() {
     let args: Args = argh::from_env();
 
-    let mut user_config: config::Config = toml::from_str(
-            &std::fs::read_to_string("ServerConfig.toml")
-                .map_err(|_| error!("Failed to read config file!"))
-                .expect("Failed to read config file!")
-        )
-        .map_err(|_| eprintln!("Failed to parse config file!"))
-        .expect("Failed to parse config file!");
+    if args.check {
+        let ok = check::run_checks(config::CONFIG_PATH).await;
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
+    let user_config = match config::Config::load(config::CONFIG_PATH) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        },
+    };
+
+    if !user_config.general.private && !user_config.general.is_auth_key_valid() {
+        eprintln!("The AuthKey is missing or invalid, so the server can't be listed on the server list.");
+        eprintln!("Get a key at https://beammp.com/k/dashboard, or set Private = true to run the server without one.");
+        std::process::exit(1);
+    }
 
     let level_filter = if user_config.general.debug { log::LevelFilter::max() } else { log::LevelFilter::Info };
     if !args.disable_tui {
@@ -45,30 +60,42 @@ async fn main() {
             .init();
     }
 
+    let session_seed = seed::init_session_seed(user_config.general.seed);
+    info!("Session seed: {}", session_seed);
+
+    if let Err(e) = output::init_event_directory(&user_config) {
+        error!("Failed to create the event directory, saving event files to the working directory: {:?}", e);
+    }
+
+    if let Err(e) = server::start_capture(&user_config.capture) {
+        error!("Failed to start the packet capture: {:?}", e);
+    }
+
     let client_resources = user_config.general
         .get_client_resource_folder()
         .expect("Failed to create the client resource folder");
 
-    for entry in std::fs::read_dir(client_resources).expect("Failed to read client resource folder!") {
-        if let Ok(entry) = entry {
-            if entry.path().is_file() {
-                if let Ok(metadata) = entry.metadata() {
-                    if let Some(filename) = entry.path().file_name().map(|s| s.to_string_lossy()) {
-                        let mut name = filename.to_string();
-                        if !name.starts_with("/") {
-                            name = format!("/{name}");
-                        }
-                        user_config.mods.push((name, metadata.len() as usize));
-                    }
-                }
-            }
+    if !user_config.mods_settings.remote.is_empty() || user_config.mods_settings.repository_index.is_some() {
+        info!("Syncing remote mods...");
+        if let Err(e) = mod_sync::sync_remote_mods(&user_config).await {
+            error!("Failed to sync remote mods: {:?}", e);
         }
     }
 
-    debug!("Mods: {:?}", user_config.mods);
+    mods::scan_mods(Path::new(&client_resources), &user_config.mods_settings).expect("Failed to read client resource folder!");
+
+    debug!("Mods: {:?}", mods::get_mods());
+    if user_config.mods_settings.serve_over_http {
+        info!("Mods are also available over HTTP at http://<server ip>:{}/mods", user_config.general.port.unwrap_or(48900));
+    }
+    tokio::spawn(mods::watch_mods(client_resources, user_config.mods_settings.clone()));
 
     let user_config = Arc::new(user_config);
 
+    if !user_config.mods_settings.remote.is_empty() || user_config.mods_settings.repository_index.is_some() {
+        tokio::spawn(mod_sync::remote_mod_sync(user_config.clone()));
+    }
+
     let (cmd_tx, cmd_rx) = mpsc::channel(100);
     let (status_tx, status_rx) = mpsc::channel(100);
 
@@ -76,51 +103,63 @@ async fn main() {
         tokio::spawn(tui::tui_main(user_config.clone(), cmd_tx, status_rx));
     }
 
+    if args.bots > 0 {
+        tokio::spawn(bots::run_bots(user_config.general.port.unwrap_or(48900), args.bots, user_config.bots.clone()));
+    }
+
     server_main(user_config, cmd_rx, status_tx).await;
 }
 
 async fn server_main(user_config: Arc<config::Config>, mut cmd_rx: mpsc::Receiver<Vec<String>>, status_tx: mpsc::Sender<server::ServerStatus>) {
     let (hb_tx, hb_rx) = mpsc::channel(100);
+    let (hb_health_tx, hb_health_rx) = watch::channel(heartbeat::HeartbeatHealth::default());
 
-    tokio::spawn(heartbeat::backend_heartbeat(user_config.clone(), hb_rx));
+    tokio::spawn(heartbeat::backend_heartbeat(user_config.clone(), hb_rx, hb_health_tx));
 
-    let mut server = server::Server::new(user_config)
+    let mut server = server::Server::new(user_config.clone(), hb_health_rx)
         .await
         .map_err(|e| error!("{:?}", e))
         .expect("Failed to start server!");
 
+    let tick_rate = user_config.general.tick_rate.max(1);
+    let tick_interval = Duration::from_secs_f64(1.0 / tick_rate as f64);
+    let mut ticker = tokio::time::interval(tick_interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut tick_stats = TickStats::default();
+
     let mut status = server.get_server_status();
     hb_tx.send(status.clone()).await;
     status_tx.send(status.clone()).await;
     'server: loop {
         // TODO: Error handling
-        if server.clients.len() > 0 {
-            tokio::select! {
-                ret = server::read_tcp(&mut server.clients) => {
-                    match ret {
-                        Ok(ret) => if let Some((index, packet)) = ret {
-                            server.process_tcp(index, packet).await;
-                        },
-                        Err(e) => error!("Error: {e}"),
-                    }
+        tokio::select! {
+            Some((id, session, result)) = server::read_tcp(&mut server.tcp_rx) => {
+                if let Err(e) = server.process_tcp_read(id, session, result).await {
+                    error!("{}", e);
                 }
-                ret = server::read_udp(&mut server.udp_socket) => {
-                    if let Some((addr, packet)) = ret {
-                        server.process_udp(addr, packet).await;
+                continue 'server;
+            }
+            packets = server::read_udp(&server.udp_socket), if !server.clients.is_empty() => {
+                for (addr, packet) in packets {
+                    if let Err(e) = server.process_udp(addr, packet).await {
+                        error!("{}", e);
                     }
                 }
-                _ = tokio::time::sleep(tokio::time::Duration::from_millis(50)) => {}
+                continue 'server;
             }
-        } else {
-            // TODO: Scuffed?
-            tokio::time::sleep(tokio::time::Duration::from_millis(150)).await;
+            _ = ticker.tick() => {}
         }
 
-        if let Err(e) = server.process().await {
-            error!("{:?}", e);
+        let tick_start = Instant::now();
+        match std::panic::AssertUnwindSafe(server.process()).catch_unwind().await {
+            Ok(Err(e)) => error!("{:?}", e),
+            Err(payload) => error!("Panic while processing the server tick: {}", server::panic_message(&payload)),
+            Ok(Ok(())) => {},
         }
+        tick_stats.record(tick_start.elapsed(), tick_interval, tick_rate);
 
-        let new_status = server.get_server_status();
+        let mut new_status = server.get_server_status();
+        new_status.tick_time_ms = tick_stats.last_max.as_millis() as u64;
 
         if status != new_status {
             status = new_status;
@@ -131,27 +170,11 @@ async fn server_main(user_config: Arc<config::Config>, mut cmd_rx: mpsc::Receive
         // Process commands
         match cmd_rx.try_recv() {
             Ok(cmd) => if cmd.len() > 0 {
-                match cmd[0].as_str() {
-                    "exit" => {
-                        server.close().await;
-                        break 'server;
-                    },
-                    "players" => {
-                        let mut pl = "Players:\n".to_string();
-                        for (i, (id, player)) in status.player_list.iter().enumerate() {
-                            pl.push_str(&format!("\t[{: >2}] - {player}", id));
-                            if i + 1 < status.player_list.len() {
-                                pl.push('\n');
-                            }
-                        }
-                        info!("{}", pl);
-                    },
-                    "say" => {
-                        let msg = cmd[1..].iter().map(|s| s.as_str()).collect::<Vec<&str>>().join(" ");
-                        server.send_chat_message(&msg, None).await;
-                    },
-                    _ => info!("Unknown command!"),
+                if cmd[0] == "exit" {
+                    server.close().await;
+                    break 'server;
                 }
+                server.run_command(server::CommandSource::Console, &cmd).await;
             } else {
                 // what!
             },
@@ -163,3 +186,37 @@ async fn server_main(user_config: Arc<config::Config>, mut cmd_rx: mpsc::Receive
         }
     }
 }
+
+/// Keeps track of how long server ticks take, and warns (at most once per second) when
+/// ticks take longer than the tick interval.
+#[derive(Default)]
+struct TickStats {
+    ticks: u32,
+    overruns: u32,
+    max: Duration,
+    /// Slowest tick of the last full second
+    last_max: Duration,
+}
+
+impl TickStats {
+    fn record(&mut self, tick_time: Duration, tick_interval: Duration, tick_rate: u32) {
+        self.ticks += 1;
+        self.max = self.max.max(tick_time);
+        if tick_time > tick_interval {
+            self.overruns += 1;
+        }
+
+        if self.ticks >= tick_rate {
+            if self.overruns > 0 {
+                warn!(
+                    "{} of the last {} server ticks took longer than {} ms (slowest: {} ms). Consider lowering the TickRate.",
+                    self.overruns, self.ticks, tick_interval.as_millis(), self.max.as_millis(),
+                );
+            }
+            self.last_max = self.max;
+            self.ticks = 0;
+            self.overruns = 0;
+            self.max = Duration::ZERO;
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::config::{Config, RemoteModSettings};
+use crate::fs_util;
+use crate::server::{http_client_builder, with_retries};
+
+/// Downloads all remote mods (from the config and the repository index) that are missing or
+/// out of date. Returns true if any mod was updated.
+pub async fn sync_remote_mods(config: &Config) -> anyhow::Result<bool> {
+    let client = http_client_builder(&config.http)?.build()?;
+    let client_resources = config.general.get_client_resource_folder()?;
+
+    let mut remote = config.mods_settings.remote.clone();
+    if let Some(index_url) = &config.mods_settings.repository_index {
+        match fetch_index(&client, config, index_url).await {
+            Ok(mut mods) => remote.append(&mut mods),
+            Err(e) => error!("Failed to fetch the mod repository index: {:?}", e),
+        }
+    }
+
+    let mut changed = false;
+    for remote_mod in &remote {
+        match sync_mod(&client, config, Path::new(&client_resources), remote_mod).await {
+            Ok(updated) => changed |= updated,
+            Err(e) => error!("Failed to sync remote mod {}: {:?}", remote_mod.path, e),
+        }
+    }
+    Ok(changed)
+}
+
+/// Syncs the remote mods at the configured interval, rescanning the client resources
+/// whenever something changed.
+pub async fn remote_mod_sync(config: Arc<Config>) {
+    if config.mods_settings.sync_interval_minutes == 0 {
+        return;
+    }
+    let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(config.mods_settings.sync_interval_minutes * 60));
+    interval.tick().await; // The first tick completes immediately, and we synced at startup
+    loop {
+        interval.tick().await;
+        match sync_remote_mods(&config).await {
+            Ok(true) => {
+                let client_resources = match config.general.get_client_resource_folder() {
+                    Ok(path) => path,
+                    Err(e) => { error!("{:?}", e); continue; },
+                };
+                // Scanning hashes every mod, which shouldn't hold up the runtime
+                let settings = config.mods_settings.clone();
+                let scan = tokio::task::spawn_blocking(move || crate::mods::scan_mods(Path::new(&client_resources), &settings)).await;
+                if let Err(e) = scan.map_err(anyhow::Error::from).and_then(|result| result) {
+                    error!("Failed to scan client resources: {:?}", e);
+                }
+            },
+            Ok(false) => {},
+            Err(e) => error!("Failed to sync remote mods: {:?}", e),
+        }
+    }
+}
+
+async fn fetch_index(client: &reqwest::Client, config: &Config, url: &str) -> anyhow::Result<Vec<RemoteModSettings>> {
+    with_retries(&config.http, || async {
+        Ok(client.get(url).send().await?.error_for_status()?.json().await?)
+    }).await
+}
+
+/// Downloads a single mod if it's missing or its hash doesn't match. The file is written
+/// atomically, so clients never see half a mod.
+async fn sync_mod(client: &reqwest::Client, config: &Config, client_resources: &Path, remote_mod: &RemoteModSettings) -> anyhow::Result<bool> {
+    let path = fs_util::join_path_secure(client_resources, Path::new(&remote_mod.path))?;
+    let expected = remote_mod.sha256.as_ref().map(|h| h.to_lowercase());
+
+    if let Some(expected) = &expected {
+        if hash_existing(&path).await?.as_ref() == Some(expected) {
+            return Ok(false);
+        }
+    }
+
+    debug!("Downloading remote mod {} from {}", remote_mod.path, remote_mod.url);
+    let data = with_retries(&config.http, || async {
+        Ok(client.get(&remote_mod.url).send().await?.error_for_status()?.bytes().await?)
+    }).await?;
+
+    let (hash, data) = tokio::task::spawn_blocking(move || (crate::mods::hash_bytes(&data), data)).await?;
+    if let Some(expected) = &expected {
+        if &hash != expected {
+            anyhow::bail!("Hash mismatch, expected {} but got {}", expected, hash);
+        }
+    } else if hash_existing(&path).await? == Some(hash) {
+        return Ok(false);
+    }
+
+    let target = path.clone();
+    tokio::task::spawn_blocking(move || fs_util::write_atomic(&target, &data)).await??;
+    info!("Updated remote mod {}", remote_mod.path);
+    Ok(true)
+}
+
+/// Hashes the file at the path if there is one. Mods can be large, so it's done on the
+/// blocking thread pool.
+async fn hash_existing(path: &Path) -> anyhow::Result<Option<String>> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        if path.is_file() {
+            crate::mods::hash_file(&path).map(Some)
+        } else {
+            Ok(None)
+        }
+    }).await?
+}
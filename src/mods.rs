@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::SystemTime;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::config::ModSettings;
+use crate::fs_util;
+
+lazy_static! {
+    /// The current list of client resources. Kept up to date by `watch_mods`.
+    static ref MOD_LIST: RwLock<Vec<ModInfo>> = RwLock::new(Vec::new());
+}
+
+/// A single client resource, as listed in the mod manifest.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ModInfo {
+    /// Name of the mod, always starting with a `/`
+    pub name: String,
+    pub size: usize,
+    /// SHA256 hash of the file, hex encoded
+    pub hash: String,
+    pub version: Option<String>,
+    /// Optional mods are not sent to clients during the handshake, but are still listed in the
+    /// manifest and can be downloaded over HTTP.
+    pub required: bool,
+
+    #[serde(skip)]
+    modified: Option<SystemTime>,
+}
+
+/// Returns a copy of the current mod list.
+pub fn get_mods() -> Vec<ModInfo> {
+    MOD_LIST.read().expect("Mod list lock poisoned!").clone()
+}
+
+/// Returns the mods clients have to download when joining.
+pub fn get_required_mods() -> Vec<ModInfo> {
+    get_mods().into_iter().filter(|m| m.required).collect()
+}
+
+/// Returns the manifest of all mods as JSON.
+pub fn manifest_json() -> String {
+    serde_json::to_string(&get_mods()).unwrap_or_else(|_| String::from("[]"))
+}
+
+/// Scans the client resource folder and updates the mod list. Files are only hashed again
+/// if their size or modification time changed. Returns true if the mod list changed.
+pub fn scan_mods(client_resources: &Path, settings: &ModSettings) -> anyhow::Result<bool> {
+    let previous: HashMap<String, ModInfo> = get_mods().into_iter().map(|m| (m.name.clone(), m)).collect();
+    let mut mods = Vec::new();
+
+    let mut files = Vec::new();
+    collect_files(client_resources, &mut files)?;
+
+    for path in files {
+        // Mod names keep their path relative to the resource folder, so `maps/track.zip` becomes `/maps/track.zip`
+        let Ok(relative) = path.strip_prefix(client_resources) else { continue; };
+        let relative = relative.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect::<Vec<_>>().join("/");
+        let name = format!("/{}", relative);
+        let metadata = std::fs::metadata(&path)?;
+        let size = metadata.len() as usize;
+        let modified = metadata.modified().ok();
+
+        let hash = match previous.get(&name) {
+            Some(prev) if prev.size == size && prev.modified == modified && modified.is_some() => prev.hash.clone(),
+            _ => hash_file(&path)?,
+        };
+
+        let info = settings.info.get(name.trim_start_matches('/'));
+        mods.push(ModInfo {
+            name,
+            size,
+            hash,
+            version: info.and_then(|i| i.version.clone()),
+            required: info.map(|i| i.required).unwrap_or(true),
+            modified,
+        });
+    }
+    mods.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut lock = MOD_LIST.write().expect("Mod list lock poisoned!");
+    let changed = *lock != mods;
+    *lock = mods;
+    Ok(changed)
+}
+
+/// Rescans the client resource folder every few seconds, so mods can be added, updated
+/// or removed without restarting the server.
+pub async fn watch_mods(client_resources: String, settings: ModSettings) {
+    let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(10));
+    interval.tick().await; // The first tick completes immediately, and we just scanned at startup
+    loop {
+        interval.tick().await;
+        let path = client_resources.clone();
+        let settings = settings.clone();
+        match tokio::task::spawn_blocking(move || scan_mods(Path::new(&path), &settings)).await {
+            Ok(Ok(true)) => info!("Client resources changed, mod list updated ({} mods)", get_mods().len()),
+            Ok(Ok(false)) => {},
+            Ok(Err(e)) => error!("Failed to scan client resources: {:?}", e),
+            Err(e) => error!("Mod scanning task failed: {:?}", e),
+        }
+    }
+}
+
+/// Recursively collects all files in a folder, so mods can be organised in subfolders
+/// like `maps/` and `vehicles/`.
+fn collect_files(folder: &Path, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(folder)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else if path.is_file() && path.extension().map(|e| e != fs_util::TEMP_EXTENSION).unwrap_or(true) {
+            // Temporary files are downloads from the remote mod sync that haven't finished yet
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+pub fn hash_file(path: &Path) -> anyhow::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Returns the SHA256 hash of the data, hex encoded.
+pub fn hash_bytes(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
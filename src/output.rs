@@ -0,0 +1,80 @@
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::fs_util;
+
+/// Resolved once at startup, see `init_event_directory`.
+static EVENT_DIRECTORY: OnceLock<PathBuf> = OnceLock::new();
+
+/// Written to `event.json` in the event directory when the server starts.
+#[derive(Serialize)]
+struct EventInfo<'a> {
+    name: &'a str,
+    map: &'a str,
+    started_at: String,
+    /// Setting this as the `Seed` in the config reproduces the random decisions of this event.
+    seed: u64,
+}
+
+/// Resolves the event directory template, creates the directory and writes `event.json` to it.
+pub fn init_event_directory(config: &Config) -> anyhow::Result<PathBuf> {
+    let now = chrono::Local::now();
+    let path = PathBuf::from(resolve_template(
+        &config.output.event_directory,
+        &now.format("%Y-%m-%d").to_string(),
+        &now.format("%H-%M-%S").to_string(),
+        &map_name(&config.general.map),
+        &config.general.name,
+    ));
+    fs_util::ensure_path_exists(&path)?;
+
+    let info = EventInfo {
+        name: &config.general.name,
+        map: &config.general.map,
+        started_at: now.to_rfc3339(),
+        seed: crate::seed::session_seed(),
+    };
+    // Restarts on the same day end up in the same directory, so keep the info of earlier runs
+    fs_util::save_json(&path.join("event.json"), &info, 10)?;
+
+    info!("Event files are saved to {}", path.display());
+    let _ = EVENT_DIRECTORY.set(path.clone());
+    Ok(path)
+}
+
+/// Returns the path of a file in the event directory. Absolute paths are returned as is, and
+/// relative paths stay relative to the working directory if there is no event directory.
+pub fn event_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let path = path.as_ref();
+    match EVENT_DIRECTORY.get() {
+        Some(dir) if path.is_relative() => dir.join(path),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Turns `/levels/west_coast_usa/info.json` into `west_coast_usa`.
+fn map_name(map: &str) -> String {
+    let mut components = Path::new(map).components().map(|c| c.as_os_str().to_string_lossy());
+    components
+        .find(|c| c == "levels")
+        .and_then(|_| components.next())
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| map.to_string())
+}
+
+fn resolve_template(template: &str, date: &str, time: &str, map: &str, name: &str) -> String {
+    template
+        .replace("{date}", date)
+        .replace("{time}", time)
+        .replace("{map}", &sanitize_file_name(map))
+        .replace("{name}", &sanitize_file_name(name))
+}
+
+/// Replaces everything but letters, digits, `-` and `_`, so names can't add folders or
+/// characters some filesystems don't support.
+fn sanitize_file_name(name: &str) -> String {
+    name.chars().map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect()
+}
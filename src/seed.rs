@@ -0,0 +1,47 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::OnceLock;
+
+/// Picked once at startup, see `init_session_seed`.
+static SESSION_SEED: OnceLock<u64> = OnceLock::new();
+
+/// Sets the seed of this session, or picks a random one if none is configured.
+/// Calling it again returns the seed that was already picked.
+pub fn init_session_seed(configured: Option<u64>) -> u64 {
+    *SESSION_SEED.get_or_init(|| configured.unwrap_or_else(random_seed))
+}
+
+/// Returns the seed of this session.
+pub fn session_seed() -> u64 {
+    init_session_seed(None)
+}
+
+/// Returns a seed for one user of randomness (like a plugin), derived from the session seed.
+/// Every user gets its own sequence, so adding one doesn't change the numbers the others get.
+pub fn seed_for(name: &str) -> u64 {
+    // FNV-1a, as the std hashers aren't guaranteed to give the same result across Rust versions
+    let mut hash = 0xcbf29ce484222325u64;
+    for byte in name.bytes() {
+        hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+    }
+    splitmix64(session_seed() ^ hash) >> 11
+}
+
+/// Random seed of 53 bits, so it survives being stored as a JSON number or a TOML integer.
+fn random_seed() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0),
+    );
+    hasher.finish() >> 11
+}
+
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
@@ -0,0 +1,242 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot, Semaphore};
+
+use super::{read_request_head, write_body, write_status, HttpError, Server, ServerSnapshot};
+use crate::config::ApiSettings;
+use crate::heartbeat::HeartbeatHealth;
+
+/// Largest request headers, and largest body, we accept.
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+/// How long a client gets to send the body, after the headers.
+const BODY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Most requests handled at the same time. Connections past that are turned away.
+const MAX_CONNECTIONS: usize = 16;
+
+/// What an API request asks the server to do.
+#[derive(Debug)]
+pub enum ApiAction {
+    Players,
+    State,
+    Kick { player: String, reason: Option<String> },
+    Chat { message: String, player: Option<String> },
+}
+
+/// A request to the REST API, answered by the server between ticks. The response is JSON, or
+/// None if the player it's about isn't on the server.
+#[derive(Debug)]
+pub struct ApiRequest {
+    pub action: ApiAction,
+    pub response: oneshot::Sender<Option<String>>,
+}
+
+#[derive(Deserialize)]
+struct KickBody {
+    player: serde_json::Value,
+    reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatBody {
+    message: String,
+    player: Option<serde_json::Value>,
+}
+
+/// The answer to `/state`: the snapshot, plus whether the server shows up on the server list.
+#[derive(Serialize)]
+struct StateResponse {
+    #[serde(flatten)]
+    snapshot: ServerSnapshot,
+    heartbeat: HeartbeatJson,
+}
+
+#[derive(Serialize)]
+struct HeartbeatJson {
+    listed: bool,
+    public: bool,
+    /// Seconds since the last successful heartbeat, if there was one.
+    last_success: Option<f64>,
+    consecutive_failures: u32,
+    latency_ms: Option<u64>,
+    key_refused: Option<String>,
+}
+
+impl HeartbeatJson {
+    fn new(health: &HeartbeatHealth) -> Self {
+        Self {
+            listed: health.is_listed(),
+            public: health.public,
+            last_success: health.last_success.map(|time| time.elapsed().as_secs_f64()),
+            consecutive_failures: health.consecutive_failures,
+            latency_ms: health.latency.map(|latency| latency.as_millis() as u64),
+            key_refused: health.key_refused.clone(),
+        }
+    }
+}
+
+/// Players can be given by id (as a number or string) or by name.
+fn player_arg(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Listens for REST API requests and hands them over to the server. Without a token anyone that
+/// can reach the API can kick players, so it only listens on localhost then.
+pub async fn serve_api(settings: ApiSettings, tx: mpsc::Sender<ApiRequest>) {
+    let host = if settings.token.is_some() { "0.0.0.0" } else { "127.0.0.1" };
+    let listener = match TcpListener::bind((host, settings.port)).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("[API] Failed to listen on port {}: {}", settings.port, e);
+            return;
+        },
+    };
+    info!("[API] Listening on http://{}:{}", host, settings.port);
+
+    let connections = Arc::new(Semaphore::new(MAX_CONNECTIONS));
+    loop {
+        let Ok((mut socket, addr)) = listener.accept().await else { continue; };
+        let Ok(permit) = Arc::clone(&connections).try_acquire_owned() else {
+            debug!("[API] Too many connections, turning away {:?}", addr);
+            let _ = write_status(&mut socket, "503 Service Unavailable").await;
+            continue;
+        };
+        let tx = tx.clone();
+        let token = settings.token.clone();
+        tokio::spawn(async move {
+            let _permit = permit;
+            if let Err(e) = handle_request(socket, token.as_deref(), &tx).await {
+                debug!("[API] Failed to handle request from {:?}: {:?}", addr, e);
+            }
+        });
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+async fn read_request(socket: &mut TcpStream) -> anyhow::Result<Request> {
+    let head = read_request_head(socket, MAX_REQUEST_SIZE).await?;
+    let mut request_line = head.request_line.split(' ');
+    let method = request_line.next().ok_or(HttpError::BadRequest)?.to_string();
+    let path = request_line.next().ok_or(HttpError::BadRequest)?.to_string();
+    let content_length: usize = match head.header("content-length") {
+        Some(value) => value.parse().map_err(|_| HttpError::BadRequest)?,
+        None => 0,
+    };
+    if content_length > MAX_REQUEST_SIZE {
+        return Err(HttpError::BadRequest.into());
+    }
+
+    let authorization = head.header("authorization").map(String::from);
+    let mut body = head.rest;
+    let mut buf = [0u8; 1024];
+    tokio::time::timeout(BODY_TIMEOUT, async {
+        while body.len() < content_length {
+            let n = socket.read(&mut buf).await?;
+            if n == 0 {
+                return Err(anyhow::Error::from(HttpError::BadRequest));
+            }
+            body.extend_from_slice(&buf[..n]);
+        }
+        Ok(())
+    }).await.map_err(|_| HttpError::BadRequest)??;
+    body.truncate(content_length);
+
+    Ok(Request { method, path, authorization, body })
+}
+
+/// Compares two byte strings in constant time, so the time it takes doesn't tell how much of a
+/// guessed token was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+async fn handle_request(mut socket: TcpStream, token: Option<&str>, tx: &mpsc::Sender<ApiRequest>) -> anyhow::Result<()> {
+    let request = match read_request(&mut socket).await {
+        Ok(request) => request,
+        Err(_) => return write_status(&mut socket, "400 Bad Request").await,
+    };
+    trace!("[API] {} {}", request.method, request.path);
+
+    if let Some(token) = token {
+        let expected = format!("Bearer {}", token);
+        if !constant_time_eq(request.authorization.unwrap_or_default().as_bytes(), expected.as_bytes()) {
+            return write_status(&mut socket, "401 Unauthorized").await;
+        }
+    }
+
+    let action = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/players") => ApiAction::Players,
+        ("GET", "/state") => ApiAction::State,
+        ("POST", "/kick") => match serde_json::from_slice::<KickBody>(&request.body) {
+            Ok(body) => ApiAction::Kick { player: player_arg(&body.player), reason: body.reason },
+            Err(_) => return write_status(&mut socket, "400 Bad Request").await,
+        },
+        ("POST", "/chat") => match serde_json::from_slice::<ChatBody>(&request.body) {
+            Ok(body) => ApiAction::Chat { message: body.message, player: body.player.as_ref().map(player_arg) },
+            Err(_) => return write_status(&mut socket, "400 Bad Request").await,
+        },
+        (_, "/players" | "/state" | "/kick" | "/chat") => return write_status(&mut socket, "405 Method Not Allowed").await,
+        _ => return write_status(&mut socket, "404 Not Found").await,
+    };
+
+    let (response_tx, response_rx) = oneshot::channel();
+    tx.send(ApiRequest { action, response: response_tx }).await?;
+    match response_rx.await? {
+        Some(body) => write_body(&mut socket, "application/json", &body).await,
+        None => write_status(&mut socket, "404 Not Found").await,
+    }?;
+    socket.shutdown().await?;
+    Ok(())
+}
+
+impl Server {
+    /// Answers the requests that came in through the REST API since the last tick.
+    pub(super) async fn process_api_requests(&mut self) {
+        while let Ok(request) = self.api_rx.try_recv() {
+            debug!("[API] {:?}", request.action);
+            let response = match request.action {
+                ApiAction::Players => serde_json::to_string(&self.snapshot().players).ok(),
+                ApiAction::State => serde_json::to_string(&StateResponse {
+                    snapshot: self.snapshot(),
+                    heartbeat: HeartbeatJson::new(&self.heartbeat.borrow()),
+                }).ok(),
+                ApiAction::Kick { player, reason } => {
+                    let reason = reason.unwrap_or_else(|| String::from("You have been kicked from the server!"));
+                    match self.find_client(&player) {
+                        Some(index) => {
+                            self.kick_player(index, reason).await;
+                            Some(String::from("{}"))
+                        },
+                        None => None,
+                    }
+                },
+                ApiAction::Chat { message, player: None } => {
+                    self.send_chat_message(&message, None).await;
+                    Some(String::from("{}"))
+                },
+                ApiAction::Chat { message, player: Some(player) } => {
+                    match self.find_client(&player).map(|i| self.clients[i].id) {
+                        Some(id) => {
+                            self.send_chat_message(&message, Some(id)).await;
+                            Some(String::from("{}"))
+                        },
+                        None => None,
+                    }
+                },
+            };
+            let _ = request.response.send(response);
+        }
+    }
+}
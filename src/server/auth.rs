@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use crate::config::{AuthProviderKind, Config, HttpSettings};
+use super::backend::*;
+use super::client::UserData;
+use super::error::ClientError;
+
+/// Turns the public key a client sends during authentication into their user data.
+#[async_trait]
+pub trait AuthProvider: Send + Sync {
+    async fn authenticate(&self, key: &str) -> anyhow::Result<UserData>;
+}
+
+/// Creates the auth provider selected in the config.
+pub fn create_auth_provider(config: &Config) -> anyhow::Result<Box<dyn AuthProvider>> {
+    let client = http_client_builder(&config.http)?.build()?;
+    Ok(match config.auth.provider {
+        AuthProviderKind::BeamMP => Box::new(BeamMPAuth::new(client, config.http.clone(), Duration::from_secs(config.auth.cache_seconds))),
+        AuthProviderKind::KeyFile => {
+            let path = config.auth.key_file.clone().ok_or(AuthError::MissingSetting("KeyFile"))?;
+            Box::new(KeyFileAuth { path: PathBuf::from(path) })
+        },
+        AuthProviderKind::HttpHook => {
+            let url = config.auth.hook_url.clone().ok_or(AuthError::MissingSetting("HookUrl"))?;
+            Box::new(HttpHookAuth { client, http_settings: config.http.clone(), url })
+        },
+    })
+}
+
+/// Authenticates players through the official BeamMP backend.
+pub struct BeamMPAuth {
+    client: reqwest::Client,
+    http_settings: HttpSettings,
+    cache_duration: Duration,
+    /// Recent successful authentications, keyed by the player's public key.
+    cache: Mutex<HashMap<String, (Instant, UserData)>>,
+}
+
+impl BeamMPAuth {
+    pub fn new(client: reqwest::Client, http_settings: HttpSettings, cache_duration: Duration) -> Self {
+        Self {
+            client,
+            http_settings,
+            cache_duration,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl AuthProvider for BeamMPAuth {
+    /// Uses the cache if possible. When the backend is unreachable, the request gets
+    /// retried as configured in the HTTP settings.
+    async fn authenticate(&self, key: &str) -> anyhow::Result<UserData> {
+        {
+            let mut lock = self.cache.lock().await;
+            lock.retain(|_, (time, _)| time.elapsed() < self.cache_duration);
+            if let Some((_, user_data)) = lock.get(key) {
+                debug!("[AUTH] Using cached user data for {}", user_data.username);
+                return Ok(user_data.clone());
+            }
+        }
+
+        let mut json = HashMap::new();
+        json.insert("key".to_string(), key.to_string());
+        let user_data: UserData = match authentication_request(&self.client, &self.http_settings, "pkToUser", json).await {
+            Ok(user_data) => user_data,
+            Err(e) if is_backend_unreachable(&e) => {
+                error!("[AUTH] Backend unreachable: {:?}", e);
+                return Err(ClientError::AuthBackendUnreachable.into());
+            },
+            Err(e) => {
+                error!("[AUTH] {:?}", e);
+                return Err(e);
+            },
+        };
+
+        if !self.cache_duration.is_zero() {
+            self.cache.lock().await.insert(key.to_string(), (Instant::now(), user_data.clone()));
+        }
+        Ok(user_data)
+    }
+}
+
+#[derive(Deserialize)]
+struct KeyFileEntry {
+    username: String,
+    /// Defaults to the username
+    uid: Option<String>,
+    #[serde(default = "default_key_file_roles")]
+    roles: String,
+}
+
+fn default_key_file_roles() -> String {
+    String::from("USER")
+}
+
+/// Authenticates players using a static JSON file mapping public keys to players, for private
+/// events that shouldn't depend on the BeamMP backend. The file is read on every join,
+/// so it can be edited while the server is running.
+///
+/// Format: `{ "<key>": { "username": "Luuk", "uid": "1234", "roles": "USER" } }`
+pub struct KeyFileAuth {
+    path: PathBuf,
+}
+
+#[async_trait]
+impl AuthProvider for KeyFileAuth {
+    async fn authenticate(&self, key: &str) -> anyhow::Result<UserData> {
+        let data = tokio::fs::read_to_string(&self.path).await?;
+        let mut entries: HashMap<String, KeyFileEntry> = serde_json::from_str(&data)?;
+        let entry = entries.remove(key).ok_or(AuthError::UnknownKey)?;
+        Ok(UserData {
+            uid: entry.uid.unwrap_or_else(|| entry.username.clone()),
+            created_at: String::new(),
+            guest: false,
+            roles: entry.roles,
+            username: entry.username,
+            identifiers: Vec::new(),
+            avatar: None,
+        })
+    }
+}
+
+/// Authenticates players by posting `{"key": "<key>"}` to an external URL, which has to
+/// respond with the same user data format as the BeamMP backend.
+pub struct HttpHookAuth {
+    client: reqwest::Client,
+    http_settings: HttpSettings,
+    url: String,
+}
+
+#[async_trait]
+impl AuthProvider for HttpHookAuth {
+    async fn authenticate(&self, key: &str) -> anyhow::Result<UserData> {
+        let mut json = HashMap::new();
+        json.insert("key".to_string(), key.to_string());
+        with_retries(&self.http_settings, || post_json(&self.client, &self.url, &json)).await
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("The selected auth provider requires Auth.{0} to be set")]
+    MissingSetting(&'static str),
+    #[error("Unknown player key")]
+    UnknownKey,
+}
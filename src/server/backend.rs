@@ -1,19 +1,90 @@
 use serde::de::DeserializeOwned;
 use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
 
-static BACKEND_URL: &'static str = "backend.beammp.com";
-static AUTH_URL: &'static str = "auth.beammp.com";
+use crate::config::HttpSettings;
+
+pub static BACKEND_URL: &str = "backend.beammp.com";
+static AUTH_URL: &str = "auth.beammp.com";
+
+/// Returns a client builder with the timeout and proxy from the config applied.
+/// All outbound HTTP requests should use a client made from this.
+pub fn http_client_builder(settings: &HttpSettings) -> anyhow::Result<reqwest::ClientBuilder> {
+    let mut builder = reqwest::Client::builder()
+        .timeout(Duration::from_secs(settings.timeout_seconds))
+        .connect_timeout(Duration::from_secs(settings.timeout_seconds));
+    if let Some(proxy) = &settings.proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+    Ok(builder)
+}
 
 pub async fn authentication_request<R: DeserializeOwned>(
+    client: &reqwest::Client,
+    settings: &HttpSettings,
     target: &str,
     map: HashMap<String, String>,
 ) -> anyhow::Result<R> {
-    let client = reqwest::Client::new();
+    let url = format!("https://{}/{}", AUTH_URL, target);
+    with_retries(settings, || post_json(client, &url, &map)).await
+}
+
+/// Posts the map as JSON to the given url, and parses the response as JSON.
+pub async fn post_json<R: DeserializeOwned>(
+    client: &reqwest::Client,
+    url: &str,
+    map: &HashMap<String, String>,
+) -> anyhow::Result<R> {
     let resp = client
-        .post(format!("https://{}/{}", AUTH_URL, target))
-        .json(&map)
+        .post(url)
+        .json(map)
         .send()
-        .await?;
+        .await?
+        .error_for_status()?;
     // panic!("json: {:?}", resp.text().await);
     Ok(resp.json().await?)
 }
+
+/// Runs the request, retrying it with a jittered exponential backoff for as long as it fails
+/// because the backend is unreachable and there are retries left.
+pub async fn with_retries<R, F, Fut>(settings: &HttpSettings, mut request: F) -> anyhow::Result<R>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<R>>,
+{
+    let mut attempt = 0;
+    loop {
+        match request().await {
+            Err(e) if is_backend_unreachable(&e) && attempt < settings.retries => {
+                attempt += 1;
+                let delay = retry_delay(settings, attempt);
+                warn!("Backend unreachable, retrying in {} ms ({}/{})...", delay.as_millis(), attempt, settings.retries);
+                tokio::time::sleep(delay).await;
+            },
+            result => return result,
+        }
+    }
+}
+
+/// Delay before the given retry attempt (starting at 1). Doubles every attempt, with up to
+/// 50% random jitter added so clients don't all retry at the same moment.
+fn retry_delay(settings: &HttpSettings, attempt: u32) -> Duration {
+    let base = settings.retry_delay_ms.saturating_mul(1 << (attempt - 1).min(16));
+    // Good enough randomness for jitter, no need to pull in a crate for it
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u64)
+        .unwrap_or(0);
+    let jitter = if base > 0 { nanos % (base / 2 + 1) } else { 0 };
+    Duration::from_millis(base + jitter)
+}
+
+/// Returns true if the error means the backend couldn't be reached (or is having issues),
+/// rather than it refusing the request. These errors are worth retrying.
+pub fn is_backend_unreachable(error: &anyhow::Error) -> bool {
+    match error.downcast_ref::<reqwest::Error>() {
+        Some(e) => e.is_connect() || e.is_timeout() || e.status().map(|s| s.is_server_error()).unwrap_or(false),
+        None => false,
+    }
+}
@@ -0,0 +1,116 @@
+use std::fs::File;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::config::CaptureSettings;
+use super::packet::decompress_packet_data;
+
+lazy_static! {
+    static ref CAPTURE: Mutex<Option<Capture>> = Mutex::new(None);
+}
+
+/// Checked before taking the lock, so packets cost next to nothing while not capturing.
+static CAPTURE_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+struct Capture {
+    file: File,
+    path: String,
+    until: Option<Instant>,
+}
+
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureDirection {
+    In,
+    Out,
+}
+
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureTransport {
+    Tcp,
+    Udp,
+}
+
+#[derive(Serialize)]
+struct CaptureEntry<'a> {
+    /// Unix time in milliseconds
+    time: f64,
+    direction: CaptureDirection,
+    transport: CaptureTransport,
+    client: Option<u8>,
+    addr: Option<SocketAddr>,
+    /// Size on the wire, without the TCP size header
+    size: usize,
+    compressed: bool,
+    /// Decompressed packet data (invalid UTF-8 gets replaced)
+    data: &'a str,
+}
+
+/// Starts capturing packets if enabled in the config. Captures are appended to the file,
+/// so several runs can be compared.
+pub fn start_capture(settings: &CaptureSettings) -> anyhow::Result<()> {
+    if !settings.enabled {
+        return Ok(());
+    }
+    let path = crate::output::event_path(&settings.path);
+    let file = File::options().create(true).append(true).open(&path)?;
+    let until = (settings.duration_seconds > 0).then(|| Instant::now() + Duration::from_secs(settings.duration_seconds));
+    match until {
+        Some(_) => warn!("Capturing all packets to {} for {} seconds. This includes player keys!", path.display(), settings.duration_seconds),
+        None => warn!("Capturing all packets to {}. This includes player keys!", path.display()),
+    }
+    *CAPTURE.lock().unwrap() = Some(Capture { file, path: path.display().to_string(), until });
+    CAPTURE_ACTIVE.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Records a packet, if a capture is running. `data` is the packet as it went over the wire,
+/// without the TCP size header. Compressed packets are stored decompressed.
+pub fn capture_packet(direction: CaptureDirection, transport: CaptureTransport, client: Option<u8>, addr: Option<SocketAddr>, data: &[u8]) {
+    if !CAPTURE_ACTIVE.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut lock = CAPTURE.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(capture) = lock.as_mut() else {
+        return;
+    };
+    if capture.until.map(|until| Instant::now() >= until).unwrap_or(false) {
+        info!("Packet capture finished, saved to {}", capture.path);
+        CAPTURE_ACTIVE.store(false, Ordering::Relaxed);
+        *lock = None;
+        return;
+    }
+
+    let decompressed = decompress_packet_data(data).ok().flatten();
+    let entry = CaptureEntry {
+        time: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64() * 1000.0,
+        direction,
+        transport,
+        client,
+        addr,
+        size: data.len(),
+        compressed: decompressed.is_some(),
+        data: &String::from_utf8_lossy(decompressed.as_deref().unwrap_or(data)),
+    };
+    let result = serde_json::to_string(&entry)
+        .map_err(anyhow::Error::from)
+        .and_then(|line| Ok(writeln!(capture.file, "{}", line)?));
+    if let Err(e) = result {
+        error!("Failed to write to the packet capture, stopping it: {:?}", e);
+        CAPTURE_ACTIVE.store(false, Ordering::Relaxed);
+        *lock = None;
+    }
+}
+
+/// Like `capture_packet`, for packets encoded with `encode_tcp_packet` (including the size header).
+pub fn capture_encoded_tcp(client: Option<u8>, addr: Option<SocketAddr>, encoded: &[u8]) {
+    if CAPTURE_ACTIVE.load(Ordering::Relaxed) {
+        capture_packet(CaptureDirection::Out, CaptureTransport::Tcp, client, addr, encoded.get(4..).unwrap_or_default());
+    }
+}
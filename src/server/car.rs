@@ -1,10 +1,34 @@
 use glam::*;
 
+use std::collections::VecDeque;
 use std::time::Instant;
 
+/// How much history (in client time) is kept per car.
+const HISTORY_SECONDS: f64 = 2.0;
+
+const GRAVITY: f64 = 9.81;
+
+/// Weight of a new sample in the smoothed acceleration. Position updates are jittery,
+/// so raw acceleration between two samples is too noisy to show.
+const ACCELERATION_SMOOTHING: f64 = 0.3;
+
+/// The state of a car at a specific point in client time (the `tim` of a position packet).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CarSnapshot {
+    pub tim: f64,
+    pub pos: DVec3,
+    pub rot: DQuat,
+    pub vel: DVec3,
+    pub rvel: DVec3,
+}
+
 #[derive(Default, Clone, Debug)]
 pub struct Car {
     pub car_json: String,
+    /// AI traffic car, see `is_traffic`.
+    pub traffic: bool,
+    /// Trailer or prop, see `PropSettings`.
+    pub prop: bool,
 
     pub pos: DVec3,
     pub rot: DQuat,
@@ -13,17 +37,108 @@ pub struct Car {
     pub tim: f64,
     pub ping: f64,
     pub last_pos_update: Option<Instant>,
+    /// Recent states, oldest first, so checks can be done at the time a packet was sent
+    /// instead of the time it arrived.
+    pub history: VecDeque<CarSnapshot>,
+
+    /// Smoothed acceleration, in m/s^2.
+    pub acceleration: DVec3,
+
+    pub impact_energy: f64,
+    pub triggered_damage_events: Vec<String>,
 }
 
 impl Car {
     pub fn new(car_json: String) -> Self {
         Self {
+            traffic: super::is_traffic(&car_json),
             car_json: car_json,
 
             ..Default::default()
         }
     }
 
+    /// Updates the velocity of the car, returning the impact energy (per unit of mass) if the
+    /// change in velocity since the last update was sudden enough to count as an impact.
+    pub fn update_velocity(&mut self, vel: DVec3, tim: f64, min_impact_acceleration: f64) -> Option<f64> {
+        let dt = tim - self.tim;
+        let mut impact = None;
+        if self.last_pos_update.is_some() && dt > 0.0 {
+            let acceleration = (vel - self.vel) / dt;
+            self.acceleration = self.acceleration.lerp(acceleration, ACCELERATION_SMOOTHING);
+            let dv = (vel - self.vel).length();
+            if dv / dt >= min_impact_acceleration {
+                let energy = 0.5 * dv * dv;
+                self.impact_energy += energy;
+                impact = Some(energy);
+            }
+        }
+        self.vel = vel;
+        self.tim = tim;
+        impact
+    }
+
+    /// Forgets the impact energy and triggered damage events, as resetting repairs the car.
+    pub fn reset_damage(&mut self) {
+        self.impact_energy = 0.0;
+        self.triggered_damage_events.clear();
+    }
+
+    /// Stores the current state in the history. Expects the position, rotation and velocity
+    /// to already be updated for the current `tim`.
+    pub fn record_history(&mut self) {
+        // Client time going backwards means the client restarted its timer (e.g. after a reload),
+        // so older entries can't be compared anymore.
+        if self.history.back().map(|s| s.tim > self.tim).unwrap_or(false) {
+            self.history.clear();
+        }
+        self.history.push_back(CarSnapshot {
+            tim: self.tim,
+            pos: self.pos,
+            rot: self.rot,
+            vel: self.vel,
+            rvel: self.rvel,
+        });
+        while self.history.front().map(|s| self.tim - s.tim > HISTORY_SECONDS).unwrap_or(false) {
+            self.history.pop_front();
+        }
+    }
+
+    /// Returns the state of the car at the given client time, interpolating between the
+    /// surrounding history entries. Times outside the history are clamped to the oldest/newest entry.
+    pub fn state_at(&self, tim: f64) -> Option<CarSnapshot> {
+        let newest = *self.history.back()?;
+        if tim >= newest.tim {
+            return Some(newest);
+        }
+        let next_idx = self.history.iter().position(|s| s.tim >= tim)?;
+        if next_idx == 0 {
+            return self.history.front().copied();
+        }
+        let a = self.history[next_idx - 1];
+        let b = self.history[next_idx];
+        let t = (tim - a.tim) / (b.tim - a.tim);
+        Some(CarSnapshot {
+            tim,
+            pos: a.pos.lerp(b.pos, t),
+            rot: a.rot.slerp(b.rot, t),
+            vel: a.vel.lerp(b.vel, t),
+            rvel: a.rvel.lerp(b.rvel, t),
+        })
+    }
+
+    pub fn speed_kmh(&self) -> f64 {
+        self.vel.length() * 3.6
+    }
+
+    /// Returns the longitudinal (positive when accelerating) and lateral (positive when
+    /// turning left) acceleration, in g. Based on the direction the car is moving in.
+    pub fn g_forces(&self) -> (f64, f64) {
+        let forward = self.vel.normalize_or_zero();
+        let left = DVec3::Z.cross(forward).normalize_or_zero();
+        (self.acceleration.dot(forward) / GRAVITY, self.acceleration.dot(left) / GRAVITY)
+    }
+
     pub fn raw_position(&self) -> DVec3 {
         self.pos
     }
@@ -40,4 +155,16 @@ impl Car {
         let t = self.last_pos_update.map(|t| t.elapsed().as_secs_f64()).unwrap_or(0.0);
         self.rot + DQuat::from_euler(glam::EulerRot::YXZ, self.rvel.x * t, self.rvel.y * t, self.rvel.z * t)
     }
+
+    /// The last reported state, as a position packet.
+    pub fn transform(&self) -> super::TransformPacket {
+        super::TransformPacket {
+            rvel: self.rvel.to_array(),
+            tim: self.tim,
+            pos: self.pos.to_array(),
+            ping: self.ping,
+            rot: self.rot.to_array(),
+            vel: self.vel.to_array(),
+        }
+    }
 }
@@ -0,0 +1,42 @@
+use super::Server;
+
+/// Returns the model of a car config, like `pickup`.
+pub fn car_model(car_json: &str) -> Option<String> {
+    let car: serde_json::Value = serde_json::from_str(car_json).ok()?;
+    car.get("jbm")
+        .or_else(|| car.pointer("/vcf/model"))
+        .and_then(|model| model.as_str())
+        .map(|model| model.to_string())
+}
+
+impl Server {
+    /// Checks the class limits for a car the client wants to spawn, or for an edit of the car
+    /// `replacing`, which then doesn't count itself. Returns why the car isn't allowed if a
+    /// limit is reached.
+    pub(super) fn car_class_limit(&self, client_idx: usize, car_json: &str, replacing: Option<u8>) -> Option<String> {
+        let model = car_model(car_json)?;
+        let (name, class) = self.config.car_class(&model)?;
+        let pid = self.clients[client_idx].id;
+        let in_class = |owner: u8, vid: u8, car_json: &str| {
+            let replaced = owner == pid && Some(vid) == replacing;
+            !replaced && car_model(car_json).map(|model| class.models.contains(&model)).unwrap_or(false)
+        };
+
+        if let Some(max_per_player) = class.max_per_player {
+            let count = self.clients[client_idx].cars.iter().filter(|(vid, car)| in_class(pid, *vid, &car.car_json)).count();
+            if count >= max_per_player {
+                return Some(format!("You can't spawn more than {} car(s) of class '{}'.", max_per_player, name));
+            }
+        }
+        if let Some(max) = class.max {
+            let count = self.clients.iter()
+                .flat_map(|client| client.cars.iter().map(move |(vid, car)| (client.id, *vid, car)))
+                .filter(|(owner, vid, car)| in_class(*owner, *vid, &car.car_json))
+                .count();
+            if count >= max {
+                return Some(format!("There can't be more than {} car(s) of class '{}' on the server.", max, name));
+            }
+        }
+        None
+    }
+}
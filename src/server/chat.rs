@@ -0,0 +1,32 @@
+/// Cleans up a chat message sent by a client. Control characters are removed, any runs of
+/// whitespace are collapsed into a single space and the message is cut off at `max_length` characters.
+pub fn sanitize_message(message: &str, max_length: usize) -> String {
+    let mut sanitized = String::with_capacity(message.len());
+    let mut last_was_space = true; // Also trims leading whitespace
+    for c in message.chars() {
+        if c.is_whitespace() {
+            if !last_was_space {
+                sanitized.push(' ');
+                last_was_space = true;
+            }
+        } else if !c.is_control() {
+            sanitized.push(c);
+            last_was_space = false;
+        }
+    }
+    let trimmed = sanitized.trim_end();
+    trimmed.chars().take(max_length).collect()
+}
+
+/// Logs every chat message that gets sent, for `LogChat`.
+pub async fn log_chat(mut events: tokio::sync::broadcast::Receiver<super::ServerEvent>) {
+    use tokio::sync::broadcast::error::RecvError;
+    loop {
+        match events.recv().await {
+            Ok(super::ServerEvent::ChatReceived { name, message, .. }) => info!("[CHAT] {}: {}", name, message),
+            Ok(_) => {},
+            Err(RecvError::Lagged(missed)) => warn!("[CHAT] Chat log fell behind, {} events were skipped", missed),
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
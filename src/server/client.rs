@@ -1,24 +1,44 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::net::SocketAddr;
 use std::ops::DerefMut;
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{
     tcp::{OwnedReadHalf, OwnedWriteHalf},
     TcpStream,
 };
-use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::mpsc::Sender;
 use tokio::sync::Mutex;
 use tokio::task::JoinHandle;
 
+use bytes::Bytes;
 use serde::Deserialize;
 use crate::fs_util;
 
-use super::backend::*;
+use super::auth::AuthProvider;
+use super::capture::*;
 use super::car::*;
+use super::chat::sanitize_message;
+use super::error::*;
+use super::shaping::*;
 use super::packet::*;
+use super::plugins::PlayerIdentifiers;
+use super::TcpRead;
+
+/// Display names from the config are cut off after this many characters.
+const MAX_DISPLAY_NAME_LENGTH: usize = 32;
+
+/// The largest packet a client may send. The size comes from the client, so without a limit
+/// anyone could make us allocate up to 4 GiB for a single packet. Vehicle configs, the largest
+/// packets clients send, stay well below it.
+const MAX_PACKET_SIZE: usize = 10 * 1024 * 1024;
+
+/// The session of the next connection, see `Client::session`.
+static NEXT_SESSION: AtomicU64 = AtomicU64::new(0);
 
 lazy_static! {
     pub static ref TAKEN_PLAYER_IDS: Mutex<Vec<u8>> = Mutex::new(Vec::new());
@@ -43,6 +63,13 @@ async fn free_id(id: u8) {
     *lock = lock.drain(..).filter(|i| *i != id).collect::<Vec<u8>>();
 }
 
+pub enum ChatPermission {
+    Allowed,
+    Cooldown,
+    /// Contains the remaining mute duration
+    Muted(Duration),
+}
+
 #[derive(PartialEq)]
 pub enum ClientState {
     None,
@@ -52,109 +79,183 @@ pub enum ClientState {
     Disconnect,
 }
 
+/// Identity of a player as returned by the BeamMP auth backend.
 #[derive(Deserialize, Debug, PartialEq, Clone)]
 pub struct UserData {
+    /// BeamMP user ID. This is what should be used to identify a player, as usernames can change.
     pub uid: String,
-    pub createdAt: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
     pub guest: bool,
     pub roles: String,
     pub username: String,
+    /// Identifiers of linked accounts, in the form of `kind:id` (e.g. `discord:1234`).
+    #[serde(default)]
+    pub identifiers: Vec<String>,
+    /// URL of the player's avatar/profile picture, if the backend sent one.
+    #[serde(default, alias = "avatar_url")]
+    pub avatar: Option<String>,
+}
+
+impl UserData {
+    /// Returns the linked identifier of the given kind, e.g. `discord`.
+    pub fn get_identifier(&self, kind: &str) -> Option<&str> {
+        self.identifiers.iter().find_map(|identifier| {
+            identifier.strip_prefix(kind).and_then(|rest| rest.strip_prefix(':'))
+        })
+    }
 }
 
 pub struct Client {
     pub id: u8,
+    /// Unique for every connection, unlike `id` which gets reused once the client leaves.
+    /// Tags the packets of the reader task, so late ones can't end up at a new client.
+    pub session: u64,
     pub udp_addr: Option<SocketAddr>,
-
-    socket: OwnedReadHalf,
+    pub tcp_addr: Option<SocketAddr>,
+    /// Where the player connects from, if GeoIP is enabled.
+    pub region: Option<String>,
+
+    /// Used directly while joining. Moves into the reader task once the client joined,
+    /// see `start_reading`.
+    socket: Option<OwnedReadHalf>,
+    read_runtime: Option<JoinHandle<()>>,
     write_half: Arc<Mutex<OwnedWriteHalf>>,
     write_runtime: JoinHandle<()>,
-    write_runtime_sender: Sender<Packet>,
+    outbound: Arc<OutboundQueue>,
 
     pub state: ClientState,
     pub info: Option<UserData>,
+    pub role: Option<crate::config::RoleSettings>,
+    pub driver: Option<crate::config::DriverSettings>,
     pub cars: Vec<(u8, Car)>,
+    pub connection: super::ConnectionQuality,
+
+    reset_times: VecDeque<Instant>,
+    chat_times: VecDeque<Instant>,
+    muted_until: Option<Instant>,
+    /// When the client last sent something, over TCP or UDP.
+    last_received: Instant,
 }
 
 impl Drop for Client {
     fn drop(&mut self) {
         tokio::spawn(free_id(self.id));
         self.write_runtime.abort();
+        if let Some(read_runtime) = &self.read_runtime {
+            read_runtime.abort();
+        }
     }
 }
 
 impl Client {
-    pub async fn new(socket: TcpStream) -> Self {
+    /// `outbound_limit` is the most bytes per second that are sent to the client, see
+    /// `GeneralSettings::outbound_limit`.
+    pub async fn new(socket: TcpStream, outbound_limit: Option<usize>) -> Self {
         let id = match claim_id().await {
             Ok(v) => v,
             Err(_) => panic!("CRITICAL: Saturated Player ID's"),
         };
         trace!("Client with ID #{} created!", id);
 
+        let tcp_addr = socket.peer_addr().ok();
         let (read_half, write_half) = socket.into_split();
-        let (tx, mut rx): (Sender<Packet>, Receiver<Packet>) = tokio::sync::mpsc::channel(128);
+        let outbound = Arc::new(OutboundQueue::new(outbound_limit));
+        let outbound_ref = Arc::clone(&outbound);
         let write_half = Arc::new(Mutex::new(write_half));
         let write_half_ref = Arc::clone(&write_half);
         let handle: JoinHandle<()> = tokio::spawn(async move {
+            let mut budget: Option<(usize, OutboundBudget)> = None;
             loop {
-                if let Some(packet) = rx.recv().await {
-                    // trace!("Runtime received packet...");
-                    let mut lock = write_half_ref.lock().await;
-                    // trace!("Runtime sending packet!");
-                    if let Err(e) = tcp_write(lock.deref_mut(), packet).await {
-                        error!("{:?}", e);
-                    };
-                    // trace!("Runtime sent packet!");
-                    drop(lock);
+                // The most important packet that's waiting goes first
+                let (priority, data, limit) = outbound_ref.pop().await;
+                // The limit can change while the server is running, see `Client::set_outbound_limit`
+                if budget.as_ref().map(|(bytes, _)| *bytes) != limit {
+                    budget = limit.map(|bytes| (bytes, OutboundBudget::new(bytes)));
                 }
+                if let Some((_, budget)) = &mut budget {
+                    if !budget.try_take(data.len()) {
+                        if priority == PacketPriority::Position {
+                            trace!("Client {} is over its outbound limit, dropping a position update", id);
+                            continue;
+                        }
+                        budget.take(data.len()).await;
+                    }
+                }
+                // trace!("Runtime received packet...");
+                capture_encoded_tcp(Some(id), tcp_addr, &data);
+                let mut lock = write_half_ref.lock().await;
+                // trace!("Runtime sending packet!");
+                if let Err(e) = tcp_write_encoded(lock.deref_mut(), &data).await {
+                    error!("{:?}", e);
+                };
+                // trace!("Runtime sent packet!");
+                drop(lock);
             }
         });
 
         Self {
             id: id,
+            session: NEXT_SESSION.fetch_add(1, Ordering::Relaxed),
             udp_addr: None,
+            tcp_addr,
+            region: None,
 
-            socket: read_half,
+            socket: Some(read_half),
+            read_runtime: None,
             write_half: write_half,
             write_runtime: handle,
-            write_runtime_sender: tx,
+            outbound,
 
             state: ClientState::Connecting,
             info: None,
+            role: None,
+            driver: None,
             cars: Vec::new(),
+            connection: Default::default(),
+
+            reset_times: VecDeque::new(),
+            chat_times: VecDeque::new(),
+            muted_until: None,
+            last_received: Instant::now(),
         }
     }
 
-    pub async fn authenticate(&mut self, config: &super::Config) -> anyhow::Result<bool> {
+    pub async fn authenticate(&mut self, config: &super::Config, auth_provider: &dyn AuthProvider, bans: &std::sync::RwLock<super::BanList>) -> anyhow::Result<bool> {
         debug!("Authenticating client {}...", self.id);
 
         // TODO: Check client version
         trace!("Client version packet");
-        self.socket.readable().await?;
+        self.socket()?.readable().await?;
         let packet = self.read_packet_waiting().await?;
         debug!("{:?}", packet);
 
         self.write_packet(Packet::Raw(RawPacket::from_code('S')))
             .await?;
 
-        self.socket.readable().await?;
+        self.socket()?.readable().await?;
         if let Some(packet) = self.read_packet_waiting().await? {
             debug!("packet: {:?}", packet);
             if packet.data.len() > 50 {
                 self.kick("Player key too big!").await;
                 return Err(ClientError::AuthenticateError.into());
             }
-            let mut json = HashMap::new();
             let key = packet.data_as_string();
             debug!("[AUTH] key: {}", key);
-            json.insert("key".to_string(), key);
-            let user_data: UserData =
-                authentication_request("pkToUser", json)
-                    .await
-                    .map_err(|e| {
-                        error!("[AUTH] {:?}", e);
-                        e
-                    })?;
+            let user_data = match crate::bots::bot_user_data(&key) {
+                Some(user_data) => user_data,
+                None => auth_provider.authenticate(&key).await?,
+            };
             debug!("user_data: {:?}", user_data);
+            if let Some(reason) = super::ban_reason(bans, &user_data.uid, &user_data.username) {
+                info!("{} is banned, turning them away", user_data.username);
+                return Err(ClientError::Banned { reason }.into());
+            }
+            if let Some((role_name, role)) = config.resolve_role(&user_data.uid, &user_data.roles) {
+                debug!("{} has role {}", user_data.username, role_name);
+                self.role = Some(role.clone());
+            }
+            self.driver = config.drivers.get(&user_data.uid).cloned();
             self.info = Some(user_data);
 
             // self.write_packet(Packet::Raw(RawPacket::from_code('S')))
@@ -183,7 +284,7 @@ impl Client {
     // TODO: https://github.com/BeamMP/BeamMP-Server/blob/master/src/TNetwork.cpp#L619
     pub async fn sync(&mut self, config: &super::Config) -> anyhow::Result<()> {
         'syncing: while self.state == ClientState::SyncingResources {
-            self.socket.readable().await?;
+            self.socket()?.readable().await?;
             if let Some(packet) = self.read_packet().await? {
                 if (packet.data.len() == 4 && packet.data == [68, 111, 110, 101]) || packet.data.len() == 0 {
                     {
@@ -195,20 +296,17 @@ impl Client {
                 match packet.data[0] as char {
                     'S' if packet.data.len() > 1 => match packet.data[1] as char {
                         'R' => {
-                            let file_packet = if config.mods.len() == 0 {
+                            let mods = crate::mods::get_required_mods();
+                            let file_packet = if mods.is_empty() {
                                 RawPacket::from_code('-')
                             } else {
                                 let mut file_data = String::new();
                                 // TODO: Collapse these 2 loops into 1
-                                for (name, _size) in &config.mods {
-                                    let mut mod_name = name.clone();
-                                    if mod_name.starts_with("/") == false {
-                                        mod_name = format!("/{mod_name}");
-                                    }
-                                    file_data.push_str(&format!("{mod_name};"));
+                                for bmod in &mods {
+                                    file_data.push_str(&format!("{};", bmod.name));
                                 }
-                                for (_name, size) in &config.mods {
-                                    file_data.push_str(&format!("{size};"));
+                                for bmod in &mods {
+                                    file_data.push_str(&format!("{};", bmod.size));
                                 }
                                 RawPacket::from_str(&file_data)
                             };
@@ -236,8 +334,8 @@ impl Client {
                         self.write_packet(Packet::Raw(RawPacket::from_str("AG"))).await?;
 
                         let mut mod_id = 0;
-                        for (i, (bmod_name, _bmod_size)) in config.mods.iter().enumerate() {
-                            if bmod_name == &mod_name {
+                        for (i, bmod) in crate::mods::get_mods().iter().enumerate() {
+                            if bmod.name == mod_name {
                                 mod_id = i;
                             }
                         }
@@ -275,21 +373,44 @@ impl Client {
         Ok(())
     }
 
-    /// This function should never block. It should simply check if there's a
-    /// packet, and then and only then should it read it. If this were to block, the server
-    /// would come to a halt until this function unblocks.
-    pub async fn process(&mut self) -> anyhow::Result<Option<RawPacket>> {
-        if let Some(packet) = self.read_packet().await? {
-            return Ok(Some(packet));
-        }
-        Ok(None)
+    /// Moves the socket into a task that reads packets as soon as they arrive and sends them to
+    /// the server, together with the client id. Read errors (including the connection closing)
+    /// are sent as well, after which the task stops.
+    pub fn start_reading(&mut self, tx: Sender<TcpRead>) {
+        let Some(mut socket) = self.socket.take() else {
+            return;
+        };
+        let id = self.id;
+        let session = self.session;
+        let tcp_addr = self.tcp_addr;
+        self.read_runtime = Some(tokio::spawn(async move {
+            loop {
+                let result = read_packet_async(id, &mut socket).await;
+                if let Ok(packet) = &result {
+                    capture_packet(CaptureDirection::In, CaptureTransport::Tcp, Some(id), tcp_addr, &packet.data);
+                }
+                let failed = result.is_err();
+                if tx.send((id, session, result)).await.is_err() || failed {
+                    break;
+                }
+            }
+        }));
     }
 
-    pub async fn process_blocking(&mut self) -> anyhow::Result<Option<RawPacket>> {
-        if let Some(packet) = self.read_packet_waiting().await? {
-            return Ok(Some(packet));
-        }
-        Ok(None)
+    /// The read half of the socket, as long as it hasn't been moved into the reader task.
+    fn socket(&mut self) -> anyhow::Result<&mut OwnedReadHalf> {
+        let id = self.id;
+        self.socket.as_mut().ok_or_else(|| anyhow::anyhow!("The socket of client {} is owned by its reader task", id))
+    }
+
+    /// Notes that the client sent something, so it's still alive.
+    pub fn mark_received(&mut self) {
+        self.last_received = Instant::now();
+    }
+
+    /// How long it's been since the client sent anything.
+    pub fn silent_for(&self) -> Duration {
+        self.last_received.elapsed()
     }
 
     pub fn disconnect(&mut self) {
@@ -311,17 +432,59 @@ impl Client {
     }
 
     // Panics when userdata is not set!
+    pub fn get_beammp_id(&self) -> &str {
+        &self.info.as_ref().unwrap().uid
+    }
+
+    // Panics when userdata is not set!
+    pub fn get_identifiers(&self) -> PlayerIdentifiers {
+        let userdata = self.info.as_ref().unwrap();
+        PlayerIdentifiers {
+            ip: self.tcp_addr.map(|addr| addr.ip().to_string()).unwrap_or_default(),
+            beammp_id: userdata.uid.clone(),
+            discord_id: userdata.get_identifier("discord").map(|s| s.to_string()),
+        }
+    }
+
     pub fn get_name(&self) -> &str {
-        &self.info.as_ref().unwrap().username
+        self.info.as_ref().map(|info| info.username.as_str()).unwrap_or("Unknown")
     }
 
     pub fn get_id(&self) -> u8 {
         self.id
     }
 
-    // Panics when userdata is not set!
     pub fn get_roles(&self) -> &str {
-        &self.info.as_ref().unwrap().roles
+        self.role.as_ref()
+            .and_then(|role| role.client_role.as_deref())
+            .or_else(|| self.info.as_ref().map(|info| info.roles.as_str()))
+            .unwrap_or("USER")
+    }
+
+    /// The name set by the organizers (with race number), falling back to the account name.
+    pub fn get_display_name(&self) -> String {
+        let driver = self.driver.as_ref();
+        let name = driver
+            .and_then(|driver| driver.display_name.as_deref())
+            .map(|name| sanitize_message(name, MAX_DISPLAY_NAME_LENGTH))
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| self.get_name().to_string());
+        match driver.and_then(|driver| driver.number) {
+            Some(number) => format!("#{} {}", number, name),
+            None => name,
+        }
+    }
+
+    /// The name shown in chat, including the tag of the player's role.
+    pub fn get_chat_name(&self) -> String {
+        match self.role.as_ref().and_then(|role| role.tag.as_ref()) {
+            Some(tag) => format!("{} {}", tag, self.get_display_name()),
+            None => self.get_display_name(),
+        }
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.role.as_ref().map(|role| role.admin).unwrap_or(false)
     }
 
     pub fn register_car(&mut self, car: Car) -> u8 {
@@ -357,9 +520,63 @@ impl Client {
         None
     }
 
+    /// Registers a vehicle reset, returning false if the client already reset
+    /// `max_per_minute` times within the last minute.
+    pub fn try_register_reset(&mut self, max_per_minute: u32) -> bool {
+        while let Some(time) = self.reset_times.front() {
+            if time.elapsed() >= Duration::from_secs(60) {
+                self.reset_times.pop_front();
+            } else {
+                break;
+            }
+        }
+        if self.reset_times.len() >= max_per_minute as usize {
+            return false;
+        }
+        self.reset_times.push_back(Instant::now());
+        true
+    }
+
+    /// Registers a chat message, checking it against the cooldown and spam settings.
+    /// Muting happens automatically when the client exceeds the spam threshold.
+    pub fn register_chat_message(&mut self, settings: &crate::config::ChatSettings) -> ChatPermission {
+        let now = Instant::now();
+        if let Some(muted_until) = self.muted_until {
+            if muted_until > now {
+                return ChatPermission::Muted(muted_until - now);
+            }
+            self.muted_until = None;
+        }
+
+        if let Some(last) = self.chat_times.back() {
+            if last.elapsed() < Duration::from_millis(settings.cooldown_ms) {
+                return ChatPermission::Cooldown;
+            }
+        }
+
+        while let Some(time) = self.chat_times.front() {
+            if time.elapsed() >= Duration::from_secs(10) {
+                self.chat_times.pop_front();
+            } else {
+                break;
+            }
+        }
+        self.chat_times.push_back(now);
+
+        if self.chat_times.len() > settings.spam_threshold {
+            let duration = Duration::from_secs(settings.mute_seconds);
+            info!("Client #{} has been muted for spamming!", self.id);
+            self.muted_until = Some(now + duration);
+            self.chat_times.clear();
+            return ChatPermission::Muted(duration);
+        }
+
+        ChatPermission::Allowed
+    }
+
     async fn read_raw(&mut self, count: usize) -> anyhow::Result<Vec<u8>> {
         let mut b = vec![0u8; count];
-        self.socket.read_exact(&mut b).await?;
+        self.socket()?.read_exact(&mut b).await?;
         Ok(b)
     }
 
@@ -374,13 +591,13 @@ impl Client {
             }
             tokio::time::sleep(std::time::Duration::from_millis(500)).await;
         }
-        Err(ClientError::ConnectionTimeout.into())
+        Err(NetworkError::ConnectionTimeout { client_id: self.id }.into())
     }
 
     /// Must be non-blocking
     async fn read_packet(&mut self) -> anyhow::Result<Option<RawPacket>> {
         let mut header = [0u8; 4];
-        match self.socket.try_read(&mut header) {
+        match self.socket()?.try_read(&mut header) {
             Ok(0) => {
                 error!("Socket is readable, yet has 0 bytes to read! Disconnecting client...");
                 self.disconnect();
@@ -390,15 +607,18 @@ impl Client {
             Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
                 return Ok(None);
             }
-            Err(e) => return Err(e.into()),
+            Err(e) => return Err(NetworkError::Read { client_id: self.id, source: e }.into()),
         }
 
         let expected_size = u32::from_le_bytes(header) as usize;
+        if expected_size > MAX_PACKET_SIZE {
+            return Err(ProtocolError::PacketTooLarge { client_id: self.id, size: expected_size }.into());
+        }
         let mut data = Vec::new();
         let mut tmp_data = vec![0u8; expected_size];
         let mut data_size = 0;
         while data_size < expected_size {
-            match self.socket.try_read(&mut tmp_data) {
+            match self.socket()?.try_read(&mut tmp_data) {
                 Ok(0) => {
                     error!("Socket is readable, yet has 0 bytes to read! Disconnecting client...");
                     self.disconnect();
@@ -413,10 +633,12 @@ impl Client {
                     // self.socket.read(&mut data).await?;
                     return Ok(None);
                 }
-                Err(e) => return Err(e.into()),
+                Err(e) => return Err(NetworkError::Read { client_id: self.id, source: e }.into()),
             }
         }
 
+        capture_packet(CaptureDirection::In, CaptureTransport::Tcp, Some(self.id), self.tcp_addr, &data[..data_size]);
+
         Ok(Some(RawPacket {
             header: data_size as u32,
             data: data[..data_size].to_vec(),
@@ -425,10 +647,12 @@ impl Client {
 
     /// Blocking write
     pub async fn write_packet(&mut self, packet: Packet) -> anyhow::Result<()> {
+        let data = encode_tcp_packet(&packet)?;
+        capture_encoded_tcp(Some(self.id), self.tcp_addr, &data);
         let mut lock = self.write_half.lock().await;
         lock.writable().await?;
         trace!("Sending packet!");
-        if let Err(e) = tcp_write(lock.deref_mut(), packet).await {
+        if let Err(e) = tcp_write_encoded(lock.deref_mut(), &data).await {
             error!("{:?}", e);
         }
         trace!("Packet sent!");
@@ -437,8 +661,28 @@ impl Client {
     }
 
     pub async fn queue_packet(&self, packet: Packet) {
-        // TODO: If packet gets lost, put it back at the front of the queue?
-        let _ = self.write_runtime_sender.send(packet).await;
+        match encode_tcp_packet(&packet) {
+            Ok(data) => self.queue_encoded(data, packet.priority(), packet.car()).await,
+            Err(e) => error!("Failed to encode packet: {:?}", e),
+        }
+    }
+
+    /// Queues a packet that was already encoded with `encode_tcp_packet`. Used for broadcasts,
+    /// so the packet is encoded once and the buffer is shared between all clients. Never waits
+    /// for the client, see `OutboundQueue`.
+    pub async fn queue_encoded(&self, data: Bytes, priority: PacketPriority, car: Option<(u8, u8)>) {
+        self.outbound.push(priority, car, data);
+    }
+
+    /// Changes the most bytes per second sent to the client, see `GeneralSettings::outbound_limit`.
+    pub fn set_outbound_limit(&self, limit: Option<usize>) {
+        self.outbound.set_limit(limit);
+    }
+
+    /// Whether the client fell so far behind on the packets sent to it that some had to be
+    /// thrown away. Its game no longer matches the server, so it should be disconnected.
+    pub fn is_backed_up(&self) -> bool {
+        self.outbound.overflowed()
     }
 
     pub async fn trigger_client_event<S: Into<String>, D: Into<String>>(&self, event_name: S, data: D) {
@@ -450,22 +694,19 @@ impl Client {
     }
 }
 
-#[derive(Debug)]
-pub enum ClientError {
-    AuthenticateError,
-    ConnectionTimeout,
-    IsDownloader,
-}
 
-impl std::fmt::Display for ClientError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-        write!(f, "{:?}", self)?;
-        Ok(())
+/// Reads a whole packet, waiting for as long as it takes. Not cancel safe, so it should only
+/// be used from a task that does nothing else.
+async fn read_packet_async(client_id: u8, socket: &mut OwnedReadHalf) -> anyhow::Result<RawPacket> {
+    let size = socket.read_u32_le().await?;
+    if size as usize > MAX_PACKET_SIZE {
+        return Err(ProtocolError::PacketTooLarge { client_id, size: size as usize }.into());
     }
+    let mut data = vec![0u8; size as usize];
+    socket.read_exact(&mut data).await?;
+    Ok(RawPacket { header: size, data })
 }
 
-impl std::error::Error for ClientError {}
-
 #[async_trait]
 trait Writable {
     async fn writable(&self) -> std::io::Result<()>;
@@ -485,43 +726,12 @@ impl Writable for OwnedWriteHalf {
     }
 }
 
-async fn tcp_write<W: AsyncWriteExt + Writable + std::marker::Unpin>(
-    w: &mut W,
-    mut packet: Packet,
-) -> anyhow::Result<()> {
-    let compressed = match packet.get_code() {
-        Some('O') => true,
-        Some('T') => true,
-        _ => packet.get_data().len() > 400,
-    };
-
-    if compressed {
-        let mut compressed: Vec<u8> = Vec::with_capacity(100_000);
-        let mut compressor = flate2::Compress::new(flate2::Compression::best(), true);
-        compressor.compress_vec(
-            packet.get_data(),
-            &mut compressed,
-            flate2::FlushCompress::Sync,
-        )?;
-        let mut new_data = "ABG:".as_bytes()[..4].to_vec();
-        new_data.append(&mut compressed);
-        packet.set_header(new_data.len() as u32);
-        packet.set_data(new_data);
-    }
-
-    tcp_write_raw(w, packet).await?;
-
-    Ok(())
-}
-
-async fn tcp_write_raw<W: AsyncWriteExt + Writable + std::marker::Unpin>(
+async fn tcp_write_encoded<W: AsyncWriteExt + Writable + std::marker::Unpin>(
     w: &mut W,
-    mut packet: Packet,
+    data: &[u8],
 ) -> anyhow::Result<()> {
-    let mut raw_data: Vec<u8> = packet.get_header().to_le_bytes().to_vec();
-    raw_data.extend_from_slice(packet.get_data());
     w.writable().await?;
-    w.write(&raw_data).await?;
+    w.write_all(data).await?;
     Ok(())
 }
 
@@ -530,6 +740,7 @@ async fn tcp_send_file<W: AsyncWriteExt + Writable + std::marker::Unpin>(
     file_name: String,
 ) -> anyhow::Result<()> {
     debug!("Sending file '{}'", file_name);
-    tcp_write(w, Packet::Raw(RawPacket::from_str("KYou have not downloaded the mod manually!"))).await?;
+    let packet = Packet::Raw(RawPacket::from_str("KYou have not downloaded the mod manually!"));
+    tcp_write_encoded(w, &encode_tcp_packet(&packet)?).await?;
     Ok(())
 }
@@ -0,0 +1,284 @@
+use super::*;
+
+/// Where a command came from, used for permission checks and to know where to send the reply.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommandSource {
+    Console,
+    Client(u8),
+}
+
+/// Splits a command line into arguments. Arguments can be quoted to include spaces.
+/// Used for both console input and chat commands, so they behave exactly the same.
+pub fn parse_command_args(input: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut has_arg = false;
+    for c in input.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                has_arg = true;
+            },
+            c if c.is_whitespace() && !in_quotes => {
+                if has_arg {
+                    args.push(std::mem::take(&mut current));
+                    has_arg = false;
+                }
+            },
+            c => {
+                current.push(c);
+                has_arg = true;
+            },
+        }
+    }
+    if has_arg {
+        args.push(current);
+    }
+    args
+}
+
+/// Returns true for the commands of the server itself, as opposed to the ones plugins register.
+pub(super) fn is_builtin_command(command: &str) -> bool {
+    matches!(command, "help" | "players" | "rc" | "say" | "kick" | "ban" | "unban" | "banlist" | "world" | "save" | "tp" | "set" | "reload" | "snapshot" | "report" | "reports" | "decide")
+}
+
+/// Returns true if the command can only be used by admins when ran from chat. Plugins check
+/// permissions for their own commands.
+fn requires_admin(command: &str) -> bool {
+    // The teleport commands have their own permission setting
+    !matches!(command, "help" | "players" | "rc" | "report" | "save" | "tp")
+}
+
+impl Server {
+    /// Returns true if the given command exists. Used to decide whether a chat message
+    /// starting with '!' is a command or should be treated as a regular message.
+    pub fn is_command(&self, command: &str) -> bool {
+        is_builtin_command(command) || self.plugin_commands.contains_key(command)
+    }
+
+    pub(super) fn has_admin_permission(&self, source: CommandSource) -> bool {
+        match source {
+            CommandSource::Console => true,
+            CommandSource::Client(id) => self.clients.iter()
+                .find(|client| client.id == id)
+                .map(|client| client.is_admin() || self.config.general.admins.iter().any(|id| id == client.get_beammp_id()))
+                .unwrap_or(false),
+        }
+    }
+
+    pub(super) async fn command_reply(&self, source: CommandSource, message: &str) {
+        match source {
+            CommandSource::Console => info!("{}", message),
+            CommandSource::Client(id) => self.send_chat_message(message, Some(id)).await,
+        }
+    }
+
+    /// Runs a command from either the console or the chat. The first argument is the command name.
+    pub async fn run_command(&mut self, source: CommandSource, args: &[String]) {
+        let Some(command) = args.first() else { return; };
+
+        if let Some(&plugin) = self.plugin_commands.get(command) {
+            // Console commands come from player -1, like chat messages the server sends to everyone
+            let pid = match source {
+                CommandSource::Console => -1,
+                CommandSource::Client(id) => id as i64,
+            };
+            self.plugins[plugin].send_event(PluginBoundPluginEvent::CallEventHandler((
+                ScriptEvent::OnCommand { pid, command: command.clone(), args: args[1..].to_vec() },
+                None,
+            ))).await;
+            return;
+        }
+
+        if requires_admin(command) && !self.has_admin_permission(source) {
+            self.command_reply(source, "You don't have permission to use this command!").await;
+            return;
+        }
+
+        match command.as_str() {
+            "help" => {
+                self.command_reply(source, "Commands: help, players, rc [message], say <message>, kick <id|name> [reason], ban <id|name> [reason], unban <name|beammp id>, banlist, world [time|weather] [value], save <name>, tp <name|id>, set [setting] [value], reload, snapshot, report <id|name> <what happened>, reports [all], decide <report> <decision>").await;
+            },
+            "players" => {
+                let mut pl = "Players:\n".to_string();
+                for (i, client) in self.clients.iter().enumerate() {
+                    pl.push_str(&format!("\t[{: >2}] - {}", client.id, client.get_name()));
+                    if client.driver.is_some() {
+                        pl.push_str(&format!(" ({})", client.get_display_name()));
+                    }
+                    if let Some(region) = &client.region {
+                        pl.push_str(&format!(" [{}]", region));
+                    }
+                    if let Some(connection) = client.connection.summary() {
+                        pl.push_str(&format!(" ({})", connection));
+                    }
+                    if i + 1 < self.clients.len() {
+                        pl.push('\n');
+                    }
+                }
+                self.command_reply(source, &pl).await;
+            },
+            "rc" => {
+                if args.len() < 2 {
+                    self.command_reply(source, &self.race_control_history()).await;
+                } else if !self.has_admin_permission(source) {
+                    self.command_reply(source, "You don't have permission to send race control messages!").await;
+                } else {
+                    self.send_race_control_message(&args[1..].join(" ")).await;
+                }
+            },
+            "say" => {
+                let msg = args[1..].join(" ");
+                self.send_chat_message(&msg, None).await;
+            },
+            "kick" => {
+                let Some(target) = args.get(1) else {
+                    self.command_reply(source, "Usage: kick <id|name> [reason]").await;
+                    return;
+                };
+                let reason = if args.len() > 2 { args[2..].join(" ") } else { String::from("You have been kicked from the server!") };
+                if let Some(index) = self.find_client(target) {
+                    self.kick_player(index, reason).await;
+                } else {
+                    self.command_reply(source, &format!("Could not find player '{}'", target)).await;
+                }
+            },
+            "ban" => {
+                let Some(target) = args.get(1) else {
+                    self.command_reply(source, "Usage: ban <id|name> [reason]").await;
+                    return;
+                };
+                let reason = if args.len() > 2 { args[2..].join(" ") } else { String::from("No reason given") };
+                self.ban_player(source, target, reason).await;
+            },
+            "unban" => {
+                let Some(target) = args.get(1) else {
+                    self.command_reply(source, "Usage: unban <name|beammp id>").await;
+                    return;
+                };
+                self.unban_player(source, target).await;
+            },
+            "banlist" => self.list_bans(source).await,
+            "world" => self.world_command(source, args).await,
+            "save" => {
+                let Some(name) = args.get(1) else {
+                    self.command_reply(source, "Usage: save <name>").await;
+                    return;
+                };
+                self.save_bookmark(source, name).await;
+            },
+            "tp" => {
+                let Some(target) = args.get(1) else {
+                    self.command_reply(source, "Usage: tp <saved position|id|name>").await;
+                    return;
+                };
+                self.teleport(source, target).await;
+            },
+            "set" => {
+                let (Some(setting), Some(value)) = (args.get(1), args.get(2)) else {
+                    let general = &self.config.general;
+                    let chat = &self.config.chat;
+                    self.command_reply(source, &format!(
+                        "Settings: max_cars = {}, max_resets = {}, max_players = {}, chat_cooldown_ms = {}, max_message_length = {}, lock_liveries = {}, edits = {:?}",
+                        general.max_cars.map(|n| n.to_string()).unwrap_or_else(|| String::from("none")),
+                        general.max_resets_per_minute.map(|n| n.to_string()).unwrap_or_else(|| String::from("none")),
+                        general.max_players,
+                        chat.cooldown_ms,
+                        chat.max_message_length,
+                        self.config.liveries.locked,
+                        self.config.edits.mode,
+                    )).await;
+                    return;
+                };
+                match self.set_setting(setting, value) {
+                    Ok(()) => {
+                        info!("{} changed to {}", setting, value);
+                        self.send_chat_message(&format!("Server setting {} changed to {}", setting, value), None).await;
+                    },
+                    Err(e) => self.command_reply(source, &e).await,
+                }
+            },
+            "reload" => {
+                match Config::load(crate::config::CONFIG_PATH) {
+                    Ok(new_config) => {
+                        self.replace_config(self.config.reloaded(new_config));
+                        // Player count rules apply again on the next tick, on top of the new settings
+                        self.player_count_rules = PlayerCountRules::default();
+                        info!("Reloaded {}", crate::config::CONFIG_PATH);
+                        self.command_reply(source, "Config reloaded. Changes to ports, folders and auth settings need a restart.").await;
+                    },
+                    Err(e) => {
+                        error!("{}", e);
+                        self.command_reply(source, "Failed to reload the config, nothing changed!").await;
+                    },
+                }
+            },
+            "snapshot" => {
+                let path = crate::output::event_path("snapshot.json");
+                match serde_json::to_vec_pretty(&self.snapshot()) {
+                    Ok(data) => {
+                        self.command_reply(source, &format!("Saving the server state to {}", path.display())).await;
+                        tokio::task::spawn_blocking(move || {
+                            if let Err(e) = crate::fs_util::write_atomic(&path, &data) {
+                                error!("Failed to save the server state: {:?}", e);
+                            }
+                        });
+                    },
+                    Err(e) => {
+                        error!("Failed to serialize the server state: {:?}", e);
+                        self.command_reply(source, "Failed to save the server state!").await;
+                    },
+                }
+            },
+            "report" => {
+                let (Some(driver), true) = (args.get(1), args.len() > 2) else {
+                    self.command_reply(source, "Usage: report <id|name> <what happened>").await;
+                    return;
+                };
+                let description = sanitize_message(&args[2..].join(" "), self.config.chat.max_message_length);
+                self.report_incident(source, driver, description).await;
+            },
+            "reports" => {
+                let all = args.get(1).map(|arg| arg == "all").unwrap_or(false);
+                self.list_reports(source, all).await;
+            },
+            "decide" => {
+                let (Some(id), true) = (args.get(1), args.len() > 2) else {
+                    self.command_reply(source, "Usage: decide <report> <decision>").await;
+                    return;
+                };
+                self.decide_report(source, id, args[2..].join(" ")).await;
+            },
+            _ => self.command_reply(source, "Unknown command!").await,
+        }
+    }
+
+    /// Changes a setting of the running server. The config is shared, so this swaps in a
+    /// modified copy. Changes are lost when the server restarts.
+    fn set_setting(&mut self, setting: &str, value: &str) -> Result<(), String> {
+        fn parse_optional<T: std::str::FromStr>(value: &str) -> Result<Option<T>, String> {
+            match value {
+                "none" | "off" => Ok(None),
+                value => value.parse().map(Some).map_err(|_| format!("Invalid value '{}'", value)),
+            }
+        }
+        fn parse<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+            value.parse().map_err(|_| format!("Invalid value '{}'", value))
+        }
+
+        let mut config = Config::clone(&self.config);
+        match setting {
+            "max_cars" => config.general.max_cars = parse_optional(value)?,
+            "max_resets" => config.general.max_resets_per_minute = parse_optional(value)?,
+            "max_players" => config.general.max_players = parse(value)?,
+            "chat_cooldown_ms" => config.chat.cooldown_ms = parse(value)?,
+            "max_message_length" => config.chat.max_message_length = parse(value)?,
+            "lock_liveries" => config.liveries.locked = parse(value)?,
+            "edits" => config.edits.mode = parse(value)?,
+            _ => return Err(format!("Unknown setting '{}'", setting)),
+        }
+        self.replace_config(config);
+        Ok(())
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::config::ConnectionQualitySettings;
+
+use super::{Packet, PluginBoundPluginEvent, RawPacket, ScriptEvent, Server};
+
+/// How many pings the ping and jitter are averaged over.
+const PING_SAMPLES: usize = 30;
+
+/// Gaps between position updates (in client time) longer than this are a paused game or a
+/// loading screen, not loss.
+const MAX_UPDATE_GAP: f64 = 1.0;
+
+/// Weight of a new gap in the usual interval between position updates of a car.
+const INTERVAL_SMOOTHING: f64 = 0.1;
+
+/// Without a UDP ping for this long, a spectating player's connection can't be graded.
+const MAX_PING_SILENCE: Duration = Duration::from_secs(3);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ConnectionGrade {
+    Good,
+    Fair,
+    Poor,
+}
+
+impl fmt::Display for ConnectionGrade {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ConnectionGrade::Good => "good",
+            ConnectionGrade::Fair => "fair",
+            ConnectionGrade::Poor => "poor",
+        })
+    }
+}
+
+/// What to do with a player after grading their connection.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum QualityAction {
+    Warn,
+    Spectate,
+    Release,
+}
+
+/// Ping, jitter and loss of a client, measured from the position updates of their cars.
+#[derive(Default, Debug)]
+pub struct ConnectionQuality {
+    /// Most recent pings, in milliseconds.
+    pings: VecDeque<f64>,
+    /// Client time of the last position update and the usual interval between them, per car.
+    updates: HashMap<u8, (f64, Option<f64>)>,
+    /// Position updates that arrived and that went missing. Halved every grading, so older
+    /// updates count less.
+    received: f64,
+    lost: f64,
+    /// Updates since the last grading.
+    new_updates: u32,
+    /// When the last UDP ping of the client arrived, and the most recent intervals between
+    /// them in milliseconds. Used while spectating, as there are no cars to measure then.
+    last_udp_ping: Option<Instant>,
+    udp_ping_intervals: VecDeque<f64>,
+
+    pub ping_ms: f64,
+    pub jitter_ms: f64,
+    pub loss_percent: f64,
+    /// None while there are no position updates to grade, like when the player has no cars.
+    pub grade: Option<ConnectionGrade>,
+
+    poor_since: Option<Instant>,
+    fine_since: Option<Instant>,
+    warned: bool,
+    /// Moved to spectator because of a poor connection.
+    pub spectating: bool,
+}
+
+impl ConnectionQuality {
+    /// Records a position update of one of the client's cars.
+    pub fn record_position(&mut self, car_id: u8, tim: f64, ping: f64) {
+        self.pings.push_back(ping * 1000.0);
+        if self.pings.len() > PING_SAMPLES {
+            self.pings.pop_front();
+        }
+        self.received += 1.0;
+        self.new_updates += 1;
+
+        let (last_tim, interval) = self.updates.entry(car_id).or_insert((tim, None));
+        let gap = tim - *last_tim;
+        *last_tim = tim;
+        if gap <= 0.0 || gap > MAX_UPDATE_GAP {
+            return;
+        }
+        match *interval {
+            None => *interval = Some(gap),
+            // Some leeway, as the client doesn't send updates at an exact rate
+            Some(usual) if gap > usual * 1.8 => self.lost += (gap / usual).round() - 1.0,
+            Some(usual) => *interval = Some(usual + (gap - usual) * INTERVAL_SMOOTHING),
+        }
+    }
+
+    /// Records a UDP ping of the client, which the client sends whether it has cars or not.
+    pub fn record_udp_ping(&mut self) {
+        let now = Instant::now();
+        match self.last_udp_ping.replace(now) {
+            Some(last) if now - last <= MAX_PING_SILENCE => {
+                self.udp_ping_intervals.push_back((now - last).as_secs_f64() * 1000.0);
+                if self.udp_ping_intervals.len() > PING_SAMPLES {
+                    self.udp_ping_intervals.pop_front();
+                }
+            },
+            // The client stopped pinging for a while, like while loading, so start over
+            _ => self.udp_ping_intervals.clear(),
+        }
+    }
+
+    /// Grades the connection from the updates since the last grading.
+    fn grade(&mut self, settings: &ConnectionQualitySettings) {
+        if self.new_updates == 0 {
+            self.pings.clear();
+            self.updates.clear();
+            self.received = 0.0;
+            self.lost = 0.0;
+            self.grade = if self.spectating { self.grade_udp_pings(settings) } else { None };
+            return;
+        }
+        self.new_updates = 0;
+
+        self.ping_ms = self.pings.iter().sum::<f64>() / self.pings.len() as f64;
+        self.jitter_ms = if self.pings.len() > 1 {
+            self.pings.iter().zip(self.pings.iter().skip(1)).map(|(a, b)| (b - a).abs()).sum::<f64>() / (self.pings.len() - 1) as f64
+        } else {
+            0.0
+        };
+        self.loss_percent = self.lost / (self.received + self.lost) * 100.0;
+        self.received /= 2.0;
+        self.lost /= 2.0;
+
+        // Worst fraction of a limit
+        let worst = [
+            self.ping_ms / settings.max_ping_ms,
+            self.jitter_ms / settings.max_jitter_ms,
+            self.loss_percent / settings.max_loss_percent,
+        ].into_iter().fold(0.0, f64::max);
+        self.grade = Some(grade_from_worst(worst));
+    }
+
+    /// Grades the connection from the UDP pings of the client. The ping itself isn't known
+    /// from these, so only jitter and loss are graded.
+    fn grade_udp_pings(&mut self, settings: &ConnectionQualitySettings) -> Option<ConnectionGrade> {
+        if self.last_udp_ping?.elapsed() > MAX_PING_SILENCE || self.udp_ping_intervals.len() < 2 {
+            return None;
+        }
+        let mut sorted: Vec<f64> = self.udp_ping_intervals.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let usual = sorted[sorted.len() / 2];
+        if usual <= 0.0 {
+            return None;
+        }
+
+        let intervals = &self.udp_ping_intervals;
+        self.jitter_ms = intervals.iter().zip(intervals.iter().skip(1)).map(|(a, b)| (b - a).abs()).sum::<f64>() / (intervals.len() - 1) as f64;
+        // Same leeway as for position updates
+        let lost: f64 = intervals.iter().filter(|interval| **interval > usual * 1.8).map(|interval| (interval / usual).round() - 1.0).sum();
+        self.loss_percent = lost / (intervals.len() as f64 + lost) * 100.0;
+
+        let worst = f64::max(self.jitter_ms / settings.max_jitter_ms, self.loss_percent / settings.max_loss_percent);
+        Some(grade_from_worst(worst))
+    }
+
+    /// Decides whether the player should be warned, moved to spectator or released, based on
+    /// how long their connection has (or hasn't) been poor.
+    fn action(&mut self, settings: &ConnectionQualitySettings) -> Option<QualityAction> {
+        let now = Instant::now();
+        match self.grade {
+            Some(ConnectionGrade::Poor) => {
+                self.fine_since = None;
+                self.poor_since.get_or_insert(now);
+            },
+            Some(_) => {
+                self.poor_since = None;
+                self.fine_since.get_or_insert(now);
+                self.warned = false;
+            },
+            // Nothing to measure, so it's not poor, but not known to be fine either
+            None => {
+                self.poor_since = None;
+                self.fine_since = None;
+                self.warned = false;
+            },
+        }
+
+        let spectate_after = Duration::from_secs(settings.spectate_after);
+        if self.spectating {
+            if self.fine_since.is_some_and(|since| since.elapsed() >= spectate_after) {
+                self.spectating = false;
+                return Some(QualityAction::Release);
+            }
+            return None;
+        }
+        let poor_for = self.poor_since?.elapsed();
+        if poor_for >= spectate_after {
+            self.spectating = true;
+            self.warned = false;
+            Some(QualityAction::Spectate)
+        } else if poor_for >= Duration::from_secs(settings.warn_after) && !self.warned {
+            self.warned = true;
+            Some(QualityAction::Warn)
+        } else {
+            None
+        }
+    }
+
+    /// Short description for the players command, like "42 ms, good".
+    pub fn summary(&self) -> Option<String> {
+        let grade = self.grade?;
+        let mut summary = format!("{:.0} ms, {}", self.ping_ms, grade);
+        if self.spectating {
+            summary.push_str(", spectating");
+        }
+        Some(summary)
+    }
+}
+
+/// Grades a connection by the worst fraction of a limit.
+fn grade_from_worst(worst: f64) -> ConnectionGrade {
+    if worst > 1.0 {
+        ConnectionGrade::Poor
+    } else if worst > 0.5 {
+        ConnectionGrade::Fair
+    } else {
+        ConnectionGrade::Good
+    }
+}
+
+impl Server {
+    /// Grades the connection of every player once per second, warning and moving players with a
+    /// poor connection to spectator if AutoSpectate is on.
+    pub(super) async fn update_connection_quality(&mut self) {
+        if self.last_connection_update.elapsed() < Duration::from_secs(1) {
+            return;
+        }
+        self.last_connection_update = Instant::now();
+
+        let settings = self.config.connection_quality.clone();
+        for i in 0..self.clients.len() {
+            let pid = self.clients[i].id;
+            let quality = &mut self.clients[i].connection;
+            quality.grade(&settings);
+            let action = if settings.auto_spectate {
+                quality.action(&settings)
+            } else if quality.spectating {
+                // AutoSpectate got turned off by a reload
+                quality.spectating = false;
+                Some(QualityAction::Release)
+            } else {
+                None
+            };
+            let details = format!("ping {:.0} ms, jitter {:.0} ms, loss {:.1}%", quality.ping_ms, quality.jitter_ms, quality.loss_percent);
+
+            match action {
+                Some(QualityAction::Warn) => {
+                    info!("Client {} has a poor connection ({})", pid, details);
+                    let message = format!("Your connection is unstable ({}). You'll be moved to spectator if it doesn't improve.", details);
+                    self.send_chat_message(&message, Some(pid)).await;
+                },
+                Some(QualityAction::Spectate) => {
+                    info!("Moving client {} to spectator because of their connection ({})", pid, details);
+                    self.remove_cars(i).await;
+                    self.clients[i].trigger_client_event("Spectate", "true").await;
+                    let message = format!("You've been moved to spectator because of your connection ({}).", details);
+                    self.send_chat_message(&message, Some(pid)).await;
+                },
+                Some(QualityAction::Release) => {
+                    info!("Client {} can drive again", pid);
+                    self.clients[i].trigger_client_event("Spectate", "false").await;
+                    self.send_chat_message("You can drive again.", Some(pid)).await;
+                },
+                None => {},
+            }
+        }
+    }
+
+    /// Why the client can't spawn a car, if they've been moved to spectator.
+    pub(super) fn spectate_limit(&self, client_idx: usize) -> Option<String> {
+        self.clients[client_idx].connection.spectating
+            .then(|| String::from("You can't spawn cars while spectating because of your connection."))
+    }
+
+    /// Deletes all cars of the client, for everyone.
+    async fn remove_cars(&mut self, client_idx: usize) {
+        let pid = self.clients[client_idx].id;
+        let car_ids: Vec<u8> = self.clients[client_idx].cars.iter().map(|(vid, _)| *vid).collect();
+        for vid in car_ids {
+            self.clients[client_idx].unregister_car(vid);
+            let packet = Packet::Raw(RawPacket::from_str(&format!("Od:{}-{}", pid, vid)));
+            for client in &self.clients {
+                client.queue_packet(packet.clone()).await;
+            }
+            for plugin in &self.plugins {
+                plugin.send_event(PluginBoundPluginEvent::CallEventHandler((
+                    ScriptEvent::OnVehicleDeleted { pid, vid },
+                    None,
+                ))).await;
+            }
+        }
+    }
+}
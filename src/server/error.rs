@@ -0,0 +1,48 @@
+use thiserror::Error;
+
+/// Errors on the connection to a client.
+#[derive(Debug, Error)]
+pub enum NetworkError {
+    #[error("Timed out waiting for a packet from client {client_id}")]
+    ConnectionTimeout { client_id: u8 },
+    #[error("Failed to read from client {client_id}: {source}")]
+    Read {
+        client_id: u8,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+/// Errors in the packets clients send us. They carry the client and packet they came from,
+/// so the logs show who sent what.
+#[derive(Debug, Error)]
+pub enum ProtocolError {
+    #[error("Client {client_id} sent a broken '{code}' packet: {reason}")]
+    BrokenPacket { client_id: u8, code: char, reason: &'static str },
+    #[error("Client {client_id} sent a '{code}' packet for car {car_id}, which doesn't exist")]
+    CarDoesntExist { client_id: u8, code: char, car_id: u8 },
+    #[error("Client {client_id} sent a packet of {size} bytes, which is more than we accept")]
+    PacketTooLarge { client_id: u8, size: usize },
+}
+
+/// Errors in the state of the server itself. These are bugs, not something a client did.
+#[derive(Debug, Error)]
+pub enum StateError {
+    #[error("There is no client at index {index}")]
+    ClientDoesntExist { index: usize },
+}
+
+/// Errors that stop a client from joining. The message is shown to the player when they get kicked.
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("Failed to authenticate player!")]
+    AuthenticateError,
+    #[error("The BeamMP authentication servers can't be reached right now. Please try again in a minute!")]
+    AuthBackendUnreachable,
+    #[error("You are banned from this server: {reason}")]
+    Banned { reason: String },
+    #[error("This server is full!")]
+    ServerFull,
+    #[error("Connection is a downloader")]
+    IsDownloader,
+}
@@ -0,0 +1,96 @@
+use glam::DVec3;
+use tokio::sync::broadcast;
+
+/// Amount of events a subscriber can fall behind before it starts missing events.
+const EVENT_BUS_CAPACITY: usize = 1_024;
+
+/// Something that happened on the server. Published by the network layer, so features can react
+/// to it without having to be part of the packet handling.
+#[derive(Clone, Debug)]
+pub enum ServerEvent {
+    ServerStarted,
+    PlayerJoined { pid: u8, name: String },
+    PlayerLeft { pid: u8, name: String },
+    /// A chat message that made it past the plugins, with the name it's shown with.
+    ChatReceived { pid: u8, name: String, message: String },
+    PositionUpdated { pid: u8, vid: u8, pos: DVec3, vel: DVec3 },
+    PlayerKicked { pid: u8, name: String, reason: String },
+    /// The player may not be on the server, see `Server::ban_player`.
+    PlayerBanned { name: String, reason: String },
+}
+
+/// Broadcasts `ServerEvent`s to every subscriber. Subscribers that fall too far behind get a
+/// `RecvError::Lagged` and skip the events they missed, so a slow feature can't hold up the server.
+#[derive(Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<ServerEvent>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+        Self { tx }
+    }
+
+    pub fn publish(&self, event: ServerEvent) {
+        // Fails only if nobody is subscribed, which is fine
+        let _ = self.tx.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ServerEvent> {
+        self.tx.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::broadcast::error::TryRecvError;
+
+    fn joined(pid: u8) -> ServerEvent {
+        ServerEvent::PlayerJoined { pid, name: format!("player{}", pid) }
+    }
+
+    #[test]
+    fn subscribers_get_every_event_in_order() {
+        let bus = EventBus::new();
+        let mut first = bus.subscribe();
+        let mut second = bus.subscribe();
+        bus.publish(joined(1));
+        bus.publish(ServerEvent::PlayerLeft { pid: 1, name: String::from("player1") });
+
+        for subscriber in [&mut first, &mut second] {
+            assert!(matches!(subscriber.try_recv(), Ok(ServerEvent::PlayerJoined { pid: 1, .. })));
+            assert!(matches!(subscriber.try_recv(), Ok(ServerEvent::PlayerLeft { pid: 1, .. })));
+            assert!(matches!(subscriber.try_recv(), Err(TryRecvError::Empty)));
+        }
+    }
+
+    #[test]
+    fn subscribers_only_get_events_published_after_subscribing() {
+        let bus = EventBus::new();
+        // Nobody is listening, which is fine
+        bus.publish(joined(1));
+        let mut subscriber = bus.subscribe();
+        bus.publish(joined(2));
+        assert!(matches!(subscriber.try_recv(), Ok(ServerEvent::PlayerJoined { pid: 2, .. })));
+    }
+
+    #[test]
+    fn slow_subscribers_skip_what_they_missed() {
+        let bus = EventBus::new();
+        let mut subscriber = bus.subscribe();
+        for i in 0..EVENT_BUS_CAPACITY + 10 {
+            bus.publish(joined(i as u8));
+        }
+        assert!(matches!(subscriber.try_recv(), Err(TryRecvError::Lagged(10))));
+        // Continues with the oldest event that's still there
+        assert!(matches!(subscriber.try_recv(), Ok(ServerEvent::PlayerJoined { pid: 10, .. })));
+    }
+}
@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Serialize;
+
+use super::Server;
+use crate::fs_util;
+
+/// The cars of each player (keyed by BeamMP ID) when they last left, with their edits.
+#[derive(Default)]
+pub struct Garages {
+    vehicles: HashMap<String, Vec<String>>,
+}
+
+#[derive(Serialize)]
+struct GarageData {
+    vehicles: Vec<serde_json::Value>,
+}
+
+impl Garages {
+    pub fn load(path: &str) -> Self {
+        Self { vehicles: fs_util::load_json(Path::new(path), "the garages") }
+    }
+
+    fn save(&self, path: &str) {
+        if let Err(e) = fs_util::save_json(Path::new(path), &self.vehicles, 3) {
+            error!("Failed to save the garages: {:?}", e);
+        }
+    }
+}
+
+impl Server {
+    /// Saves the cars of a player that is leaving. Guests don't have a BeamMP ID to keep them under.
+    pub(super) fn store_garage(&mut self, client_idx: usize) {
+        let settings = &self.config.garage;
+        let client = &self.clients[client_idx];
+        let Some(info) = client.info.as_ref().filter(|info| settings.enabled && !info.guest) else {
+            return;
+        };
+        let cars: Vec<String> = client.cars.iter().map(|(_, car)| car.car_json.clone()).collect();
+        if cars.is_empty() {
+            self.garages.vehicles.remove(&info.uid);
+        } else {
+            self.garages.vehicles.insert(info.uid.clone(), cars);
+        }
+        self.garages.save(&settings.file);
+    }
+
+    /// Sends a joining player the cars they had when they last left, as the client event "Garage".
+    pub(super) async fn restore_garage(&self, client_idx: usize) {
+        let client = &self.clients[client_idx];
+        let Some(info) = client.info.as_ref().filter(|info| self.config.garage.enabled && !info.guest) else {
+            return;
+        };
+        let Some(cars) = self.garages.vehicles.get(&info.uid) else {
+            return;
+        };
+        let data = GarageData {
+            vehicles: cars.iter().filter_map(|car_json| serde_json::from_str(car_json).ok()).collect(),
+        };
+        match serde_json::to_string(&data) {
+            Ok(data) => client.trigger_client_event("Garage", data).await,
+            Err(e) => error!("Failed to serialize the garage of {}: {:?}", client.get_name(), e),
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::Semaphore;
+
+use super::{http_client_builder, ServerEvent};
+use crate::config::{Config, HookEvent};
+
+/// How many hook commands and webhooks can run at the same time. Once that many are running,
+/// new events wait, and are skipped if too many pile up.
+const MAX_RUNNING_HOOKS: usize = 8;
+
+/// The hook event and placeholder values of a server event, if hooks can run on it.
+fn hook_values(event: &ServerEvent) -> Option<(HookEvent, HashMap<&'static str, String>)> {
+    let (hook_event, pid, name, reason) = match event {
+        ServerEvent::ServerStarted => (HookEvent::ServerStarted, None, None, None),
+        ServerEvent::PlayerJoined { pid, name } => (HookEvent::PlayerJoined, Some(*pid), Some(name), None),
+        ServerEvent::PlayerLeft { pid, name } => (HookEvent::PlayerLeft, Some(*pid), Some(name), None),
+        ServerEvent::PlayerKicked { pid, name, reason } => (HookEvent::PlayerKicked, Some(*pid), Some(name), Some(reason)),
+        ServerEvent::PlayerBanned { name, reason } => (HookEvent::PlayerBanned, None, Some(name), Some(reason)),
+        _ => return None,
+    };
+    let mut values = HashMap::new();
+    values.insert("event", format!("{:?}", hook_event));
+    values.insert("pid", pid.map(|pid| pid.to_string()).unwrap_or_default());
+    values.insert("name", name.cloned().unwrap_or_default());
+    values.insert("reason", reason.cloned().unwrap_or_default());
+    Some((hook_event, values))
+}
+
+/// Replaces the `{key}` placeholders in `template` with their values. Values are inserted as is,
+/// so placeholders in them (like a player named "{server}") stay as they are.
+fn fill_template(template: &str, values: &HashMap<&'static str, String>) -> String {
+    let mut text = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest.find('}').and_then(|end| Some((end, values.get(&rest[1..end])?)));
+        match value {
+            Some((end, value)) => {
+                text.push_str(value);
+                rest = &rest[end + 1..];
+            },
+            None => {
+                text.push('{');
+                rest = &rest[1..];
+            },
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+/// Runs the configured hooks for every event. Hooks run in the background, so a slow script or
+/// webhook never holds up the server.
+pub async fn run_hooks(config: Config, mut events: broadcast::Receiver<ServerEvent>) {
+    let client = match http_client_builder(&config.http).and_then(|builder| Ok(builder.build()?)) {
+        Ok(client) => client,
+        Err(e) => {
+            error!("[HOOKS] Failed to create the HTTP client, webhooks won't work: {:?}", e);
+            reqwest::Client::new()
+        },
+    };
+
+    let running = Arc::new(Semaphore::new(MAX_RUNNING_HOOKS));
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(missed)) => {
+                warn!("[HOOKS] Hooks fell behind, {} events were skipped", missed);
+                continue;
+            },
+            Err(RecvError::Closed) => return,
+        };
+        let Some((hook_event, mut values)) = hook_values(&event) else { continue; };
+        values.insert("server", config.general.name.clone());
+
+        for hook in config.hooks.iter().filter(|hook| hook.event == hook_event) {
+            if let Some(command) = hook.command.clone() {
+                let args: Vec<String> = hook.args.iter().map(|arg| fill_template(arg, &values)).collect();
+                let Ok(permit) = Arc::clone(&running).acquire_owned().await else { return; };
+                tokio::task::spawn_blocking(move || {
+                    let _permit = permit;
+                    match std::process::Command::new(&command).args(&args).status() {
+                        Ok(status) if !status.success() => warn!("[HOOKS] {} exited with {}", command, status),
+                        Ok(_) => {},
+                        Err(e) => error!("[HOOKS] Failed to run {}: {}", command, e),
+                    }
+                });
+            }
+            if let Some(url) = hook.url.clone() {
+                let request = client.post(&url).json(&values);
+                let Ok(permit) = Arc::clone(&running).acquire_owned().await else { return; };
+                tokio::spawn(async move {
+                    let _permit = permit;
+                    if let Err(e) = request.send().await.and_then(|response| response.error_for_status()) {
+                        error!("[HOOKS] Webhook {} failed: {}", url, e);
+                    }
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_are_filled_in_one_pass() {
+        let values = HashMap::from([("name", String::from("{server}")), ("server", String::from("Race night"))]);
+        assert_eq!(fill_template("{name} joined {server}", &values), "{server} joined Race night");
+        assert_eq!(fill_template("{unknown} {name", &values), "{unknown} {name");
+        assert_eq!(fill_template("{{name}}", &values), "{{server}}");
+    }
+}
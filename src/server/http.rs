@@ -1,14 +1,208 @@
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{
-    tcp::{OwnedReadHalf, OwnedWriteHalf},
-    TcpStream,
-};
+use std::io::SeekFrom;
+use std::path::Path;
+use std::time::Duration;
 
-pub async fn handle_http_get(socket: TcpStream) {
-    let (read_half, write_half) = socket.into_split();
-    todo!()
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::config::Config;
+use crate::fs_util;
+
+/// Largest request (request line + headers) we accept.
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+/// How long a client gets to send the request line and headers.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Handles an HTTP GET request on the game port. The leading `GET ` has already been read.
+/// Serves the client resources under `/mods/<name>` (with support for range requests),
+/// a list of them under `/mods` and the mod manifest under `/manifest`.
+pub async fn handle_http_get(mut socket: TcpStream, config: &Config) {
+    let request = match read_get_request(&mut socket).await {
+        Ok(request) => request,
+        Err(e) => {
+            debug!("[HTTP] Failed to read request: {:?}", e);
+            return;
+        }
+    };
+    trace!("[HTTP] GET {} (range: {:?})", request.path, request.range);
+
+    if let Err(e) = respond(&mut socket, config, &request).await {
+        debug!("[HTTP] Failed to respond to request for {}: {:?}", request.path, e);
+    }
+}
+
+struct GetRequest {
+    path: String,
+    /// Requested byte range, with an optional (inclusive) end
+    range: Option<(u64, Option<u64>)>,
+}
+
+async fn read_get_request(socket: &mut TcpStream) -> anyhow::Result<GetRequest> {
+    let head = read_request_head(socket, MAX_REQUEST_SIZE).await?;
+    // The request line looks like `/path HTTP/1.1`, as `GET ` was already read
+    let path = head.request_line.split(' ').next().ok_or(HttpError::BadRequest)?.to_string();
+    let range = head.header("range").and_then(parse_range);
+    Ok(GetRequest { path, range })
+}
+
+/// The request line and headers of an HTTP request.
+pub(super) struct RequestHead {
+    pub request_line: String,
+    headers: Vec<(String, String)>,
+    /// What was read past the headers, the start of the body.
+    pub rest: Vec<u8>,
+}
+
+impl RequestHead {
+    /// Returns the value of a header, ignoring the case of its name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+}
+
+/// Reads the request line and headers. Gives up on requests larger than `max_size`, or that
+/// take longer than `REQUEST_TIMEOUT` to arrive, so slow clients can't hold connections open.
+pub(super) async fn read_request_head(socket: &mut TcpStream, max_size: usize) -> anyhow::Result<RequestHead> {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 1024];
+    let header_end = tokio::time::timeout(REQUEST_TIMEOUT, async {
+        loop {
+            if let Some(pos) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+                return Ok(pos + 4);
+            }
+            let n = socket.read(&mut buf).await?;
+            if n == 0 || data.len() > max_size {
+                return Err(anyhow::Error::from(HttpError::BadRequest));
+            }
+            data.extend_from_slice(&buf[..n]);
+        }
+    }).await.map_err(|_| HttpError::BadRequest)??;
+
+    let text = String::from_utf8_lossy(&data[..header_end]);
+    let mut lines = text.split("\r\n");
+    let request_line = lines.next().ok_or(HttpError::BadRequest)?.to_string();
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+    Ok(RequestHead { request_line, headers, rest: data[header_end..].to_vec() })
+}
+
+/// Parses a `bytes=start-end` range header. Only a single range is supported.
+fn parse_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (start, end) = value.strip_prefix("bytes=")?.split_once('-')?;
+    let start = start.trim().parse().ok()?;
+    let end = if end.trim().is_empty() { None } else { Some(end.trim().parse().ok()?) };
+    Some((start, end))
+}
+
+async fn respond(socket: &mut TcpStream, config: &Config, request: &GetRequest) -> anyhow::Result<()> {
+    if !config.mods_settings.serve_over_http {
+        return write_status(socket, "404 Not Found").await;
+    }
+
+    let path = percent_decode(&request.path);
+    if path == "/mods" || path == "/mods/" {
+        let mut list = String::new();
+        for bmod in crate::mods::get_mods() {
+            list.push_str(&format!("{};{}\n", bmod.name, bmod.size));
+        }
+        return write_body(socket, "text/plain", &list).await;
+    }
+    if path == "/manifest" {
+        return write_body(socket, "application/json", &crate::mods::manifest_json()).await;
+    }
+
+    let Some(mod_name) = path.strip_prefix("/mods/") else {
+        return write_status(socket, "404 Not Found").await;
+    };
+    let client_resources = config.general.get_client_resource_folder()?;
+    let Ok(mod_path) = fs_util::join_path_secure(Path::new(&client_resources), Path::new(mod_name)) else {
+        return write_status(socket, "404 Not Found").await;
+    };
+    if !tokio::fs::metadata(&mod_path).await.is_ok_and(|metadata| metadata.is_file()) {
+        return write_status(socket, "404 Not Found").await;
+    }
+
+    let mut file = tokio::fs::File::open(&mod_path).await?;
+    let file_size = file.metadata().await?.len();
+
+    let (start, end, header) = match request.range {
+        Some((start, end)) => {
+            let end = end.unwrap_or(file_size.saturating_sub(1)).min(file_size.saturating_sub(1));
+            if start >= file_size || start > end {
+                let header = format!("HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */{}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", file_size);
+                socket.write_all(header.as_bytes()).await?;
+                return Ok(());
+            }
+            let header = format!(
+                "HTTP/1.1 206 Partial Content\r\nContent-Type: application/zip\r\nAccept-Ranges: bytes\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                start, end, file_size, end - start + 1,
+            );
+            (start, end + 1, header)
+        },
+        None => {
+            let header = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/zip\r\nAccept-Ranges: bytes\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                file_size,
+            );
+            (0, file_size, header)
+        },
+    };
+
+    socket.write_all(header.as_bytes()).await?;
+    file.seek(SeekFrom::Start(start)).await?;
+    let mut remaining = end - start;
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+    while remaining > 0 {
+        let to_read = (remaining as usize).min(CHUNK_SIZE);
+        let n = file.read(&mut chunk[..to_read]).await?;
+        if n == 0 {
+            break;
+        }
+        socket.write_all(&chunk[..n]).await?;
+        remaining -= n as u64;
+    }
+    debug!("[HTTP] Sent {} ({} bytes)", mod_name, end - start);
+
+    Ok(())
+}
+
+pub(super) async fn write_body(socket: &mut TcpStream, content_type: &str, body: &str) -> anyhow::Result<()> {
+    let header = format!("HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", content_type, body.len());
+    socket.write_all(header.as_bytes()).await?;
+    socket.write_all(body.as_bytes()).await?;
+    Ok(())
+}
+
+pub(super) async fn write_status(socket: &mut TcpStream, status: &str) -> anyhow::Result<()> {
+    let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+    socket.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+/// Decodes `%XX` escapes in a url path. Invalid escapes are left as is.
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Some(byte) = std::str::from_utf8(&bytes[i + 1..i + 3]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
 }
 
-async fn read_get_request(socket: &mut OwnedReadHalf) {
-    todo!()
+#[derive(Debug, thiserror::Error)]
+pub enum HttpError {
+    #[error("Bad request")]
+    BadRequest,
 }
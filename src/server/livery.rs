@@ -0,0 +1,109 @@
+use serde_json::Value;
+
+use crate::config::{Config, DriverSettings, EditMode};
+
+/// The part of a car config that decides what the car looks like: the skin (the part in the
+/// `paint_design` slot) and the paints.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Livery {
+    pub paint_design: Option<Value>,
+    pub paints: Option<Value>,
+}
+
+impl Livery {
+    /// Reads the livery from the car config a client sent. Returns `None` if it isn't valid JSON.
+    pub fn from_car_json(car_json: &str) -> Option<Self> {
+        let car: Value = serde_json::from_str(car_json).ok()?;
+        Some(Self {
+            paint_design: car.pointer("/vcf/parts/paint_design").cloned(),
+            paints: car.pointer("/vcf/paints").cloned(),
+        })
+    }
+
+    /// The livery an organizer assigned to a driver, if any.
+    pub fn assigned(driver: &DriverSettings) -> Option<Self> {
+        let livery = driver.livery.as_ref()?;
+        Some(Self {
+            paint_design: livery.paint_design.clone().map(Value::String),
+            paints: livery.paints.clone().map(Value::Array),
+        })
+    }
+
+    /// Returns the parts of the car config that change how the car drives: the model, the parts
+    /// (without the skin) and the tuning variables. Everything else, like the paints, the plate
+    /// or the license name, is cosmetic.
+    fn mechanical_config(car_json: &str) -> Option<Value> {
+        let car: Value = serde_json::from_str(car_json).ok()?;
+        let mut parts = car.pointer("/vcf/parts").cloned();
+        if let Some(parts) = parts.as_mut().and_then(Value::as_object_mut) {
+            parts.remove("paint_design");
+        }
+        Some(serde_json::json!({
+            "jbm": car.get("jbm"),
+            "parts": parts,
+            "vars": car.pointer("/vcf/vars"),
+        }))
+    }
+
+    /// Returns the car config with this livery instead of its own. Parts of the livery that
+    /// aren't set are left alone. Returns `None` if the car config isn't valid JSON.
+    pub fn apply(&self, car_json: &str) -> Option<String> {
+        let mut car: Value = serde_json::from_str(car_json).ok()?;
+        let vcf = car.as_object_mut()?.entry("vcf").or_insert_with(|| Value::Object(Default::default()));
+        let vcf = vcf.as_object_mut()?;
+        if let Some(paints) = &self.paints {
+            vcf.insert(String::from("paints"), paints.clone());
+        }
+        if let Some(paint_design) = &self.paint_design {
+            let parts = vcf.entry("parts").or_insert_with(|| Value::Object(Default::default()));
+            parts.as_object_mut()?.insert(String::from("paint_design"), paint_design.clone());
+        }
+        Some(car.to_string())
+    }
+}
+
+/// Replaces the livery of a spawned car with the one assigned to the driver, if liveries are enforced.
+pub fn enforce_assigned_livery(config: &Config, driver: Option<&DriverSettings>, car_json: &str) -> String {
+    if !config.liveries.enforce {
+        return car_json.to_string();
+    }
+    driver
+        .and_then(Livery::assigned)
+        .and_then(|livery| livery.apply(car_json))
+        .unwrap_or_else(|| car_json.to_string())
+}
+
+/// Checks an edit of a car. If liveries are locked and the edit changes the livery, the edit is
+/// returned with the previous livery, and `true` to let the player know.
+pub fn check_livery_edit(config: &Config, driver: Option<&DriverSettings>, old_json: &str, new_json: &str) -> (String, bool) {
+    let new_json = enforce_assigned_livery(config, driver, new_json);
+    if !config.liveries.locked {
+        return (new_json, false);
+    }
+    let (Some(old), Some(new)) = (Livery::from_car_json(old_json), Livery::from_car_json(&new_json)) else {
+        return (new_json, false);
+    };
+    if old == new {
+        return (new_json, false);
+    }
+    match old.apply(&new_json) {
+        Some(reverted) => (reverted, true),
+        None => (new_json, false),
+    }
+}
+
+/// Returns true if the edit is allowed by the edit mode. Cosmetic edits may only change how the
+/// car looks, so the car model, parts and tuning have to stay the same.
+pub fn is_edit_allowed(config: &Config, old_json: &str, new_json: &str) -> bool {
+    match config.edits.mode {
+        EditMode::Any => true,
+        EditMode::None => false,
+        EditMode::Cosmetic => {
+            let (Some(old), Some(new)) = (Livery::mechanical_config(old_json), Livery::mechanical_config(new_json)) else {
+                // Nothing to compare with, so there's no telling what changed
+                return false;
+            };
+            old == new
+        },
+    }
+}
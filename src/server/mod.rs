@@ -1,30 +1,92 @@
 use std::net::SocketAddr;
-use std::sync::Arc;
-use std::time::Instant;
+use std::path::Path;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 use std::collections::HashMap;
 
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpListener, UdpSocket};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
 use tokio::task::{JoinHandle, JoinSet};
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{mpsc, oneshot, watch};
 
+use futures::FutureExt;
 use glam::*;
 
+mod api;
+mod auth;
 mod backend;
+mod capture;
 mod car;
+mod car_class;
+mod chat;
 mod client;
+mod commands;
+mod connection_quality;
+mod error;
+mod events;
+mod garage;
+mod hooks;
+mod http;
+mod livery;
+mod moderation;
 mod packet;
+mod player_count_rules;
 mod plugins;
-mod http;
-
+mod props;
+mod race_control;
+mod radar;
+mod reports;
+mod shaping;
+mod snapshot;
+mod store;
+mod teleport;
+mod traffic;
+mod world;
+
+pub use api::*;
+pub use auth::*;
 pub use backend::*;
+pub use capture::*;
 pub use car::*;
+pub use car_class::*;
+pub use chat::*;
 pub use client::*;
+pub use commands::*;
+pub use connection_quality::*;
+pub use error::*;
+pub use events::*;
+pub use garage::*;
+pub use hooks::*;
+pub use http::*;
+pub use livery::*;
+pub use moderation::*;
 pub use packet::*;
+pub use player_count_rules::*;
 pub use plugins::*;
-pub use http::*;
-
-pub use crate::config::Config;
+pub use race_control::*;
+pub use radar::*;
+pub use reports::*;
+pub use snapshot::*;
+pub use store::*;
+pub use teleport::*;
+pub use traffic::*;
+pub use world::*;
+
+pub use crate::config::{Config, EditMode, TrafficPolicy};
+use crate::geoip::GeoIpDatabase;
+use crate::heartbeat::HeartbeatHealth;
+
+/// Largest UDP packet we accept.
+const UDP_PACKET_SIZE: usize = 4096;
+/// Maximum amount of UDP packets read in one go, so TCP packets and ticks don't starve.
+const UDP_BATCH_SIZE: usize = 64;
+
+/// Each plugin gets its own seed, based on the name of its folder.
+fn seed_for(plugin_path: &std::path::Path) -> u64 {
+    let name = plugin_path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    crate::seed::seed_for(&format!("plugin:{}", name))
+}
 
 fn load_plugins(server_resource_folder: String) -> Vec<Plugin> {
     let mut plugins = Vec::new();
@@ -47,7 +109,7 @@ fn load_plugins(server_resource_folder: String) -> Vec<Plugin> {
                                         if let Ok(src) = std::fs::read_to_string(&path) {
                                             let extension = path.extension().map(|s| s.to_string_lossy().to_string()).unwrap_or(String::new());
                                             if let Some(backend) = match extension.as_str() {
-                                                "lua" => Some(Box::new(backend_lua::BackendLua::new())),
+                                                "lua" => Some(Box::new(backend_lua::BackendLua::new(seed_for(&res_path)))),
                                                 _ => None,
                                             } {
                                                 debug!("Loading plugin: {:?}", res_path);
@@ -70,55 +132,102 @@ fn load_plugins(server_resource_folder: String) -> Vec<Plugin> {
     plugins
 }
 
-#[derive(PartialEq, Eq, Clone, Debug, Default)]
+/// Live data of a car, shown in the TUI.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct CarStatus {
+    pub player_id: u8,
+    pub car_id: u8,
+    /// Rounded to whole km/h, so the status doesn't change with every position update.
+    pub speed_kmh: f64,
+    /// Longitudinal and lateral acceleration in g, rounded to 0.1 g.
+    pub longitudinal_g: f64,
+    pub lateral_g: f64,
+}
+
+#[derive(PartialEq, Clone, Debug, Default)]
 pub struct ServerStatus {
     pub player_count: usize,
     pub player_list: Vec<(u8, String)>,
+    /// Regions of the players, if GeoIP is enabled.
+    pub player_regions: HashMap<u8, String>,
+    pub cars: Vec<CarStatus>,
     pub max_players: usize,
+    pub heartbeat: crate::heartbeat::HeartbeatHealth,
+    /// Duration of the slowest server tick in the last second, in milliseconds.
+    pub tick_time_ms: u64,
 }
 
-pub async fn read_tcp(clients: &mut Vec<Client>) -> anyhow::Result<Option<(usize, RawPacket)>> {
-    let (result, index, _) = futures::future::select_all(
-        clients.iter_mut().map(|client| Box::pin(client.process_blocking()))
-    ).await;
+/// Packets read by the reader tasks of the clients, together with the id and session of the client.
+pub type TcpRead = (u8, u64, anyhow::Result<RawPacket>);
 
-    Ok(match result {
-        Ok(packet_opt) => {
-            if let Some(raw_packet) = packet_opt {
-                Some((index, raw_packet))
-            } else {
-                None
-            }
-        },
-        Err(e) => {
-            if let Some(client) = clients.get_mut(index) {
-                client.kick(&format!("Kicked: {:?}", e)).await;
-            }
-            None
-        }
-    })
+/// Waits for the next packet from any client. Cancel safe, as the reading happens in the
+/// reader task of each client (see `Client::start_reading`).
+pub async fn read_tcp(tcp_rx: &mut mpsc::Receiver<TcpRead>) -> Option<TcpRead> {
+    tcp_rx.recv().await
 }
 
-pub async fn read_udp(udp_socket: &UdpSocket) -> Option<(SocketAddr, RawPacket)> {
-    let mut data = vec![0u8; 4096];
-    let data_size;
-    let data_addr;
+/// Waits for a UDP packet, then also reads whatever else already arrived (up to
+/// `UDP_BATCH_SIZE` packets), so bursts get handled before the OS buffer fills up.
+pub async fn read_udp(udp_socket: &UdpSocket) -> Vec<(SocketAddr, RawPacket)> {
+    let mut packets = Vec::new();
+    let mut data = vec![0u8; UDP_PACKET_SIZE];
 
     match udp_socket.recv_from(&mut data).await {
         Ok((0, _)) => {
             error!("UDP socket is readable, yet has 0 bytes to read!");
-            return None;
+            return packets;
+        }
+        Ok((n, addr)) => packets.push(received_udp_packet(addr, &data[..n])),
+        Err(_) => return packets,
+    }
+
+    while packets.len() < UDP_BATCH_SIZE {
+        match udp_socket.try_recv_from(&mut data) {
+            Ok((0, _)) => break,
+            Ok((n, addr)) => packets.push(received_udp_packet(addr, &data[..n])),
+            Err(_) => break,
         }
-        Ok((n, addr)) => (data_size, data_addr) = (n, addr),
-        Err(_) => return None,
     }
+    packets
+}
 
-    let packet = RawPacket {
-        header: data_size as u32,
-        data: data[..data_size].to_vec(),
-    };
+fn received_udp_packet(addr: SocketAddr, data: &[u8]) -> (SocketAddr, RawPacket) {
+    capture_packet(CaptureDirection::In, CaptureTransport::Udp, None, Some(addr), data);
+    (addr, RawPacket {
+        header: data.len() as u32,
+        data: data.to_vec(),
+    })
+}
+
+/// Binds the UDP socket with a receive buffer big enough for the expected amount of players.
+/// The OS might cap the size (on Linux at `net.core.rmem_max`), which gets logged.
+fn bind_udp_socket(port: u16, config: &Config) -> anyhow::Result<UdpSocket> {
+    let socket = socket2::Socket::new(socket2::Domain::IPV4, socket2::Type::DGRAM, Some(socket2::Protocol::UDP))?;
+    let buffer_size = config.general.udp_buffer_size();
+    if let Err(e) = socket.set_recv_buffer_size(buffer_size) {
+        warn!("Failed to set the UDP receive buffer size to {} KiB: {}", buffer_size / 1024, e);
+    }
+    // Linux reports double the size, as it includes bookkeeping overhead
+    let actual_size = socket.recv_buffer_size().map(|size| if cfg!(target_os = "linux") { size / 2 } else { size });
+    match actual_size {
+        Ok(actual) if actual < buffer_size => warn!(
+            "The UDP receive buffer is {} KiB instead of the requested {} KiB, so bursts of packets might get dropped. Raise the OS limit to fix this.",
+            actual / 1024, buffer_size / 1024,
+        ),
+        Ok(actual) => debug!("UDP receive buffer is {} KiB", actual / 1024),
+        Err(_) => {},
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddr::from(([0, 0, 0, 0], port)).into())?;
+    Ok(UdpSocket::from_std(socket.into())?)
+}
 
-    Some((data_addr, packet))
+/// Makes the OS send keepalive probes on an idle connection, so a connection that died
+/// without being closed results in a read error instead of staying open forever.
+fn enable_tcp_keepalive(socket: &TcpStream, config: &Config) -> std::io::Result<()> {
+    let interval = Duration::from_secs(config.general.keepalive_interval.max(1));
+    let keepalive = socket2::TcpKeepalive::new().with_time(interval).with_interval(interval);
+    socket2::SockRef::from(socket).set_tcp_keepalive(&keepalive)
 }
 
 pub struct Server {
@@ -129,6 +238,8 @@ pub struct Server {
     clients_queue: Vec<(Client, Vec<oneshot::Receiver<Argument>>, Vec<Argument>)>,
 
     pub clients: Vec<Client>,
+    pub tcp_rx: mpsc::Receiver<TcpRead>,
+    tcp_tx: mpsc::Sender<TcpRead>,
 
     connect_runtime_handle: JoinHandle<()>,
 
@@ -137,15 +248,38 @@ pub struct Server {
     veh_edit_queue: Vec<(RawPacket, u8, u8, String, Vec<oneshot::Receiver<Argument>>, Vec<Argument>)>,
 
     config: Arc<Config>,
+    /// Hands config changes (`set`, `reload`) to the connection runtime, so new players are
+    /// authenticated with the current roles and drivers.
+    config_tx: watch::Sender<Arc<Config>>,
+    events: EventBus,
+    race_control: RaceControl,
+    reports: IncidentReports,
+    garages: Garages,
+    /// Shared with the connection runtime, which turns banned players away.
+    bans: Arc<RwLock<BanList>>,
+    world: WorldState,
+    bookmarks: Bookmarks,
+    store: PlayerStore,
+    /// Chat commands registered by plugins, with the index of the plugin that handles them.
+    plugin_commands: HashMap<String, usize>,
+    geoip: Option<GeoIpDatabase>,
+    api_rx: mpsc::Receiver<ApiRequest>,
+    /// Kept up to date by the heartbeat task.
+    heartbeat: watch::Receiver<HeartbeatHealth>,
+    radar: Radar,
+    last_connection_update: Instant,
+    player_count_rules: PlayerCountRules,
 
     last_plist_update: Instant,
+    /// Reference point for the shared server clock, see `Server::server_time`.
+    start_time: Instant,
 
     plugins: Vec<Plugin>,
 }
 
 impl Server {
-    pub async fn new(config: Arc<Config>) -> anyhow::Result<Self> {
-        let config_ref = Arc::clone(&config);
+    pub async fn new(config: Arc<Config>, heartbeat: watch::Receiver<HeartbeatHealth>) -> anyhow::Result<Self> {
+        let (config_tx, config_rx) = watch::channel(Arc::clone(&config));
 
         let port = config.general.port.unwrap_or(48900);
 
@@ -155,10 +289,7 @@ impl Server {
         };
         let tcp_listener_ref = Arc::clone(&tcp_listener);
 
-        let udp_socket = {
-            let bind_addr = &format!("0.0.0.0:{}", port);
-            Arc::new(UdpSocket::bind(bind_addr).await?)
-        };
+        let udp_socket = Arc::new(bind_udp_socket(port, &config)?);
 
         let server_resource_folder = config.general
             .get_server_resource_folder()
@@ -167,8 +298,34 @@ impl Server {
         // Load existing plugins
         let plugins = load_plugins(server_resource_folder);
 
+        let garages = Garages::load(&config.garage.file);
+        let world = WorldState::load(&config.world.file);
+        let store = PlayerStore::load(&config.player_store.file);
+
+        let geoip = config.geoip.database.as_ref().and_then(|path| match GeoIpDatabase::load(Path::new(path)) {
+            Ok(db) => {
+                info!("Loaded {} IP ranges from {}", db.len(), path);
+                Some(db)
+            },
+            Err(e) => {
+                error!("Failed to load the GeoIP database {}, players won't get a region: {:?}", path, e);
+                None
+            },
+        });
+
+        let auth_provider: Arc<dyn AuthProvider> = Arc::from(create_auth_provider(&config)?);
+
+        let (api_tx, api_rx) = mpsc::channel(100);
+        if config.api.enabled {
+            tokio::spawn(serve_api(config.api.clone(), api_tx));
+        }
+
+        let bans = Arc::new(RwLock::new(BanList::load(&config.bans.file)));
+        let bans_ref = Arc::clone(&bans);
+
         // Start client runtime
         let (clients_incoming_tx, clients_incoming_rx) = mpsc::channel(100);
+        let (tcp_tx, tcp_rx) = mpsc::channel(1_000);
         debug!("Client acception runtime starting...");
         let connect_runtime_handle = tokio::spawn(async move {
             let mut set = JoinSet::new();
@@ -179,11 +336,16 @@ impl Server {
                             Ok((mut socket, addr)) => {
                                 info!("New client connected: {:?}", addr);
 
-                                let cfg_ref = config_ref.clone();
+                                let cfg_ref = Arc::clone(&config_rx.borrow());
                                 let ci_ref = clients_incoming_tx.clone();
+                                let auth_ref = auth_provider.clone();
+                                let bans_ref = bans_ref.clone();
 
                                 set.spawn(async move {
                                     socket.set_nodelay(true); // TODO: Is this good?
+                                    if let Err(e) = enable_tcp_keepalive(&socket, &cfg_ref) {
+                                        warn!("Failed to enable TCP keepalive for {:?}: {}", addr, e);
+                                    }
 
                                     socket.readable().await.expect("Failed to wait for socket to become readable!");
                                     let mut tmp = vec![0u8; 1];
@@ -195,10 +357,12 @@ impl Server {
 
                                         match code as char {
                                             'C' => {
-                                                let mut client = Client::new(socket).await;
-                                                match client.authenticate(&cfg_ref).await {
+                                                let mut client = Client::new(socket, cfg_ref.general.outbound_limit()).await;
+                                                match client.authenticate(&cfg_ref, auth_ref.as_ref(), &bans_ref).await {
                                                     Ok(is_client) if is_client => {
-                                                        ci_ref.send(client).await;
+                                                        if let Err(e) = ci_ref.send(client).await {
+                                                            error!("Failed to hand client over to the server: {:?}", e.0.id);
+                                                        }
                                                     },
                                                     Ok(_is_client) => {
                                                         debug!("Downloader?");
@@ -206,7 +370,11 @@ impl Server {
                                                     Err(e) => {
                                                         error!("Authentication error occured, kicking player...");
                                                         error!("{:?}", e);
-                                                        client.kick("Failed to authenticate player!").await;
+                                                        // Client errors are meant to be shown to the player, anything else is too technical
+                                                        match e.downcast_ref::<ClientError>() {
+                                                            Some(client_error) => client.kick(&client_error.to_string()).await,
+                                                            None => client.kick(&ClientError::AuthenticateError.to_string()).await,
+                                                        }
                                                         // client.disconnect();
                                                     }
                                                 }
@@ -238,14 +406,15 @@ impl Server {
                                                         if mod_id < 0 {
                                                             break 'download;
                                                         }
-                                                        if mod_id as usize >= cfg_ref.mods.len() {
+                                                        let mods = crate::mods::get_mods();
+                                                        if mod_id as usize >= mods.len() {
                                                             break 'download;
                                                         }
 
-                                                        let bmod = &cfg_ref.mods[mod_id as usize]; // TODO: This is a bit uhh yeah
-                                                        debug!("[D] Mod name: {}", bmod.0);
+                                                        let bmod = &mods[mod_id as usize]; // TODO: This is a bit uhh yeah
+                                                        debug!("[D] Mod name: {}", bmod.name);
 
-                                                        bmod.0.clone()
+                                                        bmod.name.clone()
                                                     };
 
                                                     if mod_name.starts_with("/") == false {
@@ -275,7 +444,7 @@ impl Server {
                                                 socket.read_exact(&mut tmp).await.expect("Failed to read from socket!");
                                                 if tmp[0] as char == 'E' && tmp[1] as char == 'T' && tmp[2] as char == ' ' {
                                                     trace!("HTTP GET request found!");
-                                                    handle_http_get(socket).await;
+                                                    handle_http_get(socket, &cfg_ref).await;
                                                 } else {
                                                     trace!("Unknown G packet received, not sure what to do!");
                                                 }
@@ -305,6 +474,15 @@ impl Server {
         });
         debug!("Client acception runtime started!");
 
+        let events = EventBus::new();
+        if config.general.log_chat {
+            tokio::spawn(log_chat(events.subscribe()));
+        }
+        if !config.hooks.is_empty() {
+            tokio::spawn(run_hooks(Config::clone(&config), events.subscribe()));
+        }
+        events.publish(ServerEvent::ServerStarted);
+
         Ok(Self {
             tcp_listener,
             udp_socket,
@@ -313,6 +491,8 @@ impl Server {
             clients_queue: Vec::new(),
 
             clients: Vec::new(),
+            tcp_rx,
+            tcp_tx,
 
             connect_runtime_handle: connect_runtime_handle,
 
@@ -321,21 +501,59 @@ impl Server {
             veh_edit_queue: Vec::new(),
 
             config: config,
+            config_tx,
+            events,
+            race_control: RaceControl::default(),
+            reports: IncidentReports::load(),
+            garages,
+            bans,
+            world,
+            bookmarks: Bookmarks::default(),
+            store,
+            plugin_commands: HashMap::new(),
+            geoip,
+            api_rx,
+            heartbeat,
+            radar: Radar::new(),
+            last_connection_update: Instant::now(),
+            player_count_rules: PlayerCountRules::default(),
 
             last_plist_update: Instant::now(),
+            start_time: Instant::now(),
 
             plugins,
         })
     }
 
+    /// Subscribes to the events of the server, see `ServerEvent`.
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<ServerEvent> {
+        self.events.subscribe()
+    }
+
     pub fn get_server_status(&self) -> ServerStatus {
         ServerStatus {
             player_count: self.clients.len(),
             player_list: self.clients.iter().map(|client| {
                 (client.id, client.get_name().to_string())
             }).collect(),
-            // max_players: self.max_players, // TODO: Support this
+            player_regions: self.clients.iter()
+                .filter_map(|client| Some((client.id, client.region.clone()?)))
+                .collect(),
+            cars: self.clients.iter().flat_map(|client| {
+                client.cars.iter().map(|(car_id, car)| {
+                    let (longitudinal_g, lateral_g) = car.g_forces();
+                    CarStatus {
+                        player_id: client.id,
+                        car_id: *car_id,
+                        speed_kmh: car.speed_kmh().round(),
+                        longitudinal_g: (longitudinal_g * 10.0).round() / 10.0,
+                        lateral_g: (lateral_g * 10.0).round() / 10.0,
+                    }
+                })
+            }).collect(),
             max_players: self.config.general.max_players,
+            heartbeat: self.heartbeat.borrow().clone(),
+            tick_time_ms: 0,
         }
     }
 
@@ -350,62 +568,114 @@ impl Server {
         }
     }
 
+    /// Handles a packet (or read error) from the reader task of a client.
+    pub async fn process_tcp_read(&mut self, id: u8, session: u64, result: anyhow::Result<RawPacket>) -> anyhow::Result<()> {
+        let Some(index) = self.clients.iter().position(|client| client.id == id && client.session == session) else {
+            // Client is already gone, its id might belong to someone else by now
+            return Ok(());
+        };
+        match result {
+            Ok(packet) => {
+                self.clients[index].mark_received();
+                self.process_tcp(index, packet).await
+            },
+            Err(e) => {
+                debug!("Client {} disconnected: {:?}", id, e);
+                self.clients[index].disconnect();
+                Ok(())
+            },
+        }
+    }
+
     pub async fn process_tcp(&mut self, index: usize, raw_packet: RawPacket) -> anyhow::Result<()> {
-        self.parse_packet(index, raw_packet).await?;
+        // A panic while handling a packet only takes down the client that sent it, not the whole server
+        let id = self.clients.get(index).map(|client| client.id);
+        match AssertUnwindSafe(self.parse_packet(index, raw_packet)).catch_unwind().await {
+            Ok(result) => result?,
+            Err(payload) => {
+                error!("Panic while handling a packet from client {:?}: {}", id, panic_message(&payload));
+                self.kick_after_panic(id).await;
+            },
+        }
 
         Ok(())
     }
 
+    /// Kicks the client after its packet handling panicked. The client's state can't be trusted anymore.
+    async fn kick_after_panic(&mut self, id: Option<u8>) {
+        if let Some(client) = self.clients.iter_mut().find(|client| Some(client.id) == id) {
+            client.kick("Internal server error").await;
+        }
+    }
+
     pub async fn process_udp(&mut self, addr: SocketAddr, packet: RawPacket) -> anyhow::Result<()> {
-        // Process UDP packets
-        // TODO: Use a UDP addr -> client ID look up table
-        if packet.data.len() == 0 {
-            return Ok(()); // what!
+        // Packets start with the client id (offset by 1) and a ':'. Anyone can send us UDP
+        // packets, so anything shorter is ignored.
+        if packet.data.len() < 2 || packet.data[0] < 1 {
+            return Ok(());
         }
-        if packet.data[0] < 1 {
-            // return Err(ServerError::BrokenPacket.into());
-            return Ok(()); // Ignore for now?
+        let id = packet.data[0] - 1;
+        // A panic while handling a packet only takes down the client that sent it, not the whole server
+        match AssertUnwindSafe(self.process_udp_from(id, addr, packet)).catch_unwind().await {
+            Ok(result) => result,
+            Err(payload) => {
+                error!("Panic while handling a UDP packet from client {}: {}", id, panic_message(&payload));
+                self.kick_after_panic(Some(id)).await;
+                Ok(())
+            },
         }
-        let id = packet.data[0] - 1; // Offset by 1
+    }
+
+    async fn process_udp_from(&mut self, id: u8, addr: SocketAddr, packet: RawPacket) -> anyhow::Result<()> {
+        // TODO: Use a UDP addr -> client ID look up table
+        let Some(index) = self.clients.iter().position(|client| client.id == id) else {
+            return Ok(());
+        };
         let data = packet.data[2..].to_vec();
-        let packet_processed = RawPacket {
+        let packet = RawPacket {
             header: data.len() as u32,
             data,
         };
-        'search: for i in 0..self.clients.len() {
-            if self.clients[i].id == id {
-                self.parse_packet_udp(i, addr, packet_processed).await?;
-                break 'search;
-            }
-        }
-
-        Ok(())
+        self.parse_packet_udp(index, addr, packet).await
     }
 
     async fn process_authenticated_clients(&mut self) -> anyhow::Result<()> {
-        // Bit weird, but this is all to avoid deadlocking the server if anything goes wrong
-        // with the client acception runtime. If that one locks, the server won't accept
-        // more clients, but it will at least still process all other clients
+        // Authenticated clients are handed over by the acception runtime through a channel, so
+        // neither side ever waits on the other. Take every client that's waiting, not just one
+        // per tick, so players joining at the same time don't queue up behind each other.
         let mut joined_names = Vec::new();
-        match self.clients_incoming_rx.try_recv() {
-            Ok(client) => {
-                let userdata = client.get_userdata();
-                let (name, role, is_guest, beammp_id) = (userdata.username.clone(), userdata.roles.clone(), userdata.guest, userdata.uid.clone());
-                info!("Welcome {name}!");
-                joined_names.push(name.clone());
-                let mut vrx = Vec::new();
-                for plugin in &self.plugins {
-                    let (tx, rx) = oneshot::channel();
-                    plugin.send_event(PluginBoundPluginEvent::CallEventHandler((ScriptEvent::OnPlayerAuthenticated { name: name.clone(), role: role.clone(), is_guest, identifiers: PlayerIdentifiers {
-                        ip: String::from("not yet implemented"),
-                        beammp_id: beammp_id.clone(),
-                    } }, Some(tx)))).await;
-                    vrx.push(rx);
-                }
-                self.clients_queue.push((client, vrx, Vec::new()));
-            },
-            Err(mpsc::error::TryRecvError::Empty) => {},
-            Err(e) => error!("Error while receiving new clients from acception runtime: {:?}", e),
+        loop {
+            let mut client = match self.clients_incoming_rx.try_recv() {
+                Ok(client) => client,
+                Err(mpsc::error::TryRecvError::Empty) => break,
+                Err(e) => {
+                    error!("Error while receiving new clients from acception runtime: {:?}", e);
+                    break;
+                },
+            };
+            if self.clients.len() + self.clients_queue.len() >= self.config.general.max_players {
+                info!("Turning {} away, the server is full", client.get_name());
+                client.kick(&ClientError::ServerFull.to_string()).await;
+                continue;
+            }
+            let userdata = client.get_userdata();
+            let (name, role, is_guest) = (userdata.username.clone(), userdata.roles.clone(), userdata.guest);
+            client.region = self.geoip.as_ref()
+                .zip(client.tcp_addr)
+                .and_then(|(db, addr)| db.lookup(addr.ip()))
+                .map(String::from);
+            match &client.region {
+                Some(region) => info!("Welcome {name} ({region})!"),
+                None => info!("Welcome {name}!"),
+            }
+            joined_names.push(name.clone());
+            let mut vrx = Vec::new();
+            for plugin in &self.plugins {
+                let (tx, rx) = oneshot::channel();
+                plugin.send_event(PluginBoundPluginEvent::CallEventHandler((ScriptEvent::OnPlayerAuthenticated { name: name.clone(), role: role.clone(), is_guest, identifiers: client.get_identifiers() }, Some(tx)))).await;
+                vrx.push(rx);
+            }
+            self.clients_queue.push((client, vrx, Vec::new()));
         }
 
         // Bit scuffed but it just polls the return values until all lua plugins have returned
@@ -435,6 +705,10 @@ impl Server {
                 }
                 if allowed {
                     let pid = client.id;
+                    // Downloading mods doesn't count towards the connection timeout
+                    client.mark_received();
+                    client.start_reading(self.tcp_tx.clone());
+                    self.events.publish(ServerEvent::PlayerJoined { pid, name: client.get_name().to_string() });
                     self.clients.push(client);
 
                     for plugin in &mut self.plugins {
@@ -460,7 +734,8 @@ impl Server {
         let mut new_queue = Vec::new();
         let mut to_send = Vec::new();
         for (pid, pname, mut message, resp, mut next_plugin_id) in self.chat_queue.drain(..) {
-            if next_plugin_id <= self.plugins.len() {
+            // Wait for the plugin we asked last, then ask the next one, until every plugin had its say
+            if resp.is_some() || next_plugin_id < self.plugins.len() {
                 let mut cancel_message = false;
                 let next_resp = if let Some(mut resp) = resp {
                     match resp.try_recv() {
@@ -473,14 +748,10 @@ impl Server {
                                 _ => {},
                             }
                             trace!("message: {message}");
-                            next_plugin_id += 1;
                             None
                         },
                         Err(oneshot::error::TryRecvError::Empty) => Some(resp),
-                        Err(_) => {
-                            next_plugin_id += 1;
-                            None
-                        }
+                        Err(_) => None,
                     }
                 } else {
                     let (tx, rx) = oneshot::channel();
@@ -494,7 +765,12 @@ impl Server {
 
                 if !cancel_message { new_queue.push((pid, pname, message, next_resp, next_plugin_id)); }
             } else {
-                let packet = RawPacket::from_str(&format!("C:{pname}:{message}"));
+                let chat_name = self.clients.iter()
+                    .find(|client| client.id == pid)
+                    .map(|client| client.get_chat_name())
+                    .unwrap_or(pname);
+                let packet = RawPacket::from_str(&format!("C:{chat_name}:{message}"));
+                self.events.publish(ServerEvent::ChatReceived { pid, name: chat_name, message });
                 to_send.push(packet);
             }
         }
@@ -619,10 +895,14 @@ impl Server {
         for (packet, pid, vid, car_json) in to_send {
             // self.broadcast(Packet::Raw(packet), None).await;
             let packet = Packet::Raw(packet);
+            // An edit can turn a car into a prop or traffic car, or the other way around
+            let is_prop = self.is_prop(&car_json);
             for i in 0..self.clients.len() {
                 if self.clients[i].id == pid {
                     if let Some(car) = self.clients[i].get_car_mut(vid) {
                         car.car_json = car_json.clone();
+                        car.prop = is_prop;
+                        car.traffic = is_traffic(&car_json);
                     }
                 } else {
                     // Already looping so more efficient to send here
@@ -662,10 +942,7 @@ impl Server {
                     },
                     ServerBoundPluginEvent::RequestPlayerIdentifiers((pid, responder)) => {
                         if let Some(client) = self.clients.iter().find(|client| client.id == pid) {
-                            let _ = responder.send(PluginBoundPluginEvent::PlayerIdentifiers(PlayerIdentifiers {
-                                ip: String::from("not yet implemented"),
-                                beammp_id: client.get_userdata().uid,
-                            }));
+                            let _ = responder.send(PluginBoundPluginEvent::PlayerIdentifiers(client.get_identifiers()));
                         } else {
                             let _ = responder.send(PluginBoundPluginEvent::None);
                         }
@@ -701,10 +978,43 @@ impl Server {
                         self.send_chat_message(&msg, pid).await;
                     },
 
+                    ServerBoundPluginEvent::TriggerGlobalEvent((name, args)) => {
+                        for plugin in &self.plugins {
+                            plugin.send_event(PluginBoundPluginEvent::CallEventHandler((ScriptEvent::Custom { name: name.clone(), args: args.clone() }, None))).await;
+                        }
+                    },
+
+                    ServerBoundPluginEvent::RequestPlayerValue((pid, key, responder)) => {
+                        let value = self.store_key(pid).and_then(|id| self.store.get(&id, &key).cloned());
+                        let _ = responder.send(PluginBoundPluginEvent::PlayerValue(value));
+                    },
+                    ServerBoundPluginEvent::SetPlayerValue((pid, key, value)) => {
+                        if let Some(id) = self.store_key(pid) {
+                            self.store.set(&id, &key, value);
+                        }
+                    },
+                    ServerBoundPluginEvent::IncrementPlayerValue((pid, key, amount, responder)) => {
+                        let response = match self.store_key(pid).map(|id| self.store.increment(&id, &key, amount)) {
+                            Some(Ok(value)) => PluginBoundPluginEvent::PlayerValue(Some(value)),
+                            Some(Err(e)) => PluginBoundPluginEvent::PlayerValueError(e),
+                            None => PluginBoundPluginEvent::PlayerValue(None),
+                        };
+                        let _ = responder.send(response);
+                    },
+
+                    ServerBoundPluginEvent::RegisterCommand(command) => {
+                        if is_builtin_command(&command) {
+                            error!("A plugin tried to register the command '{}', which already exists", command);
+                        } else {
+                            self.plugin_commands.insert(command, i);
+                        }
+                    },
+
                     _ => {},
                 }
             }
         }
+        self.store.save_if_changed(&self.config.player_store.file);
 
         Ok(())
     }
@@ -715,66 +1025,91 @@ impl Server {
         self.process_veh_spawns().await;
         self.process_veh_edits().await;
         self.process_lua_events().await?;
+        self.process_api_requests().await;
+        self.disconnect_silent_clients();
+        self.disconnect_backed_up_clients();
+        self.update_radar().await;
+        self.update_connection_quality().await;
 
-        // I'm sorry for this code :(
-        // TODO: Clean this up. We should just grab the client once with `if let Some() = expr {}`
-        for i in 0..self.clients.len() {
-            if self.clients.get(i).ok_or(ServerError::ClientDoesntExist)?.state == ClientState::Disconnect {
-                let id = self.clients.get(i).ok_or(ServerError::ClientDoesntExist)?.id;
-                for j in 0..self.clients.get(i).ok_or(ServerError::ClientDoesntExist)?.cars.len() {
-                    let car_id = self.clients.get(i).ok_or(ServerError::ClientDoesntExist)?.cars[j].0;
-                    let delete_packet = format!("Od:{}-{}", id, car_id);
-                    self.broadcast(Packet::Raw(RawPacket::from_str(&delete_packet)), None)
-                        .await;
-                }
-
-                let name = self.clients.get(i).ok_or(ServerError::ClientDoesntExist)?.get_name().to_string();
-                for plugin in &mut self.plugins {
-                    plugin.send_event(PluginBoundPluginEvent::CallEventHandler((ScriptEvent::OnPlayerDisconnect { pid: id, name: name.clone() }, None))).await;
-                }
+        self.remove_disconnected_clients().await;
+        self.apply_player_count_rules().await;
+        self.update_player_list().await;
 
-                {
-                    let mut lock = CLIENT_MOD_PROGRESS.lock().await;
-                    lock.insert(id, -1);
-                }
+        Ok(())
+    }
 
-                info!("Disconnecting client {}...", id);
-                self.broadcast(Packet::Notification(NotificationPacket::player_left( // broadcast left message
-                    self.clients[i].info.as_ref().unwrap().username.clone()
-                )), Some(i as u8)).await;
+    /// Removes the clients that are disconnecting, deleting their cars and letting everyone know.
+    async fn remove_disconnected_clients(&mut self) {
+        let mut i = 0;
+        while i < self.clients.len() {
+            if self.clients[i].state != ClientState::Disconnect {
+                i += 1;
+                continue;
+            }
+            let id = self.clients[i].id;
+            let name = self.clients[i].get_name().to_string();
+            self.store_garage(i);
+            self.store.save(&self.config.player_store.file);
+            let car_ids: Vec<u8> = self.clients[i].cars.iter().map(|(car_id, _)| *car_id).collect();
+            for car_id in car_ids {
+                let delete_packet = format!("Od:{}-{}", id, car_id);
+                self.broadcast(Packet::Raw(RawPacket::from_str(&delete_packet)), None).await;
+            }
 
-                if i == self.clients.len() - 1 {
-                    self.clients.remove(i);
-                } else {
-                    self.clients.swap_remove(i);
-                }
-                info!("Client {} disconnected!", id);
+            for plugin in &mut self.plugins {
+                plugin.send_event(PluginBoundPluginEvent::CallEventHandler((ScriptEvent::OnPlayerDisconnect { pid: id, name: name.clone() }, None))).await;
             }
-        }
 
-        // Update the player list
-        if self.last_plist_update.elapsed().as_secs() >= 1 {
-            self.last_plist_update = Instant::now();
+            CLIENT_MOD_PROGRESS.lock().await.insert(id, -1);
 
-            let mut players = String::new();
+            info!("Disconnecting client {}...", id);
+            self.broadcast(Packet::Notification(NotificationPacket::player_left(name.clone())), Some(id)).await;
 
-            for client in &self.clients {
-                players.push_str(&format!("{},", client.get_name()));
-            }
+            // Doesn't move the clients before it, so `i` is the next one to check
+            self.clients.swap_remove(i);
+            self.events.publish(ServerEvent::PlayerLeft { pid: id, name });
+            info!("Client {} disconnected!", id);
+        }
+    }
 
-            if players.ends_with(",") {
-                players.remove(players.len() - 1);
-            }
+    /// Sends everyone the player list once a second.
+    async fn update_player_list(&mut self) {
+        if self.last_plist_update.elapsed().as_secs() < 1 {
+            return;
+        }
+        self.last_plist_update = Instant::now();
 
-            let player_count = self.clients.len();
-            let max_players = self.config.general.max_players;
+        let players: Vec<&str> = self.clients.iter().map(|client| client.get_name()).collect();
+        let player_count = self.clients.len();
+        let max_players = self.config.general.max_players;
+        let data = format!("Ss{player_count}/{max_players}:{}", players.join(","));
 
-            let data = format!("Ss{player_count}/{max_players}:{players}");
+        self.broadcast(Packet::Raw(RawPacket::from_str(&data)), None).await;
+    }
 
-            self.broadcast(Packet::Raw(RawPacket::from_str(&data)), None).await;
+    /// Disconnects players that haven't sent anything within the connection timeout. Their
+    /// connection most likely died without being closed, so they'd otherwise keep their slot.
+    fn disconnect_silent_clients(&mut self) {
+        let Some(timeout) = self.config.general.connection_timeout() else {
+            return;
+        };
+        for client in &mut self.clients {
+            if client.state != ClientState::Disconnect && client.silent_for() > timeout {
+                info!("Client {} hasn't sent anything for {} seconds, disconnecting", client.id, timeout.as_secs());
+                client.disconnect();
+            }
         }
+    }
 
-        Ok(())
+    /// Disconnects players that fell so far behind on the packets sent to them that some had to
+    /// be thrown away, see `Client::is_backed_up`.
+    fn disconnect_backed_up_clients(&mut self) {
+        for client in &mut self.clients {
+            if client.state != ClientState::Disconnect && client.is_backed_up() {
+                info!("Client {} can't keep up with the packets sent to it, disconnecting", client.id);
+                client.disconnect();
+            }
+        }
     }
 
     pub async fn send_chat_message(&self, message: &str, target: Option<u8>) {
@@ -796,6 +1131,15 @@ impl Server {
 
     // NOTE: Skips all clients that are currently connecting or syncing resources!
     async fn broadcast(&self, packet: Packet, owner: Option<u8>) {
+        // Encode once, every client gets a reference to the same buffer
+        let data = match encode_tcp_packet(&packet) {
+            Ok(data) => data,
+            Err(e) => {
+                error!("Failed to encode packet for broadcast: {:?}", e);
+                return;
+            },
+        };
+        let (priority, car) = (packet.priority(), packet.car());
         for client in &self.clients {
             if let Some(id) = owner {
                 if id == client.id {
@@ -805,95 +1149,42 @@ impl Server {
             if client.state == ClientState::Connecting || client.state == ClientState::SyncingResources {
                 continue;
             }
-            client.queue_packet(packet.clone()).await;
+            client.queue_encoded(data.clone(), priority, car).await;
         }
     }
 
     async fn broadcast_udp(&self, packet: Packet, owner: Option<u8>) {
+        let data = match encode_udp_packet(&packet) {
+            Ok(data) => data,
+            Err(e) => {
+                error!("Failed to encode UDP packet for broadcast: {:?}", e);
+                return;
+            },
+        };
         for client in &self.clients {
             if let Some(id) = owner {
                 if id == client.id {
                     continue;
                 }
             }
-            // client.queue_packet(packet.clone()).await;
             if let Some(udp_addr) = client.udp_addr {
-                self.send_udp(udp_addr, &packet).await;
+                self.send_udp_encoded(udp_addr, &data);
             }
         }
     }
 
     async fn send_udp(&self, udp_addr: SocketAddr, packet: &Packet) {
-        let data = packet.get_data();
-        if data.len() > 400 {
-            trace!("Compressing...");
-            let mut compressed: Vec<u8> = Vec::with_capacity(100_000);
-            let mut compressor = flate2::Compress::new(flate2::Compression::best(), true);
-            if let Err(e) = compressor.compress_vec(
-                data,
-                &mut compressed,
-                flate2::FlushCompress::Sync,
-            ) {
-                error!("Compression failed!");
-                return;
-            }
-            let mut new_data = "ABG:".as_bytes()[..4].to_vec();
-            new_data.append(&mut compressed);
-            if let Err(e) = self.udp_socket.try_send_to(&new_data, udp_addr) {
-                error!("UDP Packet send error: {:?}", e);
-            }
-        } else {
-            if let Err(e) = self.udp_socket.try_send_to(&data, udp_addr) {
-                error!("UDP Packet send error: {:?}", e);
-            }
+        match encode_udp_packet(packet) {
+            Ok(data) => self.send_udp_encoded(udp_addr, &data),
+            Err(e) => error!("Failed to encode UDP packet: {:?}", e),
         }
     }
 
-    async fn read_udp_packets(&self) -> Vec<(SocketAddr, RawPacket)> {
-        let mut packets = Vec::new();
-        'read: loop {
-            let mut data = vec![0u8; 4096];
-            let data_size;
-            let data_addr;
-
-            match self.udp_socket.try_recv_from(&mut data) {
-                Ok((0, _)) => {
-                    error!("UDP socket is readable, yet has 0 bytes to read!");
-                    break 'read;
-                }
-                Ok((n, addr)) => (data_size, data_addr) = (n, addr),
-                Err(_) => break 'read,
-            }
-
-            let packet = RawPacket {
-                header: data_size as u32,
-                data: data[..data_size].to_vec(),
-            };
-            packets.push((data_addr, packet));
+    fn send_udp_encoded(&self, udp_addr: SocketAddr, data: &[u8]) {
+        capture_packet(CaptureDirection::Out, CaptureTransport::Udp, None, Some(udp_addr), data);
+        if let Err(e) = self.udp_socket.try_send_to(data, udp_addr) {
+            error!("UDP Packet send error: {:?}", e);
         }
-        packets
-    }
-
-    async fn read_udp_packets_blocking(&self) -> Option<(SocketAddr, RawPacket)> {
-        let mut data = vec![0u8; 4096];
-        let data_size;
-        let data_addr;
-
-        match self.udp_socket.recv_from(&mut data).await {
-            Ok((0, _)) => {
-                error!("UDP socket is readable, yet has 0 bytes to read!");
-                return None;
-            }
-            Ok((n, addr)) => (data_size, data_addr) = (n, addr),
-            Err(_) => return None,
-        }
-
-        let packet = RawPacket {
-            header: data_size as u32,
-            data: data[..data_size].to_vec(),
-        };
-
-        Some((data_addr, packet))
     }
 
     async fn parse_packet_udp(
@@ -907,30 +1198,13 @@ impl Server {
             let client_id = client.get_id();
 
             client.udp_addr = Some(udp_addr);
+            client.mark_received();
 
             // Check if compressed
-            let mut is_compressed = false;
-            if packet.data.len() > 3 {
-                let string_data = String::from_utf8_lossy(&packet.data[..4]);
-                if string_data.starts_with("ABG:") {
-                    is_compressed = true;
-                    trace!("Packet is compressed!");
-                }
-            }
-
-            if is_compressed {
-                let compressed = &packet.data[4..];
-                let mut decompressed: Vec<u8> = Vec::with_capacity(100_000);
-                let mut decompressor = flate2::Decompress::new(true);
-                decompressor.decompress_vec(
-                    compressed,
-                    &mut decompressed,
-                    flate2::FlushDecompress::Finish,
-                )?;
+            if let Some(decompressed) = decompress_packet_data(&packet.data)? {
+                trace!("Packet is compressed!");
                 packet.header = decompressed.len() as u32;
                 packet.data = decompressed;
-                // let string_data = String::from_utf8_lossy(&packet.data[..]);
-                // debug!("Unknown packet - String data: `{}`; Array: `{:?}`; Header: `{:?}`", string_data, packet.data, packet.header);
             }
 
             // Check packet identifier
@@ -941,22 +1215,17 @@ impl Server {
             } else {
                 match packet_identifier {
                     'p' => {
+                        self.clients[client_idx].connection.record_udp_ping();
                         self.send_udp(udp_addr, &Packet::Raw(RawPacket::from_code('p')))
                             .await;
                     }
                     'Z' => {
                         if packet.data.len() < 7 {
-                            error!("Position packet too small!");
-                            return Err(ServerError::BrokenPacket.into());
+                            return Err(ProtocolError::BrokenPacket { client_id, code: 'Z', reason: "position packet too small" }.into());
                         } else {
-                            // Sent as text so removing 48 brings it from [48-57] to [0-9]
-                            if packet.data[3] < 48 || packet.data[5] < 48 {
-                                return Err(ServerError::BrokenPacket.into());
-                            }
-                            let client_id = packet.data[3] - 48;
-                            let car_id = packet.data[5] - 48;
-
-                            let pos_json = &packet.data[7..];
+                            let Some((client_id, car_id, pos_json)) = parse_vehicle_ids(&packet.data) else {
+                                return Err(ProtocolError::BrokenPacket { client_id, code: 'Z', reason: "invalid client or car id" }.into());
+                            };
                             let pos_data: TransformPacket =
                                 serde_json::from_str(&String::from_utf8_lossy(pos_json))?;
 
@@ -967,7 +1236,7 @@ impl Server {
                                     let client = &mut self.clients[i];
                                     let car = client
                                         .get_car_mut(car_id)
-                                        .ok_or(ServerError::CarDoesntExist)?;
+                                        .ok_or(ProtocolError::CarDoesntExist { client_id, code: 'Z', car_id })?;
                                     car.pos = pos_data.pos.into();
                                     car.rot = DQuat::from_xyzw(
                                         pos_data.rot[0],
@@ -975,11 +1244,27 @@ impl Server {
                                         pos_data.rot[2],
                                         pos_data.rot[3],
                                     );
-                                    car.vel = pos_data.vel.into();
+                                    let impact = car.update_velocity(pos_data.vel.into(), pos_data.tim, self.config.damage.min_impact_acceleration);
                                     car.rvel = pos_data.rvel.into();
-                                    car.tim = pos_data.tim;
                                     car.ping = pos_data.ping;
                                     car.last_pos_update = Some(Instant::now());
+                                    car.record_history();
+                                    self.events.publish(ServerEvent::PositionUpdated { pid: client_id, vid: car_id, pos: car.pos, vel: car.vel });
+
+                                    if impact.is_some() {
+                                        let mut to_trigger = Vec::new();
+                                        for (event_name, threshold) in &self.config.damage.events {
+                                            if car.impact_energy >= *threshold && !car.triggered_damage_events.contains(event_name) {
+                                                car.triggered_damage_events.push(event_name.clone());
+                                                to_trigger.push(event_name.clone());
+                                            }
+                                        }
+                                        for event_name in to_trigger {
+                                            debug!("Car {}-{} passed the damage threshold for '{}'", client_id, car_id, event_name);
+                                            self.clients[i].trigger_client_event(event_name, car_id.to_string()).await;
+                                        }
+                                    }
+                                    self.clients[i].connection.record_position(car_id, pos_data.tim, pos_data.ping);
                                 } else {
                                     if let Some(udp_addr) = self.clients[i].udp_addr {
                                         self.send_udp(udp_addr, &p).await;
@@ -1001,6 +1286,53 @@ impl Server {
         Ok(())
     }
 
+    /// Swaps in a changed config, for the server and the connection runtime.
+    pub(super) fn replace_config(&mut self, config: Config) {
+        self.config = Arc::new(config);
+        self.config_tx.send_replace(Arc::clone(&self.config));
+        let outbound_limit = self.config.general.outbound_limit();
+        for client in self.clients.iter().chain(self.clients_queue.iter().map(|(client, _, _)| client)) {
+            client.set_outbound_limit(outbound_limit);
+        }
+    }
+
+    /// Finds a client by player id or name, as players are given in commands and the API.
+    /// Returns the index of the client.
+    pub(super) fn find_client(&self, target: &str) -> Option<usize> {
+        let target_id = target.parse::<u8>().ok();
+        self.clients.iter().position(|client| Some(client.id) == target_id || client.get_name() == target)
+    }
+
+    /// Seconds since the server started. Used as the shared clock for everything clients
+    /// need to agree on (countdowns, timers), see the `TimeSyncRequest` event.
+    pub fn server_time(&self) -> f64 {
+        self.start_time.elapsed().as_secs_f64()
+    }
+
+    /// Handles events triggered by client side mods (`E:<event>:<data>`).
+    async fn parse_client_event(&mut self, client_idx: usize, packet: &RawPacket) {
+        let packet_data = packet.data_as_string();
+        let contents: Vec<&str> = packet_data.splitn(3, ':').collect();
+        if contents.len() < 3 {
+            debug!("Client event of invalid format: `{}`", packet_data);
+            return;
+        }
+        match contents[1] {
+            // The client sends its own clock in the data and gets it back together with the
+            // server clock at the moment of answering. With the round trip time it can then
+            // estimate the offset to the server clock: server_time + rtt / 2 - client_now.
+            "TimeSyncRequest" => {
+                let client_time: f64 = contents[2].trim().parse().unwrap_or(0.0);
+                let data = serde_json::json!({
+                    "clientTime": client_time,
+                    "serverTime": self.server_time(),
+                });
+                self.clients[client_idx].trigger_client_event("TimeSync", data.to_string()).await;
+            },
+            event_name => debug!("Unhandled client event '{}' with data '{}'", event_name, contents[2]),
+        }
+    }
+
     async fn parse_packet(
         &mut self,
         client_idx: usize,
@@ -1013,28 +1345,9 @@ impl Server {
             };
 
             // Check if compressed
-            let mut is_compressed = false;
-            if packet.data.len() > 3 {
-                let string_data = String::from_utf8_lossy(&packet.data[..4]);
-                if string_data.starts_with("ABG:") {
-                    is_compressed = true;
-                    // trace!("Packet is compressed!");
-                }
-            }
-
-            if is_compressed {
-                let compressed = &packet.data[4..];
-                let mut decompressed: Vec<u8> = Vec::with_capacity(100_000);
-                let mut decompressor = flate2::Decompress::new(true);
-                decompressor.decompress_vec(
-                    compressed,
-                    &mut decompressed,
-                    flate2::FlushDecompress::Finish,
-                )?;
+            if let Some(decompressed) = decompress_packet_data(&packet.data)? {
                 packet.header = decompressed.len() as u32;
                 packet.data = decompressed;
-                // let string_data = String::from_utf8_lossy(&packet.data[..]);
-                // debug!("Unknown packet - String data: `{}`; Array: `{:?}`; Header: `{:?}`", string_data, packet.data, packet.header);
             }
 
             // Check packet identifier
@@ -1056,34 +1369,46 @@ impl Server {
                             ))))
                             .await;
 
+                        // Lets client side mods know which (optional) mods are available
+                        self.clients[client_idx].trigger_client_event("ModManifest", crate::mods::manifest_json()).await;
+                        self.send_world_state(client_idx).await;
+                        self.restore_garage(client_idx).await;
+
+                        let client = &self.clients[client_idx];
+                        let welcome_id = client.id;
+                        let welcome_name = match &client.region {
+                            Some(region) => format!("{} ({})", client.get_display_name(), region),
+                            None => client.get_display_name(),
+                        };
                         self.broadcast(Packet::Notification(NotificationPacket::player_welcome( // welcome the player
-                            self.clients[client_idx].info.as_ref().unwrap().username.clone()
-                        )), Some(client_idx as u8)).await;
-
-                        // TODO: Sync all existing cars on server (this code is broken)
-                        for client in &self.clients {
-                            let pid = client.id as usize;
-                            if pid != client_idx {
-                                let role = client.get_roles();
-                                for (vid, car) in &client.cars {
-                                    self.clients[client_idx]
-                                        .queue_packet(Packet::Raw(RawPacket::from_str(&format!(
-                                            "Os:{role}:{}:{pid}-{vid}:{}",
-                                            client.get_name(),
-                                            car.car_json,
-                                        ))))
-                                        .await;
+                            welcome_name
+                        )), Some(welcome_id)).await;
+
+                        // Sync the cars that already exist, followed by where they are right now
+                        let joined_id = self.clients[client_idx].id;
+                        let mut spawns = Vec::new();
+                        let mut positions = Vec::new();
+                        for client in self.clients.iter().filter(|client| client.id != joined_id) {
+                            let pid = client.id;
+                            for (vid, car) in &client.cars {
+                                spawns.push(format!("Os:{}:{}:{pid}-{vid}:{}", client.get_roles(), client.get_name(), car.car_json));
+                                if car.last_pos_update.is_some() {
+                                    positions.push(format!("Zp:{pid}-{vid}:{}", serde_json::to_string(&car.transform())?));
                                 }
                             }
                         }
+                        for packet in spawns.iter().chain(&positions) {
+                            self.clients[client_idx].queue_packet(Packet::Raw(RawPacket::from_str(packet))).await;
+                        }
                     }
                     'O' => self.parse_vehicle_packet(client_idx, packet).await?,
+                    'E' => self.parse_client_event(client_idx, &packet).await,
                     'C' => {
                         // TODO: Separate into another runtime to avoid blocking the main one
                         //       while we wait for a response from all the plugins
-                        let playername = &self.clients[client_idx].info.as_ref().unwrap().username;
+                        let playername = self.clients[client_idx].info.as_ref().unwrap().username.clone();
                         let packet_data = packet.data_as_string();
-                        let contents: Vec<&str> = packet_data.split(":").collect();
+                        let contents: Vec<&str> = packet_data.splitn(3, ":").collect();
                         if contents.len() < 3 {
                             error!("Message Error - Message from `{}` is of invalid format", &playername);
                             return Ok(());
@@ -1093,9 +1418,35 @@ impl Server {
                             return Ok(());
                         }
 
-                        info!("[CHAT] {}", packet.data_as_string());
+                        let message = sanitize_message(contents[2], self.config.chat.max_message_length);
+                        if message.is_empty() {
+                            return Ok(());
+                        }
+
+                        match self.clients[client_idx].register_chat_message(&self.config.chat) {
+                            ChatPermission::Allowed => {},
+                            ChatPermission::Cooldown => {
+                                self.send_chat_message("You are sending messages too quickly!", Some(client_id)).await;
+                                return Ok(());
+                            },
+                            ChatPermission::Muted(remaining) => {
+                                self.send_chat_message(&format!("You are muted for {} more seconds.", remaining.as_secs() + 1), Some(client_id)).await;
+                                return Ok(());
+                            },
+                        }
+
+                        if let Some(command_line) = message.strip_prefix('!') {
+                            let args = parse_command_args(command_line);
+                            if args.first().map(|command| self.is_command(command)).unwrap_or(false) {
+                                info!("[CMD] {}: {}", playername, message);
+                                self.run_command(CommandSource::Client(client_id), &args).await;
+                                return Ok(());
+                            }
+                        }
+
+                        info!("[CHAT] {}: {}", playername, message);
                         // self.broadcast(Packet::Raw(packet), None).await;
-                        self.chat_queue.push((client_id, playername.clone(), contents[2].to_string(), None, 0));
+                        self.chat_queue.push((client_id, playername, message, None, 0));
                     }
                     _ => {
                         let string_data = String::from_utf8_lossy(&packet.data[..]);
@@ -1115,27 +1466,39 @@ impl Server {
         client_idx: usize,
         packet: RawPacket,
     ) -> anyhow::Result<()> {
+        let sender_id = self.clients.get(client_idx).ok_or(StateError::ClientDoesntExist { index: client_idx })?.id;
         if packet.data.len() < 6 {
-            error!("Vehicle packet too small!");
-            return Ok(()); // TODO: Return error here
+            return Err(ProtocolError::BrokenPacket { client_id: sender_id, code: 'O', reason: "vehicle packet too small" }.into());
         }
         let code = packet.data[1] as char;
         match code {
             's' => {
-                let client = &mut self.clients[client_idx];
-                let mut allowed = true;
-                if let Some(max_cars) = self.config.general.max_cars {
-                    if client.cars.len() >= max_cars as usize { allowed = false; }
-                }
                 // trace!("Packet string: `{}`", packet.data_as_string());
                 let split_data = packet
                     .data_as_string()
                     .splitn(3, ':')
                     .map(|s| s.to_string())
                     .collect::<Vec<String>>();
-                let car_json_str = &split_data.get(2).ok_or(std::fmt::Error)?;
+                let spawn_json = split_data.get(2).map(String::as_str).unwrap_or_default();
+                let blocked_reason = self.spectate_limit(client_idx)
+                    .or_else(|| self.prop_limit(client_idx, spawn_json))
+                    .or_else(|| self.traffic_limit(client_idx, spawn_json))
+                    .or_else(|| self.car_class_limit(client_idx, spawn_json, None));
+                let is_prop = self.is_prop(spawn_json);
+                let counts_towards_max = self.counts_towards_max_cars(is_prop, is_traffic(spawn_json));
+                let car_count = self.clients[client_idx].cars.iter()
+                    .filter(|(_, car)| self.counts_towards_max_cars(car.prop, car.traffic))
+                    .count();
+                let client = &mut self.clients[client_idx];
+                let mut allowed = blocked_reason.is_none();
+                if let Some(max_cars) = self.config.general.max_cars {
+                    if counts_towards_max && car_count >= max_cars as usize { allowed = false; }
+                }
+                let car_json_str = &enforce_assigned_livery(&self.config, client.driver.as_ref(), split_data.get(2).ok_or(std::fmt::Error)?);
                 // let car_json: serde_json::Value = serde_json::from_str(&car_json_str)?;
-                let car_id = client.register_car(Car::new(car_json_str.to_string()));
+                let mut car = Car::new(car_json_str.to_string());
+                car.prop = is_prop;
+                let car_id = client.register_car(car);
                 let client_id = client.get_id();
                 if allowed {
                     let packet_data = format!(
@@ -1186,18 +1549,51 @@ impl Server {
                     client.write_packet(Packet::Raw(response)).await;
                     client.unregister_car(car_id);
                     info!("Blocked spawn for client #{}!", client_id);
+                    if let Some(reason) = &blocked_reason {
+                        self.send_chat_message(reason, Some(client_id)).await;
+                    }
                 }
             }
             'c' => {
                 // let split_data = packet.data_as_string().splitn(3, ':').map(|s| s.to_string()).collect::<Vec<String>>();
                 // let car_json_str = &split_data.get(2).ok_or(std::fmt::Error)?;
-                if packet.data[3] < 48 || packet.data[5] < 48 {
-                    return Err(ServerError::BrokenPacket.into());
+                let Some((client_id, car_id, car_json)) = parse_vehicle_ids(&packet.data) else {
+                    return Err(ProtocolError::BrokenPacket { client_id: sender_id, code, reason: "invalid client or car id" }.into());
+                };
+                if client_id != sender_id {
+                    return Err(ProtocolError::BrokenPacket { client_id: sender_id, code, reason: "edit of a car of another client" }.into());
+                }
+                let car_json = String::from_utf8_lossy(car_json).to_string();
+                let owner = self.clients.iter().find(|client| client.id == client_id);
+                let old_json = owner
+                    .and_then(|owner| owner.cars.iter().find(|(id, _)| *id == car_id))
+                    .map(|(_, car)| car.car_json.as_str())
+                    .unwrap_or_default();
+                let driver = owner.and_then(|owner| owner.driver.as_ref());
+                if !is_edit_allowed(&self.config, old_json, &car_json) {
+                    info!("Blocked edit of car {}-{}, edits are limited to {:?}", client_id, car_id, self.config.edits.mode);
+                    let message = match self.config.edits.mode {
+                        EditMode::Cosmetic => "Only cosmetic changes are allowed right now, other players still see your car as it was.",
+                        _ => "Edits aren't allowed right now, other players still see your car as it was.",
+                    };
+                    self.send_chat_message(message, Some(client_id)).await;
+                    return Ok(());
+                }
+                if let Some(reason) = self.car_class_limit(client_idx, &car_json, Some(car_id)) {
+                    info!("Blocked edit of car {}-{}, its class is full", client_id, car_id);
+                    self.send_chat_message(&format!("{} Other players still see your car as it was.", reason), Some(client_id)).await;
+                    return Ok(());
                 }
-                let client_id = packet.data[3] - 48;
-                let car_id = packet.data[5] - 48;
-                let car_json = String::from_utf8_lossy(&packet.data[7..]).to_string();
-                let response = packet.clone();
+                let (checked_json, reverted) = check_livery_edit(&self.config, driver, old_json, &car_json);
+                if reverted {
+                    self.send_chat_message("Liveries are locked, other players still see your previous livery.", Some(client_id)).await;
+                }
+                let (car_json, response) = if checked_json != car_json {
+                    let response = RawPacket::from_str(&format!("Oc:{}-{}:{}", client_id, car_id, checked_json));
+                    (checked_json, response)
+                } else {
+                    (car_json, packet.clone())
+                };
                 let mut receivers = Vec::new();
                 for plugin in &self.plugins {
                     let (tx, rx) = oneshot::channel();
@@ -1258,12 +1654,20 @@ impl Server {
                 }
             }
             'r' => {
-                if packet.data[3] < 48 || packet.data[5] < 48 {
-                    return Err(ServerError::BrokenPacket.into());
+                let Some((client_id, car_id, car_json)) = parse_vehicle_ids(&packet.data) else {
+                    return Err(ProtocolError::BrokenPacket { client_id: sender_id, code, reason: "invalid client or car id" }.into());
+                };
+                let car_json = String::from_utf8_lossy(car_json).to_string();
+                if let Some(max_resets) = self.config.general.max_resets_per_minute {
+                    if !self.clients[client_idx].try_register_reset(max_resets) {
+                        info!("Blocked reset for client #{}!", client_id);
+                        self.send_chat_message(&format!("You can only reset {max_resets} times per minute!"), Some(client_id)).await;
+                        return Ok(());
+                    }
+                }
+                if let Some(car) = self.clients[client_idx].get_car_mut(car_id) {
+                    car.reset_damage();
                 }
-                let client_id = packet.data[3] - 48;
-                let car_id = packet.data[5] - 48;
-                let car_json = String::from_utf8_lossy(&packet.data[7..]).to_string();
                 self.broadcast(Packet::Raw(packet), Some(self.clients[client_idx].id)).await;
                 for plugin in &mut self.plugins {
                     plugin.send_event(PluginBoundPluginEvent::CallEventHandler((
@@ -1292,18 +1696,14 @@ impl Drop for Server {
     }
 }
 
-#[derive(Debug)]
-pub enum ServerError {
-    BrokenPacket,
-    CarDoesntExist,
-    ClientDoesntExist,
-}
-
-impl std::fmt::Display for ServerError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-        write!(f, "{:?}", self)?;
-        Ok(())
+/// Turns the payload of a caught panic into something that can be logged.
+pub fn panic_message(payload: &Box<dyn std::any::Any + Send>) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        String::from("Unknown panic")
     }
 }
 
-impl std::error::Error for ServerError {}
@@ -0,0 +1,146 @@
+use std::path::Path;
+use std::sync::RwLock;
+
+use serde::{Deserialize, Serialize};
+
+use super::{CommandSource, Server, ServerEvent};
+use crate::fs_util;
+
+/// A banned player. Players are matched by BeamMP ID if it's known, and by name otherwise.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Ban {
+    pub name: String,
+    pub beammp_id: Option<String>,
+    pub reason: String,
+    pub banned_by: String,
+    pub banned_at: String,
+}
+
+impl Ban {
+    fn matches(&self, beammp_id: &str, name: &str) -> bool {
+        match &self.beammp_id {
+            Some(id) => id == beammp_id,
+            None => self.name.eq_ignore_ascii_case(name),
+        }
+    }
+}
+
+/// The bans of the server, saved to `Bans.File`. Shared with the connection runtime, so banned
+/// players are turned away before they join.
+#[derive(Default)]
+pub struct BanList {
+    bans: Vec<Ban>,
+}
+
+impl BanList {
+    pub fn load(path: &str) -> Self {
+        Self { bans: fs_util::load_json(Path::new(path), "the ban list") }
+    }
+
+    /// The bans as JSON. Taken while holding the lock, and written with `save_bans` after
+    /// releasing it, so joining players don't wait on the disk.
+    fn to_json(&self) -> serde_json::Result<Vec<u8>> {
+        serde_json::to_vec_pretty(&self.bans)
+    }
+
+    /// Finds the ban of a player, if they're banned.
+    pub fn find(&self, beammp_id: &str, name: &str) -> Option<&Ban> {
+        self.bans.iter().find(|ban| ban.matches(beammp_id, name))
+    }
+}
+
+/// Returns why the player is banned, if they are.
+pub fn ban_reason(bans: &RwLock<BanList>, beammp_id: &str, name: &str) -> Option<String> {
+    bans.read().expect("Lock is poisoned!").find(beammp_id, name).map(|ban| ban.reason.clone())
+}
+
+/// Writes the bans taken with `BanList::to_json`.
+fn save_bans(path: &str, data: serde_json::Result<Vec<u8>>) {
+    let result = data.map_err(anyhow::Error::from)
+        .and_then(|data| fs_util::write_with_backup(Path::new(path), &data, 10));
+    if let Err(e) = result {
+        error!("Failed to save the ban list: {:?}", e);
+    }
+}
+
+impl Server {
+    /// Bans `target` (a player id or name) and kicks them if they're on the server. Players that
+    /// aren't on the server are banned by name.
+    pub(super) async fn ban_player(&mut self, source: CommandSource, target: &str, reason: String) {
+        let banned_by = match source {
+            CommandSource::Console => String::from("Console"),
+            CommandSource::Client(id) => self.clients.iter()
+                .find(|client| client.id == id)
+                .map(|client| client.get_name().to_string())
+                .unwrap_or_default(),
+        };
+        let client = self.find_client(target);
+        let (name, beammp_id) = match client.map(|i| &self.clients[i]) {
+            Some(client) => (client.get_name().to_string(), client.info.as_ref().filter(|info| !info.guest).map(|info| info.uid.clone())),
+            None => (target.to_string(), None),
+        };
+
+        let data = {
+            let mut bans = self.bans.write().expect("Lock is poisoned!");
+            let beammp_id_ref = beammp_id.as_deref().unwrap_or_default();
+            bans.bans.retain(|ban| !ban.matches(beammp_id_ref, &name));
+            bans.bans.push(Ban {
+                name: name.clone(),
+                beammp_id,
+                reason: reason.clone(),
+                banned_by,
+                banned_at: chrono::Local::now().to_rfc3339(),
+            });
+            bans.to_json()
+        };
+        save_bans(&self.config.bans.file, data);
+
+        info!("Banned {}: {}", name, reason);
+        if let Some(i) = client {
+            self.clients[i].kick(&format!("You have been banned from this server: {}", reason)).await;
+        }
+        self.events.publish(ServerEvent::PlayerBanned { name: name.clone(), reason });
+        self.command_reply(source, &format!("Banned {}", name)).await;
+    }
+
+    /// Kicks the client at `index` and lets everyone listening for events know.
+    pub(super) async fn kick_player(&mut self, index: usize, reason: String) {
+        let client = &mut self.clients[index];
+        info!("Kicking {}: {}", client.get_name(), reason);
+        client.kick(&reason).await;
+        self.events.publish(ServerEvent::PlayerKicked { pid: client.id, name: client.get_name().to_string(), reason });
+    }
+
+    /// Lifts the ban of a player, by name or BeamMP ID.
+    pub(super) async fn unban_player(&mut self, source: CommandSource, target: &str) {
+        let data = {
+            let mut bans = self.bans.write().expect("Lock is poisoned!");
+            let count = bans.bans.len();
+            bans.bans.retain(|ban| !ban.name.eq_ignore_ascii_case(target) && ban.beammp_id.as_deref() != Some(target));
+            (bans.bans.len() < count).then(|| bans.to_json())
+        };
+        if let Some(data) = data {
+            save_bans(&self.config.bans.file, data);
+            info!("Unbanned {}", target);
+            self.command_reply(source, &format!("Unbanned {}", target)).await;
+        } else {
+            self.command_reply(source, &format!("'{}' isn't banned", target)).await;
+        }
+    }
+
+    pub(super) async fn list_bans(&self, source: CommandSource) {
+        let list = {
+            let bans = self.bans.read().expect("Lock is poisoned!");
+            if bans.bans.is_empty() {
+                String::from("Nobody is banned.")
+            } else {
+                let mut list = String::from("Bans:");
+                for ban in &bans.bans {
+                    list.push_str(&format!("\n\t{} by {} on {}: {}", ban.name, ban.banned_by, ban.banned_at, ban.reason));
+                }
+                list
+            }
+        };
+        self.command_reply(source, &list).await;
+    }
+}
@@ -1,3 +1,4 @@
+use bytes::{BufMut, Bytes, BytesMut};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone)]
@@ -45,6 +46,46 @@ impl Packet {
     pub fn data_as_string(&self) -> String {
         String::from_utf8_lossy(&self.get_data()).to_string()
     }
+
+    pub fn priority(&self) -> PacketPriority {
+        let data = self.get_data();
+        match self.get_code() {
+            Some('Z') => PacketPriority::Position,
+            Some('J') | Some('L') => PacketPriority::Notification,
+            _ if data.starts_with(b"E:Radar:") => PacketPriority::Position,
+            // The player list, sent every second
+            _ if data.starts_with(b"Ss") => PacketPriority::Notification,
+            _ => PacketPriority::Control,
+        }
+    }
+
+    /// The car a vehicle or position packet is about, as (player id, car id).
+    pub fn car(&self) -> Option<(u8, u8)> {
+        let data = self.get_data();
+        match data.get(..2)? {
+            b"Os" => {
+                // Os:<roles>:<name>:<player id>-<car id>:<car json>
+                let ids = data.split(|&c| c == b':').nth(3)?;
+                let (player_id, car_id) = std::str::from_utf8(ids).ok()?.split_once('-')?;
+                Some((player_id.parse().ok()?, car_id.parse().ok()?))
+            },
+            [b'O' | b'Z', _] => parse_vehicle_ids(data).map(|(player_id, car_id, _)| (player_id, car_id)),
+            _ => None,
+        }
+    }
+}
+
+/// How important an outbound packet is. Clients get queued packets with the highest priority
+/// first, so state changes aren't stuck behind a pile of updates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PacketPriority {
+    /// Chat, spawns, edits, respawns, kicks, client events and other state changes
+    Control,
+    /// Positions and radar updates. The next one replaces them soon anyway, so they're
+    /// dropped when a client's outbound limit is reached.
+    Position,
+    /// Join and leave messages, and the player list
+    Notification,
 }
 
 #[derive(Debug, Clone)]
@@ -62,6 +103,77 @@ impl NotificationPacket {
     }
 }
 
+/// Compresses packet data the way BeamMP clients expect it: `ABG:` followed by the zlib stream.
+pub fn compress_packet_data(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut compressed: Vec<u8> = Vec::with_capacity(100_000);
+    let mut compressor = flate2::Compress::new(flate2::Compression::best(), true);
+    compressor.compress_vec(data, &mut compressed, flate2::FlushCompress::Sync)?;
+    let mut new_data = b"ABG:".to_vec();
+    new_data.append(&mut compressed);
+    Ok(new_data)
+}
+
+/// Decompresses packet data if it starts with `ABG:`. Returns None if the data isn't compressed.
+pub fn decompress_packet_data(data: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+    let Some(compressed) = data.strip_prefix(b"ABG:") else {
+        return Ok(None);
+    };
+    let mut decompressed: Vec<u8> = Vec::with_capacity(100_000);
+    let mut decompressor = flate2::Decompress::new(true);
+    decompressor.decompress_vec(compressed, &mut decompressed, flate2::FlushDecompress::Finish)?;
+    Ok(Some(decompressed))
+}
+
+/// Encodes a packet for sending over TCP: compressed if needed, and prefixed with its size.
+/// The result is cheap to clone, so a broadcast only has to encode a packet once.
+pub fn encode_tcp_packet(packet: &Packet) -> anyhow::Result<Bytes> {
+    let compress = match packet.get_code() {
+        Some('O') => true,
+        Some('T') => true,
+        _ => packet.get_data().len() > 400,
+    };
+
+    let mut buf;
+    if compress {
+        let data = compress_packet_data(packet.get_data())?;
+        buf = BytesMut::with_capacity(4 + data.len());
+        buf.put_u32_le(data.len() as u32);
+        buf.put_slice(&data);
+    } else {
+        buf = BytesMut::with_capacity(4 + packet.get_data().len());
+        buf.put_u32_le(packet.get_header());
+        buf.put_slice(packet.get_data());
+    }
+    Ok(buf.freeze())
+}
+
+/// Encodes a packet for sending over UDP. UDP packets have no size header, and are only
+/// compressed if they're large.
+pub fn encode_udp_packet(packet: &Packet) -> anyhow::Result<Bytes> {
+    let data = packet.get_data();
+    if data.len() > 400 {
+        Ok(Bytes::from(compress_packet_data(data)?))
+    } else {
+        Ok(Bytes::copy_from_slice(data))
+    }
+}
+
+/// Parses the ids in vehicle and position packets (`Zp:<player id>-<car id>:<data>`), returning
+/// both ids and the data after them. Ids are sent as text and can have multiple digits.
+pub fn parse_vehicle_ids(data: &[u8]) -> Option<(u8, u8, &[u8])> {
+    if data.get(2) != Some(&b':') {
+        return None;
+    }
+    let ids = &data[3..];
+    let (ids, rest) = match ids.iter().position(|&c| c == b':') {
+        Some(i) => (&ids[..i], &ids[i + 1..]),
+        None => (ids, &[][..]),
+    };
+    let ids = std::str::from_utf8(ids).ok()?;
+    let (player_id, car_id) = ids.split_once('-')?;
+    Some((player_id.parse().ok()?, car_id.parse().ok()?, rest))
+}
+
 /// Protocol:
 /// Header: 4 bytes, contains data size
 /// Data: Contains packet data
@@ -142,3 +254,152 @@ pub struct TransformPacket {
     pub rot: [f64; 4],
     pub vel: [f64; 3],
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Client to server packets in the format the BeamMP client sends them, one per file.
+    const CORPUS: &[(&str, &str)] = &[
+        ("spawn", include_str!("../../tests/fixtures/packets/spawn.txt")),
+        ("edit", include_str!("../../tests/fixtures/packets/edit.txt")),
+        ("position", include_str!("../../tests/fixtures/packets/position.txt")),
+        ("reset", include_str!("../../tests/fixtures/packets/reset.txt")),
+        ("delete", include_str!("../../tests/fixtures/packets/delete.txt")),
+        ("chat", include_str!("../../tests/fixtures/packets/chat.txt")),
+        ("event", include_str!("../../tests/fixtures/packets/event.txt")),
+    ];
+
+    fn corpus() -> impl Iterator<Item = (&'static str, RawPacket)> {
+        CORPUS.iter().map(|(name, data)| (*name, RawPacket::from_str(data.trim_end())))
+    }
+
+    fn fixture(name: &str) -> RawPacket {
+        corpus().find(|(n, _)| *n == name).unwrap().1
+    }
+
+    /// Reads a packet the way clients do: size header, then the (possibly compressed) data.
+    fn decode_tcp(encoded: &[u8]) -> Vec<u8> {
+        let size = u32::from_le_bytes(encoded[..4].try_into().unwrap()) as usize;
+        assert_eq!(size, encoded.len() - 4, "Size header doesn't match the data");
+        let data = &encoded[4..];
+        decompress_packet_data(data).unwrap().unwrap_or_else(|| data.to_vec())
+    }
+
+    #[test]
+    fn tcp_round_trip() {
+        for (name, packet) in corpus() {
+            let encoded = encode_tcp_packet(&Packet::Raw(packet.clone())).unwrap();
+            assert_eq!(decode_tcp(&encoded), packet.data, "Round trip failed for {}", name);
+        }
+    }
+
+    #[test]
+    fn udp_round_trip() {
+        for (name, packet) in corpus() {
+            let encoded = encode_udp_packet(&Packet::Raw(packet.clone())).unwrap();
+            let decoded = decompress_packet_data(&encoded).unwrap().unwrap_or_else(|| encoded.to_vec());
+            assert_eq!(decoded, packet.data, "Round trip failed for {}", name);
+        }
+    }
+
+    #[test]
+    fn vehicle_and_large_packets_are_compressed() {
+        for (name, packet) in corpus() {
+            let expect_compressed = packet.data[0] == b'O' || packet.data.len() > 400;
+            let encoded = encode_tcp_packet(&Packet::Raw(packet)).unwrap();
+            assert_eq!(encoded[4..].starts_with(b"ABG:"), expect_compressed, "Unexpected compression for {}", name);
+        }
+    }
+
+    #[test]
+    fn notification_packets() {
+        let welcome = Packet::Notification(NotificationPacket::player_welcome("alice"));
+        assert_eq!(welcome.get_code(), Some('J'));
+        assert_eq!(welcome.data_as_string(), "JWelcome alice!");
+        assert_eq!(welcome.get_header() as usize, welcome.get_data().len());
+        let left = Packet::Notification(NotificationPacket::player_left("alice"));
+        assert_eq!(decode_tcp(&encode_tcp_packet(&left).unwrap()), b"Lalice left the server!");
+    }
+
+    #[test]
+    fn position_packet_decodes() {
+        let packet = fixture("position");
+        let transform: TransformPacket = serde_json::from_slice(&packet.data[7..]).unwrap();
+        assert_eq!(transform.pos, [-717.121, 101.0, 118.675]);
+        assert_eq!(transform.rot, [0.001, -0.002, 0.924, 0.383]);
+        assert_eq!(transform.vel, [12.5, -3.25, 0.01]);
+        assert_eq!(transform.tim, 123.4567);
+        assert_eq!(transform.ping, 0.042);
+
+        let reencoded: TransformPacket = serde_json::from_str(&serde_json::to_string(&transform).unwrap()).unwrap();
+        assert_eq!(reencoded.pos, transform.pos);
+        assert_eq!(reencoded.rvel, transform.rvel);
+    }
+
+    #[test]
+    fn reset_packet_decodes() {
+        let packet = fixture("reset");
+        let respawn: RespawnPacketData = serde_json::from_slice(&packet.data[7..]).unwrap();
+        assert_eq!(respawn.pos.x, -717.121);
+        assert_eq!(respawn.rot.w, 0.383);
+    }
+
+    #[test]
+    fn spawn_and_edit_packets_carry_valid_json() {
+        for name in ["spawn", "edit"] {
+            let data = fixture(name).data_as_string();
+            let json = data.splitn(3, ':').nth(2).unwrap();
+            let car: serde_json::Value = serde_json::from_str(json).unwrap();
+            assert_eq!(car["jbm"], "pickup", "Unexpected car json in {}", name);
+        }
+    }
+
+    #[test]
+    fn vehicle_ids_can_have_multiple_digits() {
+        let position = fixture("position");
+        let (player_id, car_id, data) = parse_vehicle_ids(&position.data).unwrap();
+        assert_eq!((player_id, car_id), (0, 0));
+        assert!(data.starts_with(b"{\"rvel\""));
+
+        assert_eq!(parse_vehicle_ids(b"Zp:12-3:{}"), Some((12, 3, &b"{}"[..])));
+        assert_eq!(parse_vehicle_ids(b"Or:7-105:{\"a\":\"b:c\"}"), Some((7, 105, &b"{\"a\":\"b:c\"}"[..])));
+        assert_eq!(parse_vehicle_ids(b"Od:250-0"), Some((250, 0, &b""[..])));
+        assert_eq!(parse_vehicle_ids(b"Zp:1-:{}"), None);
+        assert_eq!(parse_vehicle_ids(b"Zp:300-1:{}"), None);
+        assert_eq!(parse_vehicle_ids(b"Zp"), None);
+    }
+
+    #[test]
+    fn packets_are_classified_by_priority() {
+        assert_eq!(Packet::Raw(fixture("position")).priority(), PacketPriority::Position);
+        assert_eq!(Packet::Raw(RawPacket::from_str("E:Radar:{\"cars\":[]}")).priority(), PacketPriority::Position);
+        assert_eq!(Packet::Raw(fixture("spawn")).priority(), PacketPriority::Control);
+        assert_eq!(Packet::Raw(fixture("chat")).priority(), PacketPriority::Control);
+        assert_eq!(Packet::Raw(RawPacket::from_str("E:Teleport:{}")).priority(), PacketPriority::Control);
+        assert_eq!(Packet::Raw(RawPacket::from_str("Snalice")).priority(), PacketPriority::Control);
+        assert_eq!(Packet::Raw(RawPacket::from_str("Ss1/8:alice")).priority(), PacketPriority::Notification);
+        assert_eq!(Packet::Notification(NotificationPacket::player_welcome("alice")).priority(), PacketPriority::Notification);
+        assert_eq!(Packet::Notification(NotificationPacket::player_left("alice")).priority(), PacketPriority::Notification);
+        assert!(PacketPriority::Control < PacketPriority::Position && PacketPriority::Position < PacketPriority::Notification);
+    }
+
+    #[test]
+    fn packets_know_their_car() {
+        assert_eq!(Packet::Raw(fixture("position")).car(), Some((0, 0)));
+        assert_eq!(Packet::Raw(RawPacket::from_str("Os:USER:alice:3-12:{\"jbm\":\"pickup\"}")).car(), Some((3, 12)));
+        assert_eq!(Packet::Raw(RawPacket::from_str("Od:3-12")).car(), Some((3, 12)));
+        assert_eq!(Packet::Raw(RawPacket::from_str("E:Radar:{\"cars\":[]}")).car(), None);
+        assert_eq!(Packet::Raw(fixture("chat")).car(), None);
+    }
+
+    #[test]
+    fn uncompressed_data_is_left_alone() {
+        assert!(decompress_packet_data(b"C:alice:hi").unwrap().is_none());
+    }
+
+    #[test]
+    fn broken_compressed_data_is_an_error() {
+        assert!(decompress_packet_data(b"ABG:definitely not zlib").is_err());
+    }
+}
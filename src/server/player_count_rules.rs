@@ -0,0 +1,63 @@
+
+use super::{Config, Server};
+
+/// The settings that player count rules can change, as they were before a rule applied.
+#[derive(Clone, Debug)]
+struct BaseSettings {
+    max_cars: Option<u8>,
+    max_resets_per_minute: Option<u32>,
+    chat_cooldown_ms: u64,
+}
+
+/// Keeps track of which player count rule is active.
+#[derive(Default)]
+pub struct PlayerCountRules {
+    active: Option<usize>,
+    /// Settings from before the first rule applied, restored once no rule applies anymore.
+    base: Option<BaseSettings>,
+}
+
+impl Server {
+    /// Applies the player count rule matching the current amount of players, if it changed.
+    pub(super) async fn apply_player_count_rules(&mut self) {
+        let player_count = self.clients.len();
+        let rule_index = self.config.player_count_rules.iter()
+            .enumerate()
+            .filter(|(_, rule)| player_count >= rule.min_players)
+            .max_by_key(|(_, rule)| rule.min_players)
+            .map(|(i, _)| i);
+        if rule_index == self.player_count_rules.active {
+            return;
+        }
+
+        let mut config = Config::clone(&self.config);
+        let base = self.player_count_rules.base.get_or_insert(BaseSettings {
+            max_cars: config.general.max_cars,
+            max_resets_per_minute: config.general.max_resets_per_minute,
+            chat_cooldown_ms: config.chat.cooldown_ms,
+        }).clone();
+        config.general.max_cars = base.max_cars;
+        config.general.max_resets_per_minute = base.max_resets_per_minute;
+        config.chat.cooldown_ms = base.chat_cooldown_ms;
+
+        let message = match rule_index.map(|i| &self.config.player_count_rules[i]) {
+            Some(rule) => {
+                config.general.max_cars = rule.max_cars.or(base.max_cars);
+                config.general.max_resets_per_minute = rule.max_resets_per_minute.or(base.max_resets_per_minute);
+                config.chat.cooldown_ms = rule.chat_cooldown_ms.unwrap_or(base.chat_cooldown_ms);
+                format!("{} or more players online, server settings changed", rule.min_players)
+            },
+            None => {
+                self.player_count_rules.base = None;
+                String::from("Server settings are back to normal")
+            },
+        };
+        info!(
+            "{} (max_cars = {:?}, max_resets = {:?}, chat_cooldown_ms = {})",
+            message, config.general.max_cars, config.general.max_resets_per_minute, config.chat.cooldown_ms,
+        );
+        self.player_count_rules.active = rule_index;
+        self.replace_config(config);
+        self.send_chat_message(&message, None).await;
+    }
+}
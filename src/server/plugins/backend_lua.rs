@@ -7,19 +7,40 @@ use super::{
 };
 
 use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::ffi::c_void;
 
 use tokio::sync::mpsc::{Sender, Receiver};
 use tokio::sync::oneshot;
 
 use mlua::prelude::*;
-use mlua::{UserData, UserDataMethods, Value, Function, Variadic};
+use mlua::{UserData, UserDataMethods, Value, Function, Variadic, StdLib, LuaOptions};
+
+/// Tables nested deeper than this aren't converted, so a plugin passing something like `_G`
+/// can't take the server down with it.
+const MAX_TABLE_DEPTH: usize = 16;
+
+/// Functions of the standard library that reach outside of the plugin: running programs,
+/// touching files, loading code from disk and stopping the server.
+const UNSAFE_GLOBALS: &[(Option<&str>, &str)] = &[
+    (None, "dofile"),
+    (None, "loadfile"),
+    (Some("os"), "execute"),
+    (Some("os"), "exit"),
+    (Some("os"), "getenv"),
+    (Some("os"), "remove"),
+    (Some("os"), "rename"),
+    (Some("os"), "tmpname"),
+];
 
 #[derive(Clone)]
 struct Context {
     tx: Arc<Sender<ServerBoundPluginEvent>>,
 
     handlers: Arc<Mutex<HashMap<String, String>>>,
+    /// Chat commands and their handlers. Separate from the event handlers, so an event can't
+    /// be triggered as if a player used the command.
+    commands: Arc<Mutex<HashMap<String, String>>>,
 }
 
 impl Context {
@@ -28,6 +49,7 @@ impl Context {
             tx,
 
             handlers: Arc::new(Mutex::new(HashMap::new())),
+            commands: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 }
@@ -45,10 +67,16 @@ impl UserData for Context {
     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
         // Implement all the API functions here
 
-        methods.add_function("RegisterEventHandler", |lua, (event_name, handler_name): (String, String)| {
-            debug!("Event handler registered: {} (EVENT) = {} (LUA)", event_name, handler_name);
+        methods.add_function("RegisterEventHandler", register_event);
+        // Name used by BeamMP server plugins
+        methods.add_function("RegisterEvent", register_event);
+
+        methods.add_function("TriggerGlobalEvent", |lua, (event_name, args): (String, Variadic<Value>)| {
             let me: Context = lua.globals().get("MP")?;
-            me.handlers.lock().expect("Lock is poisoned!").insert(event_name, handler_name);
+            let args = args.into_iter().map(value_to_arg).collect();
+            if let Err(e) = me.tx.blocking_send(ServerBoundPluginEvent::TriggerGlobalEvent((event_name, args))) {
+                error!("Failed to send packet: {:?}", e);
+            }
             Ok(())
         });
 
@@ -106,10 +134,10 @@ impl UserData for Context {
             if let Ok(message) = message {
                 match message {
                     PluginBoundPluginEvent::PlayerIdentifiers(identifiers) => {
-                        let table = lua.create_table()?;
-                        table.set("id", identifiers.ip)?;
-                        table.set("beammp_id", identifiers.beammp_id)?;
-                        Ok(Value::Table(table))
+                        match arg_to_value(lua, Argument::Table(identifiers.to_map())) {
+                            Some(value) => Ok(value),
+                            None => Ok(Value::Nil),
+                        }
                     },
                     PluginBoundPluginEvent::None => Ok(Value::Nil),
                     _ => unreachable!() // This should really never be reachable
@@ -209,19 +237,91 @@ impl UserData for Context {
             Ok(())
         });
 
+        methods.add_function("GetPlayerValue", |lua, (id, key): (u8, String)| {
+            let me: Context = lua.globals().get("MP")?;
+            let (tx, rx) = oneshot::channel();
+            if let Err(e) = me.tx.blocking_send(ServerBoundPluginEvent::RequestPlayerValue((id, key, tx))) {
+                error!("Failed to send packet: {:?}", e);
+            }
+            match rx.blocking_recv() {
+                Ok(PluginBoundPluginEvent::PlayerValue(Some(value))) => json_to_value(lua, &value),
+                Ok(PluginBoundPluginEvent::PlayerValueError(e)) => Err(LuaError::RuntimeError(e)),
+                Ok(_) => Ok(Value::Nil),
+                Err(_) => Err(LuaError::RuntimeError(String::from("The server didn't answer"))),
+            }
+        });
+
+        methods.add_function("SetPlayerValue", |lua, (id, key, value): (u8, String, Value)| {
+            let me: Context = lua.globals().get("MP")?;
+            if let Err(e) = me.tx.blocking_send(ServerBoundPluginEvent::SetPlayerValue((id, key, value_to_json(value)))) {
+                error!("Failed to send packet: {:?}", e);
+            }
+            Ok(())
+        });
+
+        methods.add_function("IncrementPlayerValue", |lua, (id, key, amount): (u8, String, f64)| {
+            let me: Context = lua.globals().get("MP")?;
+            let (tx, rx) = oneshot::channel();
+            if let Err(e) = me.tx.blocking_send(ServerBoundPluginEvent::IncrementPlayerValue((id, key, amount, tx))) {
+                error!("Failed to send packet: {:?}", e);
+            }
+            match rx.blocking_recv() {
+                Ok(PluginBoundPluginEvent::PlayerValue(Some(value))) => json_to_value(lua, &value),
+                Ok(PluginBoundPluginEvent::PlayerValueError(e)) => Err(LuaError::RuntimeError(e)),
+                Ok(_) => Ok(Value::Nil),
+                Err(_) => Err(LuaError::RuntimeError(String::from("The server didn't answer"))),
+            }
+        });
+
+        methods.add_function("RegisterCommand", |lua, (command, handler_name): (String, String)| {
+            let me: Context = lua.globals().get("MP")?;
+            me.commands.lock().expect("Lock is poisoned!").insert(command.clone(), handler_name);
+            if let Err(e) = me.tx.blocking_send(ServerBoundPluginEvent::RegisterCommand(command)) {
+                error!("Failed to send packet: {:?}", e);
+            }
+            Ok(())
+        });
+
         methods.add_function("GetOSName", |lua, ()| {
             Ok(std::env::consts::OS)
         });
     }
 }
 
+fn register_event(lua: &Lua, (event_name, handler_name): (String, String)) -> LuaResult<()> {
+    debug!("Event handler registered: {} (EVENT) = {} (LUA)", event_name, handler_name);
+    let me: Context = lua.globals().get("MP")?;
+    me.handlers.lock().expect("Lock is poisoned!").insert(event_name, handler_name);
+    Ok(())
+}
+
 pub struct BackendLua {
     lua: Lua,
 }
 
 impl BackendLua {
-    pub fn new() -> Self {
-        let lua = Lua::new();
+    /// `seed` seeds `math.random`, so scripts make the same random decisions with the same session seed.
+    pub fn new(seed: u64) -> Self {
+        // No io, package (require) or debug library, plugins only get what they need to do their job
+        let libs = StdLib::COROUTINE | StdLib::TABLE | StdLib::OS | StdLib::STRING | StdLib::UTF8 | StdLib::MATH;
+        let lua = Lua::new_with(libs, LuaOptions::default()).expect("Failed to create the Lua state");
+        let randomseed = lua.globals()
+            .get::<_, LuaTable>("math")
+            .and_then(|math| math.get::<_, Function>("randomseed"));
+        if let Err(e) = randomseed.and_then(|randomseed| randomseed.call::<_, ()>(seed as i64)) {
+            error!("[LUA] Failed to seed math.random: {:?}", e);
+        }
+
+        // Every plugin runs in the server process, so one plugin shouldn't be able to harm it
+        for (table, name) in UNSAFE_GLOBALS {
+            let removed = match table {
+                Some(table) => lua.globals().get::<_, LuaTable>(*table).and_then(|table| table.set(*name, Value::Nil)),
+                None => lua.globals().set(*name, Value::Nil),
+            };
+            if let Err(e) = removed {
+                error!("[LUA] Failed to remove {}: {:?}", name, e);
+            }
+        }
 
         Self {
             lua,
@@ -251,6 +351,8 @@ impl Backend for BackendLua {
     }
 
     fn call_event_handler(&mut self, event: ScriptEvent, resp: Option<oneshot::Sender<Argument>>) {
+        let custom_name;
+        let mut is_command = false;
         let (event_name, args) = match event {
             ScriptEvent::OnPluginLoaded => ("onInit", vec![]),
             ScriptEvent::OnShutdown => ("onShutdown", vec![]),
@@ -267,13 +369,26 @@ impl Backend for BackendLua {
             ScriptEvent::OnVehicleReset { pid, vid, car_data } => ("onVehicleReset", vec![Argument::Integer(pid as i64), Argument::Integer(vid as i64), Argument::String(car_data)]),
 
             ScriptEvent::OnChatMessage { pid, name, message } => ("onChatMessage", vec![Argument::Integer(pid as i64), Argument::String(name), Argument::String(message)]),
+
+            ScriptEvent::Custom { name, args } => {
+                custom_name = name;
+                (custom_name.as_str(), args)
+            },
+            ScriptEvent::OnCommand { pid, command, args } => {
+                custom_name = command;
+                is_command = true;
+                let mut command_args = vec![Argument::Integer(pid)];
+                command_args.extend(args.into_iter().map(Argument::String));
+                (custom_name.as_str(), command_args)
+            },
         };
 
         let mut ret = Value::Number(-1f64);
         // TODO: Error handling
         {
             let ctx: Context = self.lua.globals().get("MP").expect("MP is missing!");
-            let lock = ctx.handlers.lock().expect("Mutex is poisoned!");
+            let handlers = if is_command { &ctx.commands } else { &ctx.handlers };
+            let lock = handlers.lock().expect("Mutex is poisoned!");
             if let Some(handler_name) = lock.get(event_name) {
                 let func: LuaResult<Function> = self.lua.globals().get(handler_name.clone());
                 if let Ok(func) = func {
@@ -301,15 +416,94 @@ impl Backend for BackendLua {
 }
 
 fn value_to_arg(value: Value) -> Argument {
-    match value {
+    value_to_arg_inner(value, &mut HashSet::new()).unwrap_or(Argument::Number(-1f32))
+}
+
+/// `parents` are the tables being converted, to catch tables that contain themselves.
+fn value_to_arg_inner(value: Value, parents: &mut HashSet<*const c_void>) -> Option<Argument> {
+    Some(match value {
         Value::Boolean(b) => Argument::Boolean(b),
         Value::Integer(i) => Argument::Integer(i),
         Value::Number(f) => Argument::Number(f as f32),
         Value::String(s) => Argument::String(s.to_string_lossy().to_string()),
+        Value::Table(t) => {
+            let pointer = t.to_pointer();
+            if parents.len() >= MAX_TABLE_DEPTH || !parents.insert(pointer) {
+                return None;
+            }
+            let table = t.pairs::<Value, Value>().flatten().filter_map(|(key, value)| {
+                let key = match key {
+                    Value::String(s) => s.to_string_lossy().to_string(),
+                    Value::Integer(i) => i.to_string(),
+                    _ => return None,
+                };
+                Some((key, value_to_arg_inner(value, parents)?))
+            }).collect();
+            parents.remove(&pointer);
+            Argument::Table(table)
+        },
         _ => Argument::Number(-1f32),
+    })
+}
+
+/// Converts a value for the player store. Returns None for nil, and for values JSON can't hold.
+fn value_to_json(value: Value) -> Option<serde_json::Value> {
+    value_to_json_inner(value, &mut HashSet::new())
+}
+
+/// `parents` are the tables being converted, to catch tables that contain themselves.
+fn value_to_json_inner(value: Value, parents: &mut HashSet<*const c_void>) -> Option<serde_json::Value> {
+    match value {
+        Value::Boolean(b) => Some(serde_json::Value::from(b)),
+        Value::Integer(i) => Some(serde_json::Value::from(i)),
+        Value::Number(f) => serde_json::Number::from_f64(f).map(serde_json::Value::Number),
+        Value::String(s) => Some(serde_json::Value::from(s.to_string_lossy().to_string())),
+        Value::Table(t) => {
+            let pointer = t.to_pointer();
+            if parents.len() >= MAX_TABLE_DEPTH || !parents.insert(pointer) {
+                return None;
+            }
+            let object = t.pairs::<Value, Value>().flatten().filter_map(|(key, value)| {
+                let key = match key {
+                    Value::String(s) => s.to_string_lossy().to_string(),
+                    Value::Integer(i) => i.to_string(),
+                    _ => return None,
+                };
+                Some((key, value_to_json_inner(value, parents)?))
+            }).collect();
+            parents.remove(&pointer);
+            Some(serde_json::Value::Object(object))
+        },
+        _ => None,
     }
 }
 
+fn json_to_value<'lua>(lua: &'lua Lua, value: &serde_json::Value) -> LuaResult<Value<'lua>> {
+    Ok(match value {
+        serde_json::Value::Null => Value::Nil,
+        serde_json::Value::Bool(b) => Value::Boolean(*b),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => Value::Number(n.as_f64().unwrap_or_default()),
+        },
+        serde_json::Value::String(s) => Value::String(lua.create_string(s)?),
+        serde_json::Value::Array(values) => {
+            let table = lua.create_table()?;
+            for (i, value) in values.iter().enumerate() {
+                table.set(i + 1, json_to_value(lua, value)?)?;
+            }
+            Value::Table(table)
+        },
+        serde_json::Value::Object(values) => {
+            let table = lua.create_table()?;
+            for (key, value) in values {
+                table.set(key.as_str(), json_to_value(lua, value)?)?;
+            }
+            Value::Table(table)
+        },
+    })
+}
+
 fn arg_to_value(lua: &Lua, arg: Argument) -> Option<Value> {
     match arg {
         Argument::String(s) => if let Ok(lua_str) = lua.create_string(&s) { Some(Value::String(lua_str)) } else { None },
@@ -31,6 +31,7 @@ pub enum Argument {
 pub struct PlayerIdentifiers {
     pub ip: String,
     pub beammp_id: String,
+    pub discord_id: Option<String>,
 }
 
 impl PlayerIdentifiers {
@@ -38,6 +39,9 @@ impl PlayerIdentifiers {
         let mut m = HashMap::new();
         m.insert(String::from("ip"), Argument::String(self.ip.clone()));
         m.insert(String::from("beammp"), Argument::String(self.beammp_id.clone()));
+        if let Some(discord_id) = &self.discord_id {
+            m.insert(String::from("discord"), Argument::String(discord_id.clone()));
+        }
         m
     }
 }
@@ -91,6 +95,11 @@ pub enum ScriptEvent {
     OnVehicleReset { pid: u8, vid: u8, car_data: String },
 
     OnChatMessage { pid: u8, name: String, message: String },
+
+    /// Event triggered by a plugin with `MP.TriggerGlobalEvent`
+    Custom { name: String, args: Vec<Argument> },
+    /// A chat or console command registered with `MP.RegisterCommand`. `pid` is -1 for the console.
+    OnCommand { pid: i64, command: String, args: Vec<String> },
 }
 
 #[derive(Debug)]
@@ -105,6 +114,10 @@ pub enum PluginBoundPluginEvent {
 
     PlayerVehicles(HashMap<u8, String>),
     PositionRaw(PositionRaw),
+
+    PlayerValue(Option<serde_json::Value>),
+    /// A player value couldn't be changed, like when adding to something that isn't a number
+    PlayerValueError(String),
 }
 
 // TODO: Perhaps it would be nice to ensure each sender can only sned specifically what it needs to.
@@ -121,6 +134,17 @@ pub enum ServerBoundPluginEvent {
     RequestPositionRaw((u8, u8, oneshot::Sender<PluginBoundPluginEvent>)),
 
     SendChatMessage((isize, String)),
+
+    /// Triggers a custom event in every plugin, including the one that sent it
+    TriggerGlobalEvent((String, Vec<Argument>)),
+
+    /// Player values, see `PlayerStore`
+    RequestPlayerValue((u8, String, oneshot::Sender<PluginBoundPluginEvent>)),
+    SetPlayerValue((u8, String, Option<serde_json::Value>)),
+    IncrementPlayerValue((u8, String, f64, oneshot::Sender<PluginBoundPluginEvent>)),
+
+    /// Makes `!<name>` a chat command, see `ScriptEvent::OnCommand`
+    RegisterCommand(String),
 }
 
 pub struct Plugin {
@@ -0,0 +1,33 @@
+use crate::config::TrafficPolicy;
+
+use super::{car_model, Server};
+
+impl Server {
+    /// Returns true if the car config is of a trailer or prop.
+    pub(super) fn is_prop(&self, car_json: &str) -> bool {
+        car_model(car_json).map(|model| self.config.props.is_prop(&model)).unwrap_or(false)
+    }
+
+    /// Trailers and props (if they have a limit of their own) and traffic cars (with the Separate
+    /// traffic policy) don't count towards MaxCars. Otherwise there'd be no limit on them at all.
+    pub(super) fn counts_towards_max_cars(&self, prop: bool, traffic: bool) -> bool {
+        let separate_props = self.config.props.max_per_player.is_some();
+        let separate_traffic = self.config.traffic.policy == TrafficPolicy::Separate;
+        !(prop && separate_props || traffic && separate_traffic)
+    }
+
+    /// Checks the prop settings for a car the client wants to spawn. Returns why it can't be
+    /// spawned if it's a trailer or prop that isn't allowed.
+    pub(super) fn prop_limit(&self, client_idx: usize, car_json: &str) -> Option<String> {
+        if !self.is_prop(car_json) {
+            return None;
+        }
+        let settings = &self.config.props;
+        if !settings.allow {
+            return Some(String::from("Trailers and props aren't allowed on this server."));
+        }
+        let max = settings.max_per_player?;
+        let count = self.clients[client_idx].cars.iter().filter(|(_, car)| car.prop).count();
+        (count >= max).then(|| format!("You can't spawn more than {} trailer(s) or prop(s).", max))
+    }
+}
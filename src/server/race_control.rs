@@ -0,0 +1,51 @@
+use serde::Serialize;
+
+use super::{Packet, RawPacket, Server};
+
+/// How many of the latest messages the `rc` command shows.
+const HISTORY_SHOWN: usize = 10;
+
+/// An announcement from race control, like a safety car, a penalty or a session change.
+#[derive(Serialize, Clone, Debug)]
+pub struct RaceControlMessage {
+    /// Seconds since the server started, on the same clock clients sync to.
+    pub time: f64,
+    pub message: String,
+}
+
+/// The race control messages of this session, oldest first.
+#[derive(Default)]
+pub struct RaceControl {
+    messages: Vec<RaceControlMessage>,
+}
+
+impl Server {
+    /// Announces a race control message to everyone. It's sent as chat, and as the client
+    /// event "RaceControl" so overlay mods can show it as a banner.
+    pub async fn send_race_control_message(&mut self, message: &str) {
+        let message = RaceControlMessage {
+            time: self.server_time(),
+            message: message.to_string(),
+        };
+        match serde_json::to_string(&message) {
+            Ok(json) => self.broadcast(Packet::Raw(RawPacket::from_str(&format!("E:RaceControl:{}", json))), None).await,
+            Err(e) => error!("Failed to serialize the race control message: {:?}", e),
+        }
+        self.send_chat_message(&format!("Race control: {}", message.message), None).await;
+        self.race_control.messages.push(message);
+    }
+
+    /// The latest race control messages, for the `rc` command.
+    pub(super) fn race_control_history(&self) -> String {
+        let messages = &self.race_control.messages;
+        if messages.is_empty() {
+            return String::from("No race control messages yet");
+        }
+        let mut history = String::from("Race control:");
+        for message in &messages[messages.len().saturating_sub(HISTORY_SHOWN)..] {
+            let seconds = message.time as u64;
+            history.push_str(&format!("\n\t[{}:{:02}] {}", seconds / 60, seconds % 60, message.message));
+        }
+        history
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use glam::DVec3;
+use serde::Serialize;
+
+use super::{Car, Server};
+
+/// Keeps track of when the nearby cars were last sent, and to whom.
+pub struct Radar {
+    last_update: Instant,
+    /// Players that had cars nearby in the last update. They get one more (empty) update once
+    /// the cars are gone, so their radar clears.
+    active: HashSet<u8>,
+}
+
+impl Radar {
+    pub fn new() -> Self {
+        Self {
+            last_update: Instant::now(),
+            active: HashSet::new(),
+        }
+    }
+}
+
+impl Default for Radar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Serialize)]
+struct RadarData {
+    cars: Vec<RadarCar>,
+}
+
+#[derive(Serialize)]
+struct RadarCar {
+    vid: u8,
+    nearby: Vec<NearbyCar>,
+}
+
+#[derive(Serialize)]
+struct NearbyCar {
+    pid: u8,
+    vid: u8,
+    /// In meters.
+    distance: f64,
+    /// In degrees relative to the heading of the car, 0 is ahead and 90 is to the right.
+    bearing: f64,
+}
+
+/// Returns the bearing of `target` as seen from `car`, ignoring height differences.
+fn relative_bearing(car: &Car, target: DVec3) -> f64 {
+    // Cars face -Y in their own space
+    let forward = (car.rot * DVec3::NEG_Y).truncate().normalize_or_zero();
+    let right = glam::DVec2::new(forward.y, -forward.x);
+    let offset = (target - car.pos).truncate();
+    offset.dot(right).atan2(offset.dot(forward)).to_degrees()
+}
+
+impl Server {
+    /// Sends every player the cars of other players near each of their cars, at the configured rate.
+    pub(super) async fn update_radar(&mut self) {
+        let settings = &self.config.radar;
+        if !settings.enabled || self.radar.last_update.elapsed() < Duration::from_secs(1) / settings.update_rate.max(1) {
+            return;
+        }
+        self.radar.last_update = Instant::now();
+
+        // Only cars that sent a position, as the others are still at the origin
+        let positioned: Vec<(u8, u8, &Car)> = self.clients.iter()
+            .flat_map(|client| client.cars.iter().map(move |(vid, car)| (client.id, *vid, car)))
+            .filter(|(_, _, car)| car.last_pos_update.is_some() && !car.prop)
+            .collect();
+
+        let mut active = HashSet::new();
+        for client in &self.clients {
+            let mut data = RadarData { cars: Vec::new() };
+            for (pid, vid, car) in positioned.iter().filter(|(pid, _, _)| *pid == client.id) {
+                let mut nearby: Vec<NearbyCar> = positioned.iter()
+                    .filter(|(other_pid, _, _)| other_pid != pid)
+                    .filter_map(|(other_pid, other_vid, other)| {
+                        let distance = car.pos.distance(other.pos);
+                        (distance <= settings.range).then(|| NearbyCar {
+                            pid: *other_pid,
+                            vid: *other_vid,
+                            distance: (distance * 10.0).round() / 10.0,
+                            bearing: relative_bearing(car, other.pos).round(),
+                        })
+                    })
+                    .collect();
+                if !nearby.is_empty() {
+                    nearby.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+                    data.cars.push(RadarCar { vid: *vid, nearby });
+                }
+            }
+
+            if !data.cars.is_empty() {
+                active.insert(client.id);
+            } else if !self.radar.active.contains(&client.id) {
+                continue;
+            }
+            match serde_json::to_string(&data) {
+                Ok(json) => client.trigger_client_event("Radar", json).await,
+                Err(e) => error!("Failed to serialize the radar data: {:?}", e),
+            }
+        }
+        self.radar.active = active;
+    }
+}
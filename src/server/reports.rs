@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use super::{CommandSource, Server};
+use crate::fs_util;
+use crate::output::event_path;
+
+/// How long a player has to wait between reports, so `report` can't be used to spam the admins.
+const REPORT_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// An incident a player reported for the stewards to review.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct IncidentReport {
+    pub id: usize,
+    pub reported_at: String,
+    /// Server time of the report, see `Server::server_time`.
+    pub server_time: f64,
+    pub reporter: String,
+    pub driver: String,
+    pub description: String,
+    /// Server state at the time of the report, see `Server::snapshot`.
+    pub snapshot: Option<PathBuf>,
+    pub decision: Option<String>,
+}
+
+/// The incident reports of this event, saved to `reports.json` in the event directory. Unlike
+/// bans or garages they belong to one event, so they aren't kept between events.
+pub struct IncidentReports {
+    reports: Vec<IncidentReport>,
+    /// When each player last filed a report, by name.
+    last_report: HashMap<String, Instant>,
+}
+
+impl IncidentReports {
+    /// Picks up the reports of an earlier run in the same event directory.
+    pub fn load() -> Self {
+        let reports = fs_util::load_json(&event_path("reports.json"), "the incident reports");
+        Self { reports, last_report: HashMap::new() }
+    }
+
+    fn save(&self) {
+        if let Err(e) = fs_util::save_json(&event_path("reports.json"), &self.reports, 10) {
+            error!("Failed to save the incident reports: {:?}", e);
+        }
+    }
+
+    fn next_id(&self) -> usize {
+        self.reports.iter().map(|report| report.id).max().unwrap_or(0) + 1
+    }
+}
+
+impl Server {
+    /// Files a report against `driver` (a player id or name), with a snapshot of the server state
+    /// so the stewards can see where everyone was, and lets the admins on the server know.
+    pub(super) async fn report_incident(&mut self, source: CommandSource, driver: &str, description: String) {
+        let reporter = match source {
+            CommandSource::Console => String::from("Console"),
+            CommandSource::Client(id) => self.clients.iter()
+                .find(|client| client.id == id)
+                .map(|client| client.get_name().to_string())
+                .unwrap_or_default(),
+        };
+        if source != CommandSource::Console {
+            if let Some(since) = self.reports.last_report.get(&reporter).map(Instant::elapsed) {
+                if since < REPORT_COOLDOWN {
+                    let wait = (REPORT_COOLDOWN - since).as_secs() + 1;
+                    self.command_reply(source, &format!("You can report again in {} seconds", wait)).await;
+                    return;
+                }
+            }
+            self.reports.last_report.insert(reporter.clone(), Instant::now());
+        }
+
+        let driver = self.find_client(driver)
+            .map(|i| self.clients[i].get_name().to_string())
+            .unwrap_or_else(|| driver.to_string());
+
+        let id = self.reports.next_id();
+        let snapshot_path = event_path(format!("report_{}_snapshot.json", id));
+        // Only taking the snapshot has to happen now, writing it can happen in the background
+        let snapshot = match serde_json::to_vec_pretty(&self.snapshot()) {
+            Ok(data) => {
+                let path = snapshot_path.clone();
+                tokio::task::spawn_blocking(move || {
+                    if let Err(e) = fs_util::write_atomic(&path, &data) {
+                        error!("Failed to save the snapshot for report #{}: {:?}", id, e);
+                    }
+                });
+                Some(snapshot_path)
+            },
+            Err(e) => {
+                error!("Failed to serialize the snapshot for report #{}: {:?}", id, e);
+                None
+            },
+        };
+
+        info!("Incident report #{} by {} against {}: {}", id, reporter, driver, description);
+        let notification = format!("New incident report #{} by {} against {}", id, reporter, driver);
+        self.reports.reports.push(IncidentReport {
+            id,
+            reported_at: chrono::Local::now().to_rfc3339(),
+            server_time: self.server_time(),
+            reporter,
+            driver,
+            description,
+            snapshot,
+            decision: None,
+        });
+        self.reports.save();
+
+        self.command_reply(source, &format!("Your report has been sent to the stewards as #{}", id)).await;
+        let admins: Vec<u8> = self.clients.iter()
+            .map(|client| client.id)
+            .filter(|id| source != CommandSource::Client(*id) && self.has_admin_permission(CommandSource::Client(*id)))
+            .collect();
+        for admin in admins {
+            self.send_chat_message(&notification, Some(admin)).await;
+        }
+    }
+
+    /// Lists the reports that don't have a decision yet, or all reports if `all` is set.
+    pub(super) async fn list_reports(&self, source: CommandSource, all: bool) {
+        let reports: Vec<&IncidentReport> = self.reports.reports.iter()
+            .filter(|report| all || report.decision.is_none())
+            .collect();
+        if reports.is_empty() {
+            self.command_reply(source, if all { "There are no reports." } else { "There are no open reports." }).await;
+            return;
+        }
+
+        let mut list = String::from("Reports:");
+        for report in reports {
+            list.push_str(&format!("\n\t#{} {} against {}: {}", report.id, report.reporter, report.driver, report.description));
+            if let Some(decision) = &report.decision {
+                list.push_str(&format!(" (decision: {})", decision));
+            }
+        }
+        self.command_reply(source, &list).await;
+    }
+
+    /// Attaches the decision of the stewards to a report.
+    pub(super) async fn decide_report(&mut self, source: CommandSource, id: &str, decision: String) {
+        let report = id.trim_start_matches('#').parse::<usize>().ok()
+            .and_then(|id| self.reports.reports.iter_mut().find(|report| report.id == id));
+        let Some(report) = report else {
+            self.command_reply(source, &format!("Could not find report '{}'", id)).await;
+            return;
+        };
+        info!("Decision on report #{}: {}", report.id, decision);
+        let reply = format!("Decision on report #{} saved", report.id);
+        report.decision = Some(decision);
+        self.reports.save();
+        self.command_reply(source, &reply).await;
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use tokio::sync::Notify;
+
+use super::PacketPriority;
+
+/// Limits how many bytes per second are sent to a client. Allows a burst of up to one second
+/// worth of bytes, after which packets have to wait for the budget to refill.
+pub struct OutboundBudget {
+    bytes_per_second: f64,
+    /// Can go negative, when a packet is sent that's bigger than what was available.
+    available: f64,
+    last_refill: Instant,
+}
+
+impl OutboundBudget {
+    pub fn new(bytes_per_second: usize) -> Self {
+        Self {
+            bytes_per_second: bytes_per_second.max(1) as f64,
+            available: bytes_per_second as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let refilled = self.available + (now - self.last_refill).as_secs_f64() * self.bytes_per_second;
+        self.available = refilled.min(self.bytes_per_second);
+        self.last_refill = now;
+    }
+
+    /// Takes the bytes from the budget if they're available right now.
+    pub fn try_take(&mut self, bytes: usize) -> bool {
+        self.refill();
+        if self.available >= bytes as f64 {
+            self.available -= bytes as f64;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Takes the bytes from the budget, waiting until they're available.
+    pub async fn take(&mut self, bytes: usize) {
+        self.refill();
+        self.available -= bytes as f64;
+        if self.available < 0.0 {
+            tokio::time::sleep(Duration::from_secs_f64(-self.available / self.bytes_per_second)).await;
+        }
+    }
+}
+
+/// Most packets waiting in the queues of a client. Beyond that, position updates make room, and
+/// a client that's still behind can't keep up at all.
+const MAX_QUEUED_PACKETS: usize = 1024;
+
+/// How long notifications wait for position updates to be sent first. After that they go first,
+/// so a steady stream of updates can't hold them back forever.
+const MAX_NOTIFICATION_WAIT: Duration = Duration::from_millis(500);
+
+/// Outbound packets of a client waiting to be sent, one queue per priority.
+#[derive(Default)]
+pub struct PriorityQueues {
+    control: VecDeque<Bytes>,
+    /// Only the newest update per car (or of the radar, which has no car) is kept.
+    position: VecDeque<(Option<(u8, u8)>, Bytes)>,
+    notification: VecDeque<(Instant, Bytes)>,
+}
+
+impl PriorityQueues {
+    /// Queues a packet, see `Packet::priority` and `Packet::car`. A position update replaces the
+    /// queued update of the same car, and vehicle packets throw away the queued updates of their
+    /// car, so an old update can't arrive after the car was deleted or respawned. When the queues
+    /// are full, the oldest position update is dropped to make room. Returns false if there's no
+    /// room for the packet.
+    pub fn push(&mut self, priority: PacketPriority, car: Option<(u8, u8)>, data: Bytes) -> bool {
+        match priority {
+            PacketPriority::Position => {
+                if let Some((_, queued)) = self.position.iter_mut().find(|(queued_car, _)| *queued_car == car) {
+                    *queued = data;
+                    return true;
+                }
+            },
+            PacketPriority::Control if car.is_some() => self.position.retain(|(queued_car, _)| *queued_car != car),
+            _ => {},
+        }
+        if self.is_full() && self.position.pop_front().is_none() {
+            return false;
+        }
+        match priority {
+            PacketPriority::Control => self.control.push_back(data),
+            PacketPriority::Position => self.position.push_back((car, data)),
+            PacketPriority::Notification => self.notification.push_back((Instant::now(), data)),
+        }
+        true
+    }
+
+    /// Takes the oldest packet with the highest priority, or a notification that waited too long.
+    pub fn pop(&mut self) -> Option<(PacketPriority, Bytes)> {
+        let notification_overdue = self.notification.front().is_some_and(|(since, _)| since.elapsed() >= MAX_NOTIFICATION_WAIT);
+        let notification = |queues: &mut Self| queues.notification.pop_front().map(|(_, data)| (PacketPriority::Notification, data));
+        let position = |queues: &mut Self| queues.position.pop_front().map(|(_, data)| (PacketPriority::Position, data));
+
+        if let Some(data) = self.control.pop_front() {
+            return Some((PacketPriority::Control, data));
+        }
+        if notification_overdue {
+            return notification(self);
+        }
+        position(self).or_else(|| notification(self))
+    }
+
+    fn len(&self) -> usize {
+        self.control.len() + self.position.len() + self.notification.len()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() >= MAX_QUEUED_PACKETS
+    }
+}
+
+#[derive(Default)]
+struct OutboundState {
+    queues: PriorityQueues,
+    /// Most bytes per second sent to the client, if there's a limit.
+    limit: Option<usize>,
+    /// A packet didn't fit in the queues, so the client is missing state and should go.
+    overflowed: bool,
+}
+
+/// The outbound packets of a client, shared between the server, which queues them, and the
+/// writer task of the client, which sends them. Queueing never waits, so a client that can't
+/// keep up (or is over its outbound limit) never holds up the server.
+#[derive(Default)]
+pub struct OutboundQueue {
+    state: Mutex<OutboundState>,
+    ready: Notify,
+}
+
+impl OutboundQueue {
+    pub fn new(limit: Option<usize>) -> Self {
+        let queue = Self::default();
+        queue.set_limit(limit);
+        queue
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, OutboundState> {
+        // The state is only changed by simple pushes and pops, so it's still usable after a panic
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn push(&self, priority: PacketPriority, car: Option<(u8, u8)>, data: Bytes) {
+        let mut state = self.state();
+        if !state.queues.push(priority, car, data) {
+            state.overflowed = true;
+        }
+        drop(state);
+        self.ready.notify_one();
+    }
+
+    /// Waits for the next packet to send, together with the current outbound limit.
+    pub async fn pop(&self) -> (PacketPriority, Bytes, Option<usize>) {
+        loop {
+            {
+                let mut state = self.state();
+                let limit = state.limit;
+                if let Some((priority, data)) = state.queues.pop() {
+                    return (priority, data, limit);
+                }
+            }
+            self.ready.notified().await;
+        }
+    }
+
+    pub fn set_limit(&self, limit: Option<usize>) {
+        self.state().limit = limit;
+    }
+
+    /// Whether packets had to be thrown away because the client fell too far behind.
+    pub fn overflowed(&self) -> bool {
+        self.state().overflowed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pop_all(queues: &mut PriorityQueues) -> Vec<String> {
+        std::iter::from_fn(|| queues.pop())
+            .map(|(_, data)| String::from_utf8_lossy(&data).into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn newer_positions_replace_queued_ones() {
+        let mut queues = PriorityQueues::default();
+        queues.push(PacketPriority::Position, Some((0, 0)), Bytes::from_static(b"z1"));
+        queues.push(PacketPriority::Position, None, Bytes::from_static(b"radar"));
+        queues.push(PacketPriority::Position, Some((0, 0)), Bytes::from_static(b"z2"));
+        queues.push(PacketPriority::Position, Some((0, 1)), Bytes::from_static(b"z3"));
+        assert_eq!(pop_all(&mut queues), ["z2", "radar", "z3"]);
+    }
+
+    #[test]
+    fn vehicle_packets_drop_queued_positions_of_their_car() {
+        let mut queues = PriorityQueues::default();
+        queues.push(PacketPriority::Position, Some((0, 0)), Bytes::from_static(b"z1"));
+        queues.push(PacketPriority::Position, Some((0, 1)), Bytes::from_static(b"z3"));
+        queues.push(PacketPriority::Control, Some((0, 0)), Bytes::from_static(b"od"));
+        assert_eq!(pop_all(&mut queues), ["od", "z3"]);
+    }
+
+    #[test]
+    fn notifications_dont_wait_forever() {
+        let mut queues = PriorityQueues::default();
+        queues.push(PacketPriority::Notification, None, Bytes::from_static(b"join"));
+        queues.push(PacketPriority::Position, Some((0, 0)), Bytes::from_static(b"z1"));
+        assert_eq!(queues.pop().map(|(priority, _)| priority), Some(PacketPriority::Position));
+
+        queues.push(PacketPriority::Position, Some((0, 0)), Bytes::from_static(b"z2"));
+        std::thread::sleep(MAX_NOTIFICATION_WAIT);
+        assert_eq!(pop_all(&mut queues), ["join", "z2"]);
+    }
+}
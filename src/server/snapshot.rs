@@ -0,0 +1,93 @@
+use serde::Serialize;
+
+use super::{Car, CommandSource, Server};
+
+/// The full state of the server, for debugging and for external tools that join mid-session.
+#[derive(Serialize)]
+pub struct ServerSnapshot {
+    pub name: String,
+    pub map: String,
+    pub seed: u64,
+    /// Seconds since the server started, on the same clock clients sync to.
+    pub server_time: f64,
+    pub settings: SettingsSnapshot,
+    pub players: Vec<PlayerSnapshot>,
+}
+
+/// The settings that affect what players can do.
+#[derive(Serialize)]
+pub struct SettingsSnapshot {
+    pub max_players: usize,
+    pub max_cars: Option<u8>,
+    pub max_resets_per_minute: Option<u32>,
+    pub tick_rate: u32,
+    pub private: bool,
+}
+
+#[derive(Serialize)]
+pub struct PlayerSnapshot {
+    pub id: u8,
+    pub name: String,
+    pub display_name: String,
+    pub beammp_id: String,
+    pub roles: String,
+    pub admin: bool,
+    pub cars: Vec<CarSnapshotJson>,
+}
+
+#[derive(Serialize)]
+pub struct CarSnapshotJson {
+    pub id: u8,
+    /// The car config the player spawned the car with.
+    pub config: serde_json::Value,
+    pub pos: [f64; 3],
+    pub rot: [f64; 4],
+    pub vel: [f64; 3],
+    pub speed_kmh: f64,
+    pub ping: f64,
+    /// Seconds since the last position update, if there was one.
+    pub last_update: Option<f64>,
+}
+
+impl CarSnapshotJson {
+    fn new(id: u8, car: &Car) -> Self {
+        Self {
+            id,
+            config: serde_json::from_str(&car.car_json).unwrap_or_else(|_| serde_json::Value::String(car.car_json.clone())),
+            pos: car.pos.to_array(),
+            rot: car.rot.to_array(),
+            vel: car.vel.to_array(),
+            speed_kmh: car.speed_kmh(),
+            ping: car.ping,
+            last_update: car.last_pos_update.map(|time| time.elapsed().as_secs_f64()),
+        }
+    }
+}
+
+impl Server {
+    pub fn snapshot(&self) -> ServerSnapshot {
+        let general = &self.config.general;
+        ServerSnapshot {
+            name: general.name.clone(),
+            map: general.map.clone(),
+            seed: crate::seed::session_seed(),
+            server_time: self.server_time(),
+            settings: SettingsSnapshot {
+                max_players: general.max_players,
+                max_cars: general.max_cars,
+                max_resets_per_minute: general.max_resets_per_minute,
+                tick_rate: general.tick_rate,
+                private: general.private,
+            },
+            players: self.clients.iter().map(|client| PlayerSnapshot {
+                id: client.id,
+                name: client.get_name().to_string(),
+                display_name: client.get_display_name(),
+                beammp_id: client.get_beammp_id().to_string(),
+                roles: client.get_roles().to_string(),
+                admin: self.has_admin_permission(CommandSource::Client(client.id)),
+                cars: client.cars.iter().map(|(id, car)| CarSnapshotJson::new(*id, car)).collect(),
+            }).collect(),
+        }
+    }
+}
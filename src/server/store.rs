@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use super::Server;
+use crate::fs_util;
+
+/// How often changed values are saved at most. Players leaving save right away.
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Values plugins keep per player (keyed by BeamMP ID), like currencies and unlocks, so every
+/// plugin doesn't need its own storage. Plugin requests are handled one by one, so increments
+/// from different plugins never get lost.
+#[derive(Default)]
+pub struct PlayerStore {
+    values: HashMap<String, HashMap<String, Value>>,
+    /// Changed since it was last saved.
+    dirty: bool,
+    last_save: Option<Instant>,
+    /// Number of the last save started, and of the last one written to disk. Saves are written
+    /// in the background and can overlap, so an older one must never overwrite a newer one.
+    saves: u64,
+    written: Arc<Mutex<u64>>,
+}
+
+impl PlayerStore {
+    pub fn load(path: &str) -> Self {
+        Self { values: fs_util::load_json(Path::new(path), "the player store"), ..Default::default() }
+    }
+
+    /// Saves the values if they changed, at most once every `SAVE_INTERVAL`. Called once per tick,
+    /// instead of on every change.
+    pub fn save_if_changed(&mut self, path: &str) {
+        if self.last_save.is_some_and(|last_save| last_save.elapsed() < SAVE_INTERVAL) {
+            return;
+        }
+        self.save(path);
+    }
+
+    /// Saves the values right away if they changed. The file is written on the blocking
+    /// thread pool, so the tick doesn't wait for the disk.
+    pub fn save(&mut self, path: &str) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+        self.last_save = Some(Instant::now());
+        let data = match serde_json::to_vec_pretty(&self.values) {
+            Ok(data) => data,
+            Err(e) => {
+                error!("Failed to serialize the player store: {:?}", e);
+                return;
+            },
+        };
+        self.saves += 1;
+        let save = self.saves;
+        let written = self.written.clone();
+        let path = PathBuf::from(path);
+        tokio::task::spawn_blocking(move || {
+            let mut written = written.lock().unwrap();
+            if *written > save {
+                return;
+            }
+            if let Err(e) = fs_util::write_with_backup(&path, &data, 3) {
+                error!("Failed to save the player store: {:?}", e);
+            }
+            *written = save;
+        });
+    }
+
+    pub fn get(&self, beammp_id: &str, key: &str) -> Option<&Value> {
+        self.values.get(beammp_id)?.get(key)
+    }
+
+    /// Sets a value, or removes it if `value` is None.
+    pub fn set(&mut self, beammp_id: &str, key: &str, value: Option<Value>) {
+        match value {
+            Some(value) => {
+                self.values.entry(beammp_id.to_string()).or_default().insert(key.to_string(), value);
+            },
+            None => {
+                if let Some(values) = self.values.get_mut(beammp_id) {
+                    values.remove(key);
+                }
+            },
+        }
+        self.dirty = true;
+    }
+
+    /// Adds `amount` to a value, which starts at 0, and returns the new value. Values stay
+    /// integers as long as only whole amounts are added. Fails if the value isn't a number, or
+    /// if the result doesn't fit, leaving the value as it was.
+    pub fn increment(&mut self, beammp_id: &str, key: &str, amount: f64) -> Result<Value, String> {
+        let values = self.values.entry(beammp_id.to_string()).or_default();
+        let current = values.get(key).cloned().unwrap_or(Value::from(0));
+        let new = match (current.as_i64(), current.as_f64()) {
+            (Some(current), _) if amount.fract() == 0.0 && amount.abs() < i64::MAX as f64 => current
+                .checked_add(amount as i64)
+                .map(Value::from)
+                .ok_or_else(|| format!("Adding {} to '{}' overflows", amount, key))?,
+            (_, Some(current)) => serde_json::Number::from_f64(current + amount)
+                .map(Value::Number)
+                .ok_or_else(|| format!("Adding {} to '{}' doesn't give a number", amount, key))?,
+            _ => return Err(format!("'{}' isn't a number", key)),
+        };
+        values.insert(key.to_string(), new.clone());
+        self.dirty = true;
+        Ok(new)
+    }
+}
+
+impl Server {
+    /// The key a player's values are stored under. Guests don't have a BeamMP ID to keep them under.
+    pub(super) fn store_key(&self, pid: u8) -> Option<String> {
+        self.clients.iter()
+            .find(|client| client.id == pid)?
+            .info.as_ref()
+            .filter(|info| !info.guest)
+            .map(|info| info.uid.clone())
+    }
+}
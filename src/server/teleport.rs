@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+
+use glam::{DQuat, DVec3};
+
+use super::{CommandSource, RespawnPacketData, RespawnPacketDataPos, RespawnPacketDataRot, Server};
+
+/// How far behind another player's car (in meters) a player is teleported to.
+const TELEPORT_DISTANCE_BEHIND: f64 = 5.0;
+
+/// Positions saved with `save <name>`, to teleport to with `tp <name>`. Only kept while the
+/// server runs, as they're only valid on the current map.
+#[derive(Default)]
+pub struct Bookmarks {
+    positions: HashMap<String, (DVec3, DQuat)>,
+}
+
+impl Server {
+    /// Checks whether the player can use the teleport commands, replying why not if they can't.
+    async fn can_teleport(&self, source: CommandSource) -> Option<u8> {
+        let settings = &self.config.teleport;
+        let CommandSource::Client(id) = source else {
+            self.command_reply(source, "Only players can teleport.").await;
+            return None;
+        };
+        if !settings.enabled {
+            self.command_reply(source, "Teleporting is disabled on this server.").await;
+            return None;
+        }
+        if settings.admin_only && !self.has_admin_permission(source) {
+            self.command_reply(source, "You don't have permission to use this command!").await;
+            return None;
+        }
+        Some(id)
+    }
+
+    /// The car of the player that last sent a position, as that's the one they're driving.
+    fn driven_car(&self, pid: u8) -> Option<(u8, DVec3, DQuat)> {
+        self.clients.iter()
+            .find(|client| client.id == pid)?
+            .cars.iter()
+            .filter(|(_, car)| car.last_pos_update.is_some())
+            .max_by_key(|(_, car)| car.last_pos_update)
+            .map(|(vid, car)| (*vid, car.pos, car.rot))
+    }
+
+    /// Saves the position of the player's car as a bookmark.
+    pub(super) async fn save_bookmark(&mut self, source: CommandSource, name: &str) {
+        let Some(pid) = self.can_teleport(source).await else { return; };
+        let Some((_, pos, rot)) = self.driven_car(pid) else {
+            self.command_reply(source, "You need to be driving a car to save a position.").await;
+            return;
+        };
+        self.bookmarks.positions.insert(name.to_string(), (pos, rot));
+        self.command_reply(source, &format!("Saved position '{}'", name)).await;
+    }
+
+    /// Teleports the player's car to a bookmark, or behind the car of another player (by id or name).
+    pub(super) async fn teleport(&mut self, source: CommandSource, target: &str) {
+        let Some(pid) = self.can_teleport(source).await else { return; };
+        let Some((vid, _, _)) = self.driven_car(pid) else {
+            self.command_reply(source, "You need to be driving a car to teleport.").await;
+            return;
+        };
+
+        let destination = match self.bookmarks.positions.get(target) {
+            Some(position) => Some(*position),
+            None => self.find_client(target)
+                .map(|i| self.clients[i].id)
+                .filter(|&id| id != pid)
+                .and_then(|id| self.driven_car(id))
+                // Cars face -Y, so behind them is +Y
+                .map(|(_, pos, rot)| (pos + rot * DVec3::new(0.0, TELEPORT_DISTANCE_BEHIND, 0.0), rot)),
+        };
+        let Some((pos, rot)) = destination else {
+            self.command_reply(source, &format!("There's no saved position or driving player called '{}'", target)).await;
+            return;
+        };
+
+        let data = serde_json::json!({
+            "vid": vid,
+            "respawn": RespawnPacketData {
+                pos: RespawnPacketDataPos { x: pos.x, y: pos.y, z: pos.z },
+                rot: RespawnPacketDataRot { x: rot.x, y: rot.y, z: rot.z, w: rot.w },
+            },
+        });
+        if let Some(client) = self.clients.iter().find(|client| client.id == pid) {
+            info!("Teleporting {} to {}", client.get_name(), target);
+            client.trigger_client_event("Teleport", data.to_string()).await;
+        }
+    }
+}
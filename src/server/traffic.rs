@@ -0,0 +1,31 @@
+use crate::config::TrafficPolicy;
+
+use super::Server;
+
+/// Returns true if the car config is of an AI traffic car, which has `"isTraffic": true`.
+pub fn is_traffic(car_json: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(car_json)
+        .ok()
+        .and_then(|car| car.get("isTraffic").and_then(|traffic| traffic.as_bool()))
+        .unwrap_or(false)
+}
+
+impl Server {
+    /// Checks the traffic policy for a car the client wants to spawn. Returns why it can't be
+    /// spawned if the policy doesn't allow it.
+    pub(super) fn traffic_limit(&self, client_idx: usize, car_json: &str) -> Option<String> {
+        if !is_traffic(car_json) {
+            return None;
+        }
+        let settings = &self.config.traffic;
+        match settings.policy {
+            TrafficPolicy::Normal => None,
+            TrafficPolicy::Block => Some(String::from("Traffic isn't allowed on this server.")),
+            TrafficPolicy::Separate => {
+                let max = settings.max_per_player;
+                let count = self.clients[client_idx].cars.iter().filter(|(_, car)| car.traffic).count();
+                (count >= max).then(|| format!("You can't spawn more than {} traffic car(s).", max))
+            },
+        }
+    }
+}
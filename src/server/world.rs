@@ -0,0 +1,95 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::{CommandSource, Server};
+use crate::fs_util;
+
+/// Shared state of the world, set by admins and sent to every player as the client event
+/// "WorldState", so a client side mod can apply it. Unset values are left to the players.
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct WorldState {
+    /// Time of day in hours, from 0 up to 24.
+    pub time: Option<f64>,
+    pub weather: Option<String>,
+}
+
+impl WorldState {
+    pub fn load(path: &str) -> Self {
+        fs_util::load_json(Path::new(path), "the world state")
+    }
+
+    fn save(&self, path: &str) {
+        if let Err(e) = fs_util::save_json(Path::new(path), self, 3) {
+            error!("Failed to save the world state: {:?}", e);
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Parses a time of day like `13:30` or `13.5`.
+fn parse_time(value: &str) -> Option<f64> {
+    let time = match value.split_once(':') {
+        Some((hours, minutes)) => hours.parse::<u32>().ok()? as f64 + minutes.parse::<u32>().ok().filter(|m| *m < 60)? as f64 / 60.0,
+        None => value.parse().ok()?,
+    };
+    (0.0..24.0).contains(&time).then_some(time)
+}
+
+impl Server {
+    /// Sends the world state to a joining player.
+    pub(super) async fn send_world_state(&self, client_idx: usize) {
+        if self.world.is_empty() {
+            return;
+        }
+        match serde_json::to_string(&self.world) {
+            Ok(data) => self.clients[client_idx].trigger_client_event("WorldState", data).await,
+            Err(e) => error!("Failed to serialize the world state: {:?}", e),
+        }
+    }
+
+    /// Shows or changes the world state, like `world time 13:30` or `world weather none`.
+    pub(super) async fn world_command(&mut self, source: CommandSource, args: &[String]) {
+        let (Some(setting), Some(value)) = (args.get(1), args.get(2)) else {
+            let time = self.world.time
+                .map(|time| format!("{:02}:{:02}", time as u32, (time.fract() * 60.0).round() as u32))
+                .unwrap_or_else(|| String::from("none"));
+            let weather = self.world.weather.clone().unwrap_or_else(|| String::from("none"));
+            self.command_reply(source, &format!("World: time = {}, weather = {}", time, weather)).await;
+            return;
+        };
+
+        let clear = value == "none";
+        match setting.as_str() {
+            "time" if clear => self.world.time = None,
+            "time" => match parse_time(value) {
+                Some(time) => self.world.time = Some(time),
+                None => {
+                    self.command_reply(source, &format!("Invalid time '{}', use hh:mm", value)).await;
+                    return;
+                },
+            },
+            "weather" if clear => self.world.weather = None,
+            "weather" => self.world.weather = Some(value.clone()),
+            _ => {
+                self.command_reply(source, "Usage: world [time <hh:mm|none>|weather <name|none>]").await;
+                return;
+            },
+        }
+        self.world.save(&self.config.world.file);
+
+        info!("World {} changed to {}", setting, value);
+        match serde_json::to_string(&self.world) {
+            Ok(data) => {
+                for client in &self.clients {
+                    client.trigger_client_event("WorldState", data.clone()).await;
+                }
+            },
+            Err(e) => error!("Failed to serialize the world state: {:?}", e),
+        }
+        self.command_reply(source, &format!("World {} changed to {}", setting, value)).await;
+    }
+}
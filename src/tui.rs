@@ -105,7 +105,7 @@ impl Tui {
                         KeyCode::Backspace => { self.input.pop(); },
                         KeyCode::Enter => {
                             if self.input.is_empty() == false {
-                                let args = self.input.split(" ").map(|s| s.to_string()).collect::<Vec<String>>();
+                                let args = crate::server::parse_command_args(&self.input);
                                 if let Err(e) = cmd_tx.send(args.clone()).await {
                                     error!("Error occured sending a command to the server! {e}");
                                     return Ok(true);
@@ -182,8 +182,29 @@ impl Tui {
             );
 
             let mut lines = Vec::new();
+            let heartbeat = &self.server_status.heartbeat;
+            if heartbeat.is_listed() {
+                let latency = heartbeat.latency.map(|l| l.as_millis()).unwrap_or(0);
+                lines.push(Line::from(Span::styled(format!("LISTED ({latency} ms)"), Style::default().green())));
+            } else if heartbeat.key_refused.is_some() {
+                lines.push(Line::from(Span::styled("UNLISTED (AuthKey refused)", Style::default().red())));
+            } else if heartbeat.consecutive_failures > 0 {
+                lines.push(Line::from(Span::styled(format!("UNLISTED ({} failed heartbeats)", heartbeat.consecutive_failures), Style::default().red())));
+            } else {
+                lines.push(Line::from(Span::styled("UNLISTED", Style::default().yellow())));
+            }
+            lines.push(Line::from(format!("Tick: {} ms", self.server_status.tick_time_ms)));
             for (id, name) in &self.server_status.player_list {
-                lines.push(Line::from(format!("{id} - {name}")));
+                match self.server_status.player_regions.get(id) {
+                    Some(region) => lines.push(Line::from(format!("{id} - {name} [{region}]"))),
+                    None => lines.push(Line::from(format!("{id} - {name}"))),
+                }
+                for car in self.server_status.cars.iter().filter(|car| car.player_id == *id) {
+                    lines.push(Line::from(format!(
+                        "    car {}: {:>3} km/h  {:+.1}g lon  {:+.1}g lat",
+                        car.car_id, car.speed_kmh, car.longitudinal_g, car.lateral_g,
+                    )));
+                }
             }
             frame.render_widget(
                 Paragraph::new(lines).block(Block::new().borders(Borders::ALL).title("Player List")),
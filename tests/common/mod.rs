@@ -0,0 +1,294 @@
+//! Test harness that runs the server binary on a free port and talks to it with a fake
+//! BeamMP client, speaking just enough of the protocol to join, spawn cars, drive and chat.
+
+#![allow(dead_code)] // Not every test uses every helper
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+pub const MAP: &str = "/levels/gridmap_v2/info.json";
+
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// A server process running in its own temporary directory. Killed when dropped.
+pub struct TestServer {
+    process: Child,
+    pub dir: PathBuf,
+    pub addr: SocketAddr,
+}
+
+impl TestServer {
+    /// Starts a server where the given players can join, keyed by their player key.
+    /// `extra_config` is appended to the `[General]` section.
+    pub fn start(players: &[(&str, &str)], extra_config: &str) -> Self {
+        Self::start_with_plugins(players, extra_config, &[])
+    }
+
+    /// Like `start`, with Lua plugins given as (folder name, main.lua source).
+    pub fn start_with_plugins(players: &[(&str, &str)], extra_config: &str, plugins: &[(&str, &str)]) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "beammp_rust_server_test_{}_{}",
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("Resources/Client")).unwrap();
+        std::fs::create_dir_all(dir.join("Resources/Server")).unwrap();
+        for (name, src) in plugins {
+            std::fs::create_dir_all(dir.join("Resources/Server").join(name)).unwrap();
+            std::fs::write(dir.join("Resources/Server").join(name).join("main.lua"), src).unwrap();
+        }
+
+        let port = free_port();
+        let keys = players
+            .iter()
+            .map(|(key, name)| format!("\"{}\": {{ \"username\": \"{}\" }}", key, name))
+            .collect::<Vec<_>>()
+            .join(",");
+        std::fs::write(dir.join("keys.json"), format!("{{{}}}", keys)).unwrap();
+        std::fs::write(dir.join("ServerConfig.toml"), format!(
+r#"[General]
+Name = "Test server"
+Port = {port}
+AuthKey = ""
+Private = true
+MaxPlayers = 8
+Map = "{MAP}"
+Description = "Integration test"
+ResourceFolder = "Resources"
+Debug = false
+LogChat = false
+{extra_config}
+
+[Auth]
+Provider = "KeyFile"
+KeyFile = "keys.json"
+"#)).unwrap();
+
+        let process = Command::new(env!("CARGO_BIN_EXE_beammp_rust_server"))
+            .arg("--disable-tui")
+            .current_dir(&dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Failed to start the server");
+
+        let server = Self {
+            process,
+            dir,
+            addr: SocketAddr::from(([127, 0, 0, 1], port)),
+        };
+        server.wait_until_ready();
+        server
+    }
+
+    fn wait_until_ready(&self) {
+        let start = Instant::now();
+        while TcpStream::connect(self.addr).is_err() {
+            assert!(start.elapsed() < TIMEOUT, "Server didn't start listening in time");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+/// A fake BeamMP client connected to a test server.
+pub struct FakeClient {
+    tcp: TcpStream,
+    udp: UdpSocket,
+    server: SocketAddr,
+    pub id: u8,
+    pub name: String,
+    pub map: String,
+}
+
+impl FakeClient {
+    /// Connects, authenticates with the key and syncs resources, like a real client joining.
+    pub fn join(server: &TestServer, key: &str, name: &str) -> Self {
+        let mut client = Self::connect(server, key, name);
+        client.expect(&format!("Sn{}", name));
+        client
+    }
+
+    /// Like `join`, without waiting for the welcome, so the packets sent before it can be checked.
+    pub fn connect(server: &TestServer, key: &str, name: &str) -> Self {
+        let mut client = Self::connect_raw(server);
+        client.name = name.to_string();
+
+        client.send("VC2.0");
+        client.expect("S");
+        client.send(key);
+        let id = client.expect("P");
+        client.id = id[1..].parse().expect("Invalid player id");
+
+        client.send("SR");
+        client.recv(); // Mod list, or '-' if there are none
+        client.send("Done");
+        client.map = client.expect("M")[1..].to_string();
+
+        // Full sync, which makes the server welcome us
+        client.send("H");
+        client
+    }
+
+    /// Opens a game connection without authenticating, for tests that go through the handshake themselves.
+    pub fn connect_raw(server: &TestServer) -> Self {
+        let mut tcp = TcpStream::connect(server.addr).unwrap();
+        tcp.set_read_timeout(Some(TIMEOUT)).unwrap();
+        tcp.set_nodelay(true).unwrap();
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        udp.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+
+        tcp.write_all(b"C").unwrap();
+        Self {
+            tcp,
+            udp,
+            server: server.addr,
+            id: 0,
+            name: String::new(),
+            map: String::new(),
+        }
+    }
+
+    /// Sends a framed TCP packet.
+    pub fn send(&mut self, data: &str) {
+        let mut raw = (data.len() as u32).to_le_bytes().to_vec();
+        raw.extend_from_slice(data.as_bytes());
+        self.send_raw(&raw);
+    }
+
+    /// Sends bytes over TCP as is, without framing them.
+    pub fn send_raw(&mut self, raw: &[u8]) {
+        self.tcp.write_all(raw).unwrap();
+    }
+
+    /// Receives the next TCP packet, decompressing it if needed.
+    pub fn recv(&mut self) -> String {
+        let mut header = [0u8; 4];
+        self.tcp.read_exact(&mut header).expect("Timed out waiting for a packet");
+        let mut data = vec![0u8; u32::from_le_bytes(header) as usize];
+        self.tcp.read_exact(&mut data).expect("Timed out waiting for a packet");
+        decode(&data)
+    }
+
+    /// Receives the next TCP packet if one arrives within `wait`.
+    pub fn try_recv(&mut self, wait: Duration) -> Option<String> {
+        self.tcp.set_read_timeout(Some(wait)).unwrap();
+        let mut header = [0u8; 4];
+        let result = self.tcp.read_exact(&mut header);
+        self.tcp.set_read_timeout(Some(TIMEOUT)).unwrap();
+        result.ok()?;
+        let mut data = vec![0u8; u32::from_le_bytes(header) as usize];
+        self.tcp.read_exact(&mut data).expect("Timed out waiting for a packet");
+        Some(decode(&data))
+    }
+
+    /// Receives TCP packets until one starts with the prefix, skipping anything else.
+    pub fn expect(&mut self, prefix: &str) -> String {
+        let start = Instant::now();
+        loop {
+            assert!(start.elapsed() < TIMEOUT, "Timed out waiting for a packet starting with {:?}", prefix);
+            let packet = self.recv();
+            if packet.starts_with(prefix) {
+                return packet;
+            }
+        }
+    }
+
+    pub fn send_chat(&mut self, message: &str) {
+        let packet = format!("C:{}:{}", self.name, message);
+        self.send(&packet);
+    }
+
+    pub fn spawn_car(&mut self, car_json: &str) {
+        let packet = format!("Os:0:{}", car_json);
+        self.send(&packet);
+    }
+
+    /// Sends a UDP packet. The server finds out who sent it through the id in front.
+    pub fn send_udp(&self, data: &str) {
+        let mut raw = vec![self.id + 1, b':'];
+        raw.extend_from_slice(data.as_bytes());
+        self.send_raw_udp(&raw);
+    }
+
+    /// Sends a UDP datagram as is, without the id in front.
+    pub fn send_raw_udp(&self, raw: &[u8]) {
+        self.udp.send_to(raw, self.server).unwrap();
+    }
+
+    /// Pings the server over UDP until it answers, which also tells it our UDP address.
+    pub fn register_udp(&self) {
+        let start = Instant::now();
+        loop {
+            assert!(start.elapsed() < TIMEOUT, "Server never answered the UDP ping");
+            self.send_udp("p");
+            if let Some(packet) = self.try_recv_udp() {
+                if packet.starts_with('p') {
+                    return;
+                }
+            }
+        }
+    }
+
+    pub fn send_position(&self, car_id: u8, pos: [f64; 3]) {
+        self.send_position_at(car_id, pos, 1.0, 0.0);
+    }
+
+    /// Sends a position with the given client time and ping, both in seconds.
+    pub fn send_position_at(&self, car_id: u8, pos: [f64; 3], tim: f64, ping: f64) {
+        let packet = format!(
+            "Zp:{}-{}:{{\"rvel\":[0,0,0],\"tim\":{},\"pos\":[{},{},{}],\"ping\":{},\"rot\":[0,0,0,1],\"vel\":[0,0,0]}}",
+            self.id, car_id, tim, pos[0], pos[1], pos[2], ping,
+        );
+        self.send_udp(&packet);
+    }
+
+    pub fn try_recv_udp(&self) -> Option<String> {
+        let mut data = vec![0u8; 4096];
+        let n = self.udp.recv(&mut data).ok()?;
+        Some(decode(&data[..n]))
+    }
+
+    /// Receives UDP packets until one starts with the prefix, skipping anything else.
+    pub fn expect_udp(&self, prefix: &str) -> String {
+        let start = Instant::now();
+        loop {
+            assert!(start.elapsed() < TIMEOUT, "Timed out waiting for a UDP packet starting with {:?}", prefix);
+            if let Some(packet) = self.try_recv_udp() {
+                if packet.starts_with(prefix) {
+                    return packet;
+                }
+            }
+        }
+    }
+}
+
+/// Decompresses `ABG:` packets, and turns the packet into a string.
+fn decode(data: &[u8]) -> String {
+    if let Some(compressed) = data.strip_prefix(b"ABG:") {
+        // The server only sync-flushes the stream, so reading ends with an unexpected EOF.
+        // Everything up to that point is still in the buffer.
+        let mut decompressed = Vec::new();
+        let _ = flate2::read::ZlibDecoder::new(compressed).read_to_end(&mut decompressed);
+        String::from_utf8_lossy(&decompressed).to_string()
+    } else {
+        String::from_utf8_lossy(data).to_string()
+    }
+}
@@ -0,0 +1,802 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use common::*;
+
+#[test]
+fn client_joins_and_receives_the_map() {
+    let server = TestServer::start(&[("key_alice", "alice")], "");
+    let client = FakeClient::join(&server, "key_alice", "alice");
+    assert_eq!(client.map, MAP);
+}
+
+#[test]
+fn unknown_key_is_kicked() {
+    let server = TestServer::start(&[("key_alice", "alice")], "");
+    let mut tcp = TcpStream::connect(server.addr).unwrap();
+    tcp.set_read_timeout(Some(TIMEOUT)).unwrap();
+    tcp.write_all(b"C").unwrap();
+    for packet in ["VC2.0", "not_a_key"] {
+        let mut raw = (packet.len() as u32).to_le_bytes().to_vec();
+        raw.extend_from_slice(packet.as_bytes());
+        tcp.write_all(&raw).unwrap();
+    }
+    let mut received = Vec::new();
+    let _ = tcp.read_to_end(&mut received);
+    assert!(String::from_utf8_lossy(&received).contains("KFailed to authenticate player!"));
+}
+
+#[test]
+fn chat_is_broadcast_to_other_players() {
+    let server = TestServer::start(&[("key_alice", "alice"), ("key_bob", "bob")], "");
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+    let mut bob = FakeClient::join(&server, "key_bob", "bob");
+
+    alice.send_chat("hello bob");
+    assert_eq!(bob.expect("C:"), "C:alice:hello bob");
+}
+
+#[test]
+fn spawns_over_the_car_limit_are_blocked() {
+    let server = TestServer::start(&[("key_alice", "alice"), ("key_bob", "bob")], "MaxCars = 1");
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+    let mut bob = FakeClient::join(&server, "key_bob", "bob");
+
+    alice.spawn_car("{\"jbm\":\"pickup\"}");
+    let spawn = bob.expect("Os:");
+    assert!(spawn.contains(&format!("{}-0", alice.id)), "Unexpected spawn packet {}", spawn);
+
+    alice.spawn_car("{\"jbm\":\"covet\"}");
+    assert_eq!(alice.expect("Od:"), format!("Od:{}-1", alice.id));
+}
+
+#[test]
+fn positions_are_relayed_to_other_players() {
+    let server = TestServer::start(&[("key_alice", "alice"), ("key_bob", "bob")], "");
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+    let mut bob = FakeClient::join(&server, "key_bob", "bob");
+
+    alice.spawn_car("{\"jbm\":\"pickup\"}");
+    bob.expect("Os:");
+    bob.register_udp();
+
+    alice.send_position(0, [1.0, 2.0, 3.0]);
+    let position = bob.expect_udp("Zp:");
+    assert!(position.starts_with(&format!("Zp:{}-0:", alice.id)), "Unexpected position packet {}", position);
+}
+
+#[test]
+fn time_sync_echoes_the_client_time() {
+    let server = TestServer::start(&[("key_alice", "alice")], "");
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+
+    alice.send("E:TimeSyncRequest:12.5");
+    let response = alice.expect("E:TimeSync:");
+    let data: serde_json::Value = serde_json::from_str(&response["E:TimeSync:".len()..]).unwrap();
+    assert_eq!(data["clientTime"], 12.5);
+    assert!(data["serverTime"].as_f64().unwrap() > 0.0);
+}
+
+#[test]
+fn chat_uses_the_configured_display_name() {
+    let server = TestServer::start(
+        &[("key_alice", "alice"), ("key_bob", "bob")],
+        "\n[Drivers.alice]\nDisplayName = \"  Alice   A. \"\nNumber = 7\n[Drivers.bob]\nDisplayName = \"Bobby\"\n",
+    );
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+    let mut bob = FakeClient::join(&server, "key_bob", "bob");
+    assert_eq!(alice.expect("JWelcome"), "JWelcome Bobby!");
+
+    alice.send_chat("hello");
+    assert_eq!(bob.expect("C:"), "C:#7 Alice A.:hello");
+}
+
+#[test]
+fn capture_records_packets_in_both_directions() {
+    let server = TestServer::start(
+        &[("key_alice", "alice")],
+        "\n[Capture]\nEnabled = true\nPath = \"capture.ndjson\"\n[Output]\nEventDirectory = \"event\"\n",
+    );
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+    alice.send("E:TimeSyncRequest:1.0");
+    alice.expect("E:TimeSync:");
+
+    let capture = std::fs::read_to_string(server.dir.join("event/capture.ndjson")).unwrap();
+    let entries: Vec<serde_json::Value> = capture.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert!(entries.iter().any(|e| e["direction"] == "in" && e["data"] == "key_alice"));
+    assert!(entries.iter().any(|e| e["direction"] == "out" && e["data"].as_str().unwrap().starts_with("E:TimeSync:")));
+}
+
+#[test]
+fn admins_can_change_settings_at_runtime() {
+    let server = TestServer::start(&[("key_alice", "alice"), ("key_bob", "bob")], "Admins = [\"alice\"]");
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+    let mut bob = FakeClient::join(&server, "key_bob", "bob");
+
+    bob.send_chat("!set max_cars 1");
+    assert!(bob.expect("C:").ends_with("You don't have permission to use this command!"));
+
+    alice.send_chat("!set max_cars 1");
+    assert_eq!(bob.expect("C:"), "C:Server: Server setting max_cars changed to 1");
+
+    alice.spawn_car("{\"jbm\":\"pickup\"}");
+    alice.expect("Os:");
+    alice.spawn_car("{\"jbm\":\"covet\"}");
+    assert_eq!(alice.expect("Od:"), format!("Od:{}-1", alice.id));
+}
+
+#[test]
+fn closed_connections_are_removed_right_away() {
+    let server = TestServer::start(&[("key_alice", "alice"), ("key_bob", "bob")], "");
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+    let mut bob = FakeClient::join(&server, "key_bob", "bob");
+
+    alice.spawn_car("{\"jbm\":\"pickup\"}");
+    bob.expect("Os:");
+    drop(alice);
+    assert_eq!(bob.expect("Od:"), "Od:0-0");
+    bob.expect("Lalice left the server!");
+}
+
+#[test]
+fn oversized_packets_disconnect_the_sender() {
+    let server = TestServer::start(&[("key_alice", "alice"), ("key_bob", "bob")], "");
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+    let mut bob = FakeClient::join(&server, "key_bob", "bob");
+
+    alice.send_raw(&u32::MAX.to_le_bytes());
+    bob.expect("Lalice left the server!");
+}
+
+#[test]
+fn silent_players_are_disconnected() {
+    let server = TestServer::start(&[("key_alice", "alice"), ("key_bob", "bob")], "ConnectionTimeout = 2");
+    let _alice = FakeClient::join(&server, "key_alice", "alice");
+    let mut bob = FakeClient::join(&server, "key_bob", "bob");
+
+    // Only bob keeps pinging the server
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(3) {
+        bob.send_udp("p");
+        std::thread::sleep(Duration::from_millis(250));
+    }
+    bob.expect("Lalice left the server!");
+}
+
+#[test]
+fn event_info_records_the_session_seed() {
+    let server = TestServer::start(&[("key_alice", "alice")], "Seed = 42\n[Output]\nEventDirectory = \"event\"\n");
+    let _alice = FakeClient::join(&server, "key_alice", "alice");
+
+    let info = std::fs::read_to_string(server.dir.join("event/event.json")).unwrap();
+    let info: serde_json::Value = serde_json::from_str(&info).unwrap();
+    assert_eq!(info["seed"], 42);
+}
+
+#[test]
+fn snapshot_saves_the_server_state() {
+    let server = TestServer::start(&[("key_alice", "alice")], "Admins = [\"alice\"]\n[Output]\nEventDirectory = \"event\"\n");
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+    alice.spawn_car("{\"jbm\":\"pickup\"}");
+    alice.expect("Os:");
+
+    alice.send_chat("!snapshot");
+    assert!(alice.expect("C:").contains("Saving the server state to"));
+
+    let deadline = Instant::now() + TIMEOUT;
+    while !server.dir.join("event/snapshot.json").exists() {
+        assert!(Instant::now() < deadline, "The snapshot was never written");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let snapshot = std::fs::read_to_string(server.dir.join("event/snapshot.json")).unwrap();
+    let snapshot: serde_json::Value = serde_json::from_str(&snapshot).unwrap();
+    assert_eq!(snapshot["players"][0]["name"], "alice");
+    assert_eq!(snapshot["players"][0]["admin"], true);
+    assert_eq!(snapshot["players"][0]["cars"][0]["config"]["jbm"], "pickup");
+}
+
+#[test]
+fn race_control_messages_are_announced_and_kept() {
+    let server = TestServer::start(&[("key_alice", "alice"), ("key_bob", "bob")], "Admins = [\"alice\"]\n[Chat]\nCooldownMs = 0\n");
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+    let mut bob = FakeClient::join(&server, "key_bob", "bob");
+
+    alice.send_chat("!rc Safety car deployed");
+    let event = bob.expect("E:RaceControl:");
+    let event: serde_json::Value = serde_json::from_str(&event["E:RaceControl:".len()..]).unwrap();
+    assert_eq!(event["message"], "Safety car deployed");
+    assert_eq!(bob.expect("C:"), "C:Server: Race control: Safety car deployed");
+
+    bob.send_chat("!rc Nice try");
+    assert!(bob.expect("C:Server @").contains("don't have permission"));
+    bob.send_chat("!rc");
+    let history = bob.expect("C:Server @");
+    assert!(history.contains("Safety car deployed") && !history.contains("Nice try"), "{}", history);
+}
+
+#[test]
+fn players_can_report_incidents_to_the_stewards() {
+    let server = TestServer::start(
+        &[("key_alice", "alice"), ("key_bob", "bob")],
+        "Admins = [\"alice\"]\n[Chat]\nCooldownMs = 0\n[Output]\nEventDirectory = \"event\"\n",
+    );
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+    let mut bob = FakeClient::join(&server, "key_bob", "bob");
+
+    bob.send_chat("!report alice \"pushed me off in turn 3\"");
+    assert!(bob.expect("C:").ends_with("Your report has been sent to the stewards as #1"));
+    assert!(alice.expect("C:").ends_with("New incident report #1 by bob against alice"));
+    bob.send_chat("!report alice \"and again\"");
+    assert!(bob.expect("C:").contains("You can report again in"));
+
+    alice.send_chat("!decide 1 \"racing incident\"");
+    assert!(alice.expect("C:").ends_with("Decision on report #1 saved"));
+
+    let reports = std::fs::read_to_string(server.dir.join("event/reports.json")).unwrap();
+    let reports: serde_json::Value = serde_json::from_str(&reports).unwrap();
+    assert_eq!(reports[0]["description"], "pushed me off in turn 3");
+    assert_eq!(reports[0]["decision"], "racing incident");
+    let deadline = Instant::now() + TIMEOUT;
+    while !server.dir.join("event/report_1_snapshot.json").exists() {
+        assert!(Instant::now() < deadline, "The snapshot of the report was never written");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn radar_tells_players_about_nearby_cars() {
+    let server = TestServer::start(&[("key_alice", "alice"), ("key_bob", "bob")], "\n[Radar]\nEnabled = true\nRange = 10.0\n");
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+    let mut bob = FakeClient::join(&server, "key_bob", "bob");
+    alice.spawn_car("{\"jbm\":\"pickup\"}");
+    alice.expect("Os:");
+    bob.spawn_car("{\"jbm\":\"covet\"}");
+    // Skip the spawn of alice
+    while !bob.expect("Os:").contains(":bob:") {}
+    alice.register_udp();
+    bob.register_udp();
+
+    // Cars face -Y, so -X is to the right
+    alice.send_position(0, [0.0, 0.0, 0.0]);
+    bob.send_position(0, [-5.0, 0.0, 0.0]);
+    let radar = alice.expect("E:Radar:");
+    let radar: serde_json::Value = serde_json::from_str(&radar["E:Radar:".len()..]).unwrap();
+    let nearby = &radar["cars"][0]["nearby"][0];
+    assert_eq!(nearby["pid"], bob.id);
+    assert_eq!(nearby["distance"], 5.0);
+    assert_eq!(nearby["bearing"], 90.0);
+}
+
+#[test]
+fn late_joiners_get_the_existing_cars_and_their_positions() {
+    let server = TestServer::start(&[("key_alice", "alice"), ("key_bob", "bob")], "");
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+    alice.spawn_car("{\"jbm\":\"pickup\"}");
+    alice.expect("Os:");
+    alice.register_udp();
+    alice.send_position(0, [1.0, 2.0, 3.0]);
+    // The position is handled once alice gets it back from the server
+    alice.send_udp("p");
+    alice.expect_udp("p");
+
+    let mut bob = FakeClient::join(&server, "key_bob", "bob");
+    let spawn = bob.expect("Os:");
+    assert!(spawn.contains(&format!(":alice:{}-0:{{\"jbm\":\"pickup\"}}", alice.id)), "{}", spawn);
+    let position = bob.expect(&format!("Zp:{}-0:", alice.id));
+    let transform: serde_json::Value = serde_json::from_str(position.splitn(3, ':').nth(2).unwrap()).unwrap();
+    assert_eq!(transform["pos"], serde_json::json!([1.0, 2.0, 3.0]));
+}
+
+#[test]
+fn short_udp_packets_are_ignored() {
+    let server = TestServer::start(&[("key_alice", "alice")], "");
+    let alice = FakeClient::join(&server, "key_alice", "alice");
+    alice.register_udp();
+    alice.send_raw_udp(&[alice.id + 1]);
+    alice.send_raw_udp(&[]);
+    alice.send_udp("p");
+    alice.expect_udp("p");
+}
+
+#[test]
+fn packets_still_arrive_over_the_outbound_limit() {
+    let server = TestServer::start(&[("key_alice", "alice"), ("key_bob", "bob")], "MaxOutboundKiBPerSecond = 1\n");
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+    let mut bob = FakeClient::join(&server, "key_bob", "bob");
+
+    // Well over the limit, so the spawn has to wait for the budget to refill
+    let numbers: Vec<String> = (0..800u32).map(|i| (i * 7919 % 10007).to_string()).collect();
+    alice.spawn_car(&format!("{{\"jbm\":\"pickup\",\"data\":[{}]}}", numbers.join(",")));
+    let spawn = bob.expect("Os:");
+    assert!(spawn.contains(":alice:"), "{}", spawn);
+}
+
+#[test]
+fn players_with_a_poor_connection_are_moved_to_spectator() {
+    let server = TestServer::start(
+        &[("key_alice", "alice")],
+        "\n[ConnectionQuality]\nMaxPingMs = 100.0\nAutoSpectate = true\nWarnAfter = 0\nSpectateAfter = 1\n",
+    );
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+    alice.spawn_car("{\"jbm\":\"pickup\"}");
+    alice.expect("Os:");
+    alice.register_udp();
+
+    // Keep reporting a 500 ms ping until the server gives up on alice
+    let mut received = Vec::new();
+    let deadline = Instant::now() + TIMEOUT;
+    'spectating: for i in 0.. {
+        assert!(Instant::now() < deadline, "Never got moved to spectator, got {:?}", received);
+        alice.send_position_at(0, [0.0, 0.0, 0.0], i as f64 * 0.1, 0.5);
+        std::thread::sleep(Duration::from_millis(100));
+        while let Some(packet) = alice.try_recv(Duration::from_millis(1)) {
+            let done = packet.starts_with("E:Spectate:true");
+            received.push(packet);
+            if done {
+                break 'spectating;
+            }
+        }
+    }
+    let warning = received.iter().find(|packet| packet.starts_with("C:Server @")).expect("No warning");
+    assert!(warning.contains("Your connection is unstable (ping 500 ms"), "{}", warning);
+    assert!(received.contains(&format!("Od:{}-0", alice.id)), "{:?}", received);
+
+    alice.spawn_car("{\"jbm\":\"pickup\"}");
+    alice.expect("Od:");
+    let blocked = alice.expect("C:Server @");
+    assert!(blocked.contains("while spectating"), "{}", blocked);
+
+    // Without cars, steady UDP pings show the connection is fine again
+    let deadline = Instant::now() + TIMEOUT;
+    'released: loop {
+        assert!(Instant::now() < deadline, "Never got out of spectator");
+        std::thread::sleep(Duration::from_millis(100));
+        alice.send_udp("p");
+        while let Some(packet) = alice.try_recv(Duration::from_millis(1)) {
+            if packet.starts_with("E:Spectate:false") {
+                break 'released;
+            }
+        }
+    }
+}
+
+#[test]
+fn assigned_and_locked_liveries_are_enforced() {
+    let server = TestServer::start(
+        &[("key_alice", "alice"), ("key_bob", "bob")],
+        "\n[Liveries]\nEnforce = true\nLocked = true\n[Drivers.alice]\nLivery = { PaintDesign = \"pickup_skin_police\" }\n",
+    );
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+    let mut bob = FakeClient::join(&server, "key_bob", "bob");
+
+    alice.spawn_car("{\"jbm\":\"pickup\",\"vcf\":{\"parts\":{\"paint_design\":\"\"},\"paints\":[{\"baseColor\":[1,0,0,1]}]}}");
+    let spawn = bob.expect("Os:");
+    assert!(spawn.contains("\"paint_design\":\"pickup_skin_police\""), "{}", spawn);
+
+    alice.send(&format!("Oc:{}-0:{{\"jbm\":\"pickup\",\"vcf\":{{\"parts\":{{\"paint_design\":\"\"}},\"paints\":[{{\"baseColor\":[0,0,1,1]}}]}}}}", alice.id));
+    assert!(alice.expect("C:").contains("Liveries are locked"));
+    let edit = bob.expect("Oc:");
+    assert!(edit.contains("\"baseColor\":[1,0,0,1]"), "{}", edit);
+    assert!(edit.contains("\"paint_design\":\"pickup_skin_police\""), "{}", edit);
+}
+
+#[test]
+fn cosmetic_edit_mode_blocks_part_changes() {
+    let server = TestServer::start(&[("key_alice", "alice"), ("key_bob", "bob")], "\n[Edits]\nMode = \"Cosmetic\"\n");
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+    let mut bob = FakeClient::join(&server, "key_bob", "bob");
+
+    alice.spawn_car("{\"jbm\":\"pickup\",\"vcf\":{\"parts\":{\"pickup_engine\":\"v8\"},\"paints\":[{\"baseColor\":[1,0,0,1]}]}}");
+    bob.expect("Os:");
+
+    alice.send(&format!("Oc:{}-0:{{\"jbm\":\"pickup\",\"vcf\":{{\"parts\":{{\"pickup_engine\":\"i6\"}},\"paints\":[{{\"baseColor\":[1,0,0,1]}}]}}}}", alice.id));
+    assert!(alice.expect("C:").contains("Only cosmetic changes are allowed"));
+
+    alice.send(&format!("Oc:{}-0:{{\"jbm\":\"pickup\",\"vcf\":{{\"parts\":{{\"pickup_engine\":\"v8\"}},\"paints\":[{{\"baseColor\":[0,0,1,1]}}],\"licenseName\":\"FAST 1\"}}}}", alice.id));
+    let edit = bob.expect("Oc:");
+    assert!(edit.contains("\"baseColor\":[0,0,1,1]"), "{}", edit);
+    assert!(edit.contains("\"licenseName\":\"FAST 1\""), "{}", edit);
+}
+
+#[test]
+fn players_cant_edit_cars_of_others() {
+    let server = TestServer::start(&[("key_alice", "alice"), ("key_bob", "bob")], "");
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+    let mut bob = FakeClient::join(&server, "key_bob", "bob");
+
+    alice.spawn_car("{\"jbm\":\"pickup\"}");
+    bob.expect("Os:");
+    alice.expect("Os:");
+
+    bob.send(&format!("Oc:{}-0:{{\"jbm\":\"covet\"}}", alice.id));
+    bob.spawn_car("{\"jbm\":\"pickup\"}");
+    let next = alice.expect("O");
+    assert!(next.starts_with("Os:"), "{}", next);
+}
+
+#[test]
+fn player_count_rules_change_settings() {
+    let server = TestServer::start(
+        &[("key_alice", "alice"), ("key_bob", "bob")],
+        "\n[[PlayerCountRules]]\nMinPlayers = 2\nMaxCars = 1\n",
+    );
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+    alice.spawn_car("{\"jbm\":\"pickup\"}");
+    alice.expect("Os:");
+
+    let mut bob = FakeClient::join(&server, "key_bob", "bob");
+    assert!(alice.expect("C:").ends_with("2 or more players online, server settings changed"));
+    bob.spawn_car("{\"jbm\":\"pickup\"}");
+    bob.expect("Os:");
+    bob.spawn_car("{\"jbm\":\"covet\"}");
+    assert_eq!(bob.expect("Od:"), format!("Od:{}-1", bob.id));
+
+    drop(bob);
+    assert!(alice.expect("C:").ends_with("Server settings are back to normal"));
+    alice.spawn_car("{\"jbm\":\"covet\"}");
+    assert!(alice.expect("Os:").contains(&format!(":{}-1:", alice.id)));
+}
+
+#[test]
+fn car_classes_limit_spawns() {
+    let server = TestServer::start(
+        &[("key_alice", "alice"), ("key_bob", "bob")],
+        "\n[CarClasses.trucks]\nModels = [\"us_semi\"]\nMax = 1\n",
+    );
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+    let mut bob = FakeClient::join(&server, "key_bob", "bob");
+
+    alice.spawn_car("{\"jbm\":\"us_semi\"}");
+    alice.expect("Os:");
+    bob.spawn_car("{\"jbm\":\"us_semi\"}");
+    assert_eq!(bob.expect("Od:"), format!("Od:{}-0", bob.id));
+    assert!(bob.expect("C:").ends_with("There can't be more than 1 car(s) of class 'trucks' on the server."));
+
+    // Cars without a class aren't limited
+    bob.spawn_car("{\"jbm\":\"pickup\"}");
+    assert!(bob.expect("Os:").contains(&format!(":{}-0:", bob.id)));
+
+    // Neither can an edit turn a car into one of a full class
+    bob.send(&format!("Oc:{}-0:{{\"jbm\":\"us_semi\"}}", bob.id));
+    assert!(bob.expect("C:").contains("There can't be more than 1 car(s) of class 'trucks' on the server."));
+
+    // The car that's edited doesn't count against its own class
+    alice.send(&format!("Oc:{}-0:{{\"jbm\":\"us_semi\",\"vcf\":{{\"licenseName\":\"BIG 1\"}}}}", alice.id));
+    assert!(bob.expect("Oc:").contains("BIG 1"));
+}
+
+#[test]
+fn separate_traffic_policy_has_its_own_limit() {
+    let server = TestServer::start(
+        &[("key_alice", "alice")],
+        "MaxCars = 1\n[Traffic]\nPolicy = \"Separate\"\nMaxPerPlayer = 1\n",
+    );
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+
+    alice.spawn_car("{\"jbm\":\"pickup\"}");
+    alice.expect("Os:");
+    // Traffic doesn't count towards MaxCars
+    alice.spawn_car("{\"jbm\":\"covet\",\"isTraffic\":true}");
+    assert!(alice.expect("Os:").contains(&format!(":{}-1:", alice.id)));
+    alice.spawn_car("{\"jbm\":\"covet\",\"isTraffic\":true}");
+    assert_eq!(alice.expect("Od:"), format!("Od:{}-2", alice.id));
+    assert!(alice.expect("C:").ends_with("You can't spawn more than 1 traffic car(s)."));
+}
+
+#[test]
+fn plugins_can_trigger_events_in_other_plugins() {
+    let server = TestServer::start_with_plugins(
+        &[("key_alice", "alice")],
+        "",
+        &[
+            ("sender", "function onChat(pid, name, message)\n  MP.TriggerGlobalEvent(\"relay\", name, message)\nend\nMP.RegisterEvent(\"onChatMessage\", \"onChat\")\n"),
+            ("receiver", "function onRelay(name, message)\n  MP.SendChatMessage(-1, name .. \" said \" .. message)\nend\nMP.RegisterEvent(\"relay\", \"onRelay\")\n"),
+        ],
+    );
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+
+    alice.send_chat("hello");
+    let relayed = loop {
+        let message = alice.expect("C:");
+        if message.starts_with("C:Server:") { break message; }
+    };
+    assert_eq!(relayed, "C:Server: alice said hello");
+}
+
+#[test]
+fn plugins_are_sandboxed() {
+    let server = TestServer::start_with_plugins(
+        &[("key_alice", "alice")],
+        "",
+        &[(
+            "sneaky",
+            "function onChat(pid, name, message)\n  local t = {}\n  t.self = t\n  MP.TriggerGlobalEvent(\"loop\", t, _G)\n  MP.SendChatMessage(-1, tostring(io) .. \" \" .. tostring(os.execute) .. \" \" .. tostring(require))\nend\nMP.RegisterEvent(\"onChatMessage\", \"onChat\")\n",
+        )],
+    );
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+
+    alice.send_chat("hello");
+    let reply = loop {
+        let message = alice.expect("C:");
+        if message.starts_with("C:Server:") { break message; }
+    };
+    assert_eq!(reply, "C:Server: nil nil nil");
+}
+
+#[test]
+fn props_do_not_count_towards_max_cars() {
+    let server = TestServer::start(
+        &[("key_alice", "alice")],
+        "MaxCars = 1\n[Props]\nMaxPerPlayer = 1\n",
+    );
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+
+    alice.spawn_car("{\"jbm\":\"pickup\"}");
+    alice.expect("Os:");
+    alice.spawn_car("{\"jbm\":\"dryvan\"}");
+    assert!(alice.expect("Os:").contains(&format!(":{}-1:", alice.id)));
+    alice.spawn_car("{\"jbm\":\"cones\"}");
+    assert_eq!(alice.expect("Od:"), format!("Od:{}-2", alice.id));
+    assert!(alice.expect("C:").ends_with("You can't spawn more than 1 trailer(s) or prop(s)."));
+}
+
+#[test]
+fn props_count_towards_max_cars_without_a_limit_of_their_own() {
+    let server = TestServer::start(&[("key_alice", "alice")], "MaxCars = 1
+");
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+
+    alice.spawn_car("{\"jbm\":\"pickup\"}");
+    alice.expect("Os:");
+    alice.spawn_car("{\"jbm\":\"cones\"}");
+    assert_eq!(alice.expect("Od:"), format!("Od:{}-1", alice.id));
+}
+
+#[test]
+fn reload_applies_config_changes() {
+    let server = TestServer::start(&[("key_alice", "alice"), ("key_bob", "bob")], "Admins = [\"alice\"]\nMaxCars = 1");
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+
+    let config_path = server.dir.join("ServerConfig.toml");
+    let config = std::fs::read_to_string(&config_path).unwrap();
+    let config = config.replace("MaxCars = 1", "MaxCars = 2") + "\n[Drivers.bob]\nDisplayName = \"Bobby\"\n";
+    std::fs::write(&config_path, config).unwrap();
+    alice.send_chat("!reload");
+    assert!(alice.expect("C:").ends_with("Config reloaded. Changes to ports, folders and auth settings need a restart."));
+
+    alice.spawn_car("{\"jbm\":\"pickup\"}");
+    alice.expect("Os:");
+    alice.spawn_car("{\"jbm\":\"covet\"}");
+    assert!(alice.expect("Os:").contains(&format!(":{}-1:", alice.id)));
+
+    // Players joining after the reload get the new settings too
+    let mut bob = FakeClient::join(&server, "key_bob", "bob");
+    bob.send_chat("hi");
+    assert_eq!(alice.expect("C:Bobby"), "C:Bobby:hi");
+}
+
+#[test]
+fn garage_is_restored_on_join() {
+    let server = TestServer::start(&[("key_alice", "alice")], "[Garage]\nEnabled = true\n");
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+    alice.spawn_car("{\"jbm\":\"pickup\"}");
+    alice.expect("Os:");
+    drop(alice);
+
+    let garages = server.dir.join("garages.json");
+    let start = Instant::now();
+    while !garages.exists() {
+        assert!(start.elapsed() < Duration::from_secs(5), "Garage wasn't saved in time");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    let mut alice = FakeClient::connect(&server, "key_alice", "alice");
+    assert_eq!(alice.expect("E:Garage:"), "E:Garage:{\"vehicles\":[{\"jbm\":\"pickup\"}]}");
+}
+
+#[test]
+fn players_beyond_max_players_are_turned_away() {
+    let server = TestServer::start(&[("key_alice", "alice"), ("key_bob", "bob")], "Admins = [\"alice\"]");
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+    alice.send_chat("!set max_players 1");
+    assert!(alice.expect("C:").ends_with("Server setting max_players changed to 1"));
+
+    let mut bob = FakeClient::connect(&server, "key_bob", "bob");
+    assert_eq!(bob.expect("K"), "KThis server is full!");
+}
+
+#[test]
+fn banned_players_are_turned_away() {
+    let server = TestServer::start(
+        &[("key_alice", "alice"), ("key_bob", "bob")],
+        "Admins = [\"alice\"]\n[Chat]\nCooldownMs = 0\n[Bans]\nFile = \"moderation/bans.json\"\n",
+    );
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+    let mut bob = FakeClient::join(&server, "key_bob", "bob");
+
+    alice.send_chat("!ban bob ramming");
+    assert_eq!(bob.expect("K"), "KYou have been banned from this server: ramming");
+    assert!(alice.expect("C:").ends_with("Banned bob"));
+    let bans = std::fs::read_to_string(server.dir.join("moderation/bans.json")).unwrap();
+    assert!(bans.contains("ramming"), "{}", bans);
+
+    let mut bob = FakeClient::connect_raw(&server);
+    bob.send("VC2.0");
+    bob.expect("S");
+    bob.send("key_bob");
+    assert_eq!(bob.expect("K"), "KYou are banned from this server: ramming");
+
+    alice.send_chat("!unban bob");
+    assert!(alice.expect("C:").ends_with("Unbanned bob"));
+    FakeClient::join(&server, "key_bob", "bob");
+}
+
+#[test]
+fn world_state_is_sent_to_players() {
+    let server = TestServer::start(&[("key_alice", "alice"), ("key_bob", "bob")], "Admins = [\"alice\"]");
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+
+    alice.send_chat("!world time 13:30");
+    assert_eq!(alice.expect("E:WorldState:"), "E:WorldState:{\"time\":13.5,\"weather\":null}");
+    assert!(alice.expect("C:").ends_with("World time changed to 13:30"));
+
+    let mut bob = FakeClient::connect(&server, "key_bob", "bob");
+    assert_eq!(bob.expect("E:WorldState:"), "E:WorldState:{\"time\":13.5,\"weather\":null}");
+    assert!(server.dir.join("world.json").exists());
+}
+
+/// Sends an HTTP request and returns the whole response.
+fn http_request(port: u16, request: &str) -> String {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn api_lists_players_and_sends_chat() {
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let server = TestServer::start(
+        &[("key_alice", "alice")],
+        &format!("[Api]\nEnabled = true\nPort = {}\nToken = \"secret\"\n", port),
+    );
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+
+    let response = http_request(port, "GET /players HTTP/1.1\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 401"));
+
+    let response = http_request(port, "GET /players HTTP/1.1\r\nAuthorization: Bearer secret\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200"));
+    let body = response.split("\r\n\r\n").nth(1).unwrap();
+    let players: serde_json::Value = serde_json::from_str(body).unwrap();
+    assert_eq!(players[0]["name"], "alice");
+
+    let response = http_request(port, "GET /state HTTP/1.1\r\nAuthorization: Bearer secret\r\n\r\n");
+    let body = response.split("\r\n\r\n").nth(1).unwrap();
+    let state: serde_json::Value = serde_json::from_str(body).unwrap();
+    assert_eq!(state["players"][0]["name"], "alice");
+    // Test servers never reach the backend
+    assert_eq!(state["heartbeat"]["listed"], false);
+
+    let body = "{\"message\":\"Race starts in 5 minutes\"}";
+    let response = http_request(port, &format!(
+        "POST /chat HTTP/1.1\r\nAuthorization: Bearer secret\r\nContent-Length: {}\r\n\r\n{}", body.len(), body,
+    ));
+    assert!(response.starts_with("HTTP/1.1 200"));
+    assert_eq!(alice.expect("C:"), "C:Server: Race starts in 5 minutes");
+
+    let body = "{\"player\":\"nobody\"}";
+    let response = http_request(port, &format!(
+        "POST /kick HTTP/1.1\r\nAuthorization: Bearer secret\r\nContent-Length: {}\r\n\r\n{}", body.len(), body,
+    ));
+    assert!(response.starts_with("HTTP/1.1 404"));
+}
+
+#[test]
+fn players_can_teleport_to_saved_positions_and_players() {
+    let server = TestServer::start(
+        &[("key_alice", "alice"), ("key_bob", "bob")],
+        "\n[Chat]\nCooldownMs = 0\n[Teleport]\nEnabled = true\n",
+    );
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+    let mut bob = FakeClient::join(&server, "key_bob", "bob");
+    alice.spawn_car("{\"jbm\":\"pickup\"}");
+    alice.expect("Os:");
+    bob.spawn_car("{\"jbm\":\"covet\"}");
+    while !bob.expect("Os:").contains(":bob:") {}
+    alice.register_udp();
+    bob.register_udp();
+    // Each position is handled once the other player gets it
+    alice.send_position(0, [10.0, 20.0, 0.0]);
+    bob.expect_udp("Zp:");
+    bob.send_position(0, [-50.0, 0.0, 0.0]);
+    alice.expect_udp("Zp:");
+
+    alice.send_chat("!save start");
+    assert!(alice.expect("C:").ends_with("Saved position 'start'"));
+    alice.send_chat("!tp bob");
+    let teleport = alice.expect("E:Teleport:");
+    let teleport: serde_json::Value = serde_json::from_str(&teleport["E:Teleport:".len()..]).unwrap();
+    assert_eq!(teleport["vid"], 0);
+    assert_eq!(teleport["respawn"]["pos"], serde_json::json!({"x": -50.0, "y": 5.0, "z": 0.0}));
+
+    alice.send_chat("!tp start");
+    let teleport = alice.expect("E:Teleport:");
+    let teleport: serde_json::Value = serde_json::from_str(&teleport["E:Teleport:".len()..]).unwrap();
+    assert_eq!(teleport["respawn"]["pos"], serde_json::json!({"x": 10.0, "y": 20.0, "z": 0.0}));
+}
+
+#[test]
+fn plugins_keep_player_values_and_add_commands() {
+    let server = TestServer::start_with_plugins(
+        &[("key_alice", "alice")],
+        "\n[Chat]\nCooldownMs = 0\n",
+        &[("economy", "function onEarn(pid, amount)\n  local total = MP.IncrementPlayerValue(pid, \"coins\", tonumber(amount))\n  MP.SendChatMessage(pid, \"You have \" .. total .. \" coins\")\nend\nMP.RegisterCommand(\"earn\", \"onEarn\")\n\
+            function onTitle(pid)\n  MP.SetPlayerValue(pid, \"title\", \"champion\")\n  local ok = pcall(MP.IncrementPlayerValue, pid, \"title\", 1)\n  MP.SendChatMessage(pid, \"Incremented: \" .. tostring(ok) .. \", title: \" .. MP.GetPlayerValue(pid, \"title\"))\nend\nMP.RegisterCommand(\"title\", \"onTitle\")\n\
+            function onChat(pid, name, message)\n  MP.TriggerGlobalEvent(\"command:earn\", pid, \"100\")\nend\nMP.RegisterEvent(\"onChatMessage\", \"onChat\")\n")],
+    );
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+
+    alice.send_chat("!earn 5");
+    assert!(alice.expect("C:").ends_with("You have 5 coins"));
+    // Events can't pretend to be a command
+    alice.send_chat("hello");
+    alice.send_chat("!earn 3");
+    assert!(alice.expect("C:Server").ends_with("You have 8 coins"));
+
+    alice.send_chat("!title");
+    assert!(alice.expect("C:Server").ends_with("Incremented: false, title: champion"));
+
+    // The values are saved when the player leaves
+    drop(alice);
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        if let Ok(store) = std::fs::read_to_string(server.dir.join("store.json")) {
+            let store: serde_json::Value = serde_json::from_str(&store).unwrap();
+            if store.as_object().unwrap().values().next().unwrap()["coins"] == 8 {
+                break;
+            }
+        }
+        assert!(Instant::now() < deadline, "The player store was never saved");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn hooks_run_commands_on_events() {
+    let server = TestServer::start(
+        &[("key_alice", "alice")],
+        "\n[[Hooks]]\nEvent = \"ServerStarted\"\nCommand = \"touch\"\nArgs = [\"started\"]\n\
+         [[Hooks]]\nEvent = \"PlayerJoined\"\nCommand = \"touch\"\nArgs = [\"joined_{name}_{pid}\"]\n",
+    );
+    let alice = FakeClient::join(&server, "key_alice", "alice");
+
+    let joined = server.dir.join(format!("joined_alice_{}", alice.id));
+    let start = Instant::now();
+    while !joined.exists() {
+        assert!(start.elapsed() < Duration::from_secs(5), "Hook didn't run in time");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(server.dir.join("started").exists());
+}
+
+#[test]
+fn players_get_their_region_from_the_geoip_database() {
+    let database = std::env::temp_dir().join(format!("beammp_rust_server_geoip_{}.csv", std::process::id()));
+    std::fs::write(&database, "10.0.0.0,10.255.255.255,XA\n127.0.0.0,127.255.255.255,XL\n").unwrap();
+    let server = TestServer::start(
+        &[("key_alice", "alice")],
+        &format!("\n[GeoIp]\nDatabase = {:?}\n", database.to_string_lossy()),
+    );
+    let mut alice = FakeClient::join(&server, "key_alice", "alice");
+
+    alice.send_chat("!players");
+    assert!(alice.expect("C:").contains("alice [XL]"));
+    let _ = std::fs::remove_file(&database);
+}